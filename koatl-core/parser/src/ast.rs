@@ -73,17 +73,22 @@ pub enum AssignModifier {
 pub enum Stmt<'a> {
     Module,
     Assign(SExpr<'a>, SExpr<'a>, Vec<AssignModifier>),
+    Annotate(SIdent<'a>, SExpr<'a>),
+    TypeAlias(SIdent<'a>, SExpr<'a>),
     Expr(SExpr<'a>),
 
     Return(SExpr<'a>),
-    While(SExpr<'a>, SExpr<'a>),
-    For(SPattern<'a>, SExpr<'a>, SExpr<'a>),
+    While(Option<SIdent<'a>>, SExpr<'a>, SExpr<'a>),
+    For(Option<SIdent<'a>>, SPattern<'a>, SExpr<'a>, SExpr<'a>),
     Import(ImportStmt<'a>),
     Try(SExpr<'a>, Vec<MatchCase<'a>>, Option<SExpr<'a>>),
     Assert(SExpr<'a>, Option<SExpr<'a>>),
+    Del(SExpr<'a>),
+    Defer(SExpr<'a>),
     Raise(Option<SExpr<'a>>),
-    Break,
-    Continue,
+    Break(Option<SIdent<'a>>),
+    Continue(Option<SIdent<'a>>),
+    Pass,
     Err,
 }
 
@@ -93,6 +98,7 @@ pub type SStmt<'a> = Spanned<Stmt<'a>>;
 pub enum Literal<'a> {
     Num(Cow<'a, str>),
     Str(Cow<'a, str>),
+    Bytes(Vec<u8>),
     Bool(bool),
     None,
 }
@@ -111,6 +117,8 @@ pub type SFmtExpr<'a> = Spanned<FmtExpr<'a>>;
 pub enum ListItem<'a> {
     Item(SExpr<'a>),
     Spread(SExpr<'a>),
+    /// `target = default`, only valid in a list being destructured.
+    Default(SExpr<'a>, SExpr<'a>),
 }
 
 #[derive(Debug, Clone)]
@@ -118,6 +126,8 @@ pub enum MappingItem<'a> {
     Ident(SIdent<'a>),
     Item(SExpr<'a>, SExpr<'a>),
     Spread(SExpr<'a>),
+    /// `key: target = default`, only valid in a mapping being destructured.
+    Default(SExpr<'a>, SExpr<'a>, SExpr<'a>),
 }
 
 #[derive(Debug, Clone)]
@@ -166,27 +176,35 @@ pub enum Expr<'a> {
     Await(Box<SExpr<'a>>),
     Yield(Box<SExpr<'a>>),
     YieldFrom(Box<SExpr<'a>>),
+    Raise(Box<SExpr<'a>>),
 
     If(Box<SExpr<'a>>, Box<SExpr<'a>>, Option<Box<SExpr<'a>>>),
     Match(Box<SExpr<'a>>, Vec<MatchCase<'a>>),
+    While(Box<SExpr<'a>>, Box<SExpr<'a>>),
     Matches(Box<SExpr<'a>>, Box<SPattern<'a>>),
-    Class(Vec<SCallItem<'a>>, Box<SExpr<'a>>),
+    Class(Vec<SCallItem<'a>>, Box<SExpr<'a>>, Vec<SIdent<'a>>),
 
     Call(Box<SExpr<'a>>, Vec<SCallItem<'a>>),
     Subscript(Box<SExpr<'a>>, Vec<ListItem<'a>>),
     Attribute(Box<SExpr<'a>>, SIdent<'a>),
+    DynAttribute(Box<SExpr<'a>>, Box<SExpr<'a>>),
     Then(Box<SExpr<'a>>, Box<SExpr<'a>>),
     Extension(Box<SExpr<'a>>, SIdent<'a>),
 
     MappedCall(Box<SExpr<'a>>, Vec<SCallItem<'a>>),
     MappedSubscript(Box<SExpr<'a>>, Vec<ListItem<'a>>),
     MappedAttribute(Box<SExpr<'a>>, SIdent<'a>),
+    MappedDynAttribute(Box<SExpr<'a>>, Box<SExpr<'a>>),
     MappedThen(Box<SExpr<'a>>, Box<SExpr<'a>>),
     MappedExtension(Box<SExpr<'a>>, SIdent<'a>),
 
-    Checked(Box<SExpr<'a>>, Option<Box<SPattern<'a>>>),
+    Checked(
+        Box<SExpr<'a>>,
+        Option<Box<SPattern<'a>>>,
+        Option<Box<SExpr<'a>>>,
+    ),
 
-    Fn(Vec<ArgDefItem<'a>>, Box<SExpr<'a>>),
+    Fn(Vec<ArgDefItem<'a>>, Box<SExpr<'a>>, Vec<SIdent<'a>>),
     Fstr(Spanned<String>, Vec<(SFmtExpr<'a>, Spanned<String>)>),
 
     Decorated(Vec<SExpr<'a>>, Box<SExpr<'a>>),
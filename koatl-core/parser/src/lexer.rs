@@ -9,12 +9,19 @@ use std::{collections::HashSet, fmt};
 
 use crate::ast::{Span, Spanned};
 
+/// Prefix of identifiers the lexer produces itself; source identifiers may not use it.
+pub const RESERVED_IDENT_PREFIX: &str = "_tl_";
+
+/// The identifier `$$` lexes to, since a literal `$` is not a valid Python name.
+pub const DOLLAR_IDENT: &str = "_tl_dollar";
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token<'src> {
     Ident(&'src str),
     None,
     Bool(bool),
     Str(String),
+    Bytes(Vec<u8>),
     FstrBegin(String),
     FstrContinue(String),
 
@@ -35,6 +42,7 @@ impl fmt::Display for Token<'_> {
             Token::Bool(x) => write!(f, "<literal {x}>"),
             Token::Num(n) => write!(f, "<literal {n}>"),
             Token::Str(s) => write!(f, "<literal {s}>"),
+            Token::Bytes(b) => write!(f, "<literal {}>", String::from_utf8_lossy(b)),
             Token::Symbol(s) => write!(f, "{s}"),
             Token::Ident(s) => write!(f, "{s}"),
             Token::Kw(s) => write!(f, "<{s}>"),
@@ -77,32 +85,40 @@ pub enum NewBlockType {
     Continuation,
 }
 
-pub type TOutput<'src> = TokenList<'src>;
+pub struct TOutput<'src> {
+    pub tokens: TokenList<'src>,
+    /// The tag (e.g. `[attr]`) of every `# type: ignore` comment.
+    pub type_ignores: Vec<Spanned<&'src str>>,
+    /// Input that Python accepts with a warning, like an unknown escape sequence.
+    pub warnings: Vec<Spanned<String>>,
+}
 pub type TError<'src> = Rich<'src, char, Span>;
 pub type TExtra<'src> = extra::Full<TError<'src>, (), ()>;
 
 type TResult<'src, T> = Result<T, TError<'src>>;
 
+fn escape_char(c: char) -> String {
+    match c {
+        '\\' => "\\\\".to_string(),
+        '"' => "\\\"".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        c if c.is_control() => format!("\\u{:04x}", c as u32),
+        _ => c.to_string(),
+    }
+}
+
 pub fn escape_str(s: &str) -> String {
-    // TODO
-    s.chars()
-        .map(|c| match c {
-            '\n' => "\\n".to_string(),
-            '"' => "\\\"".to_string(),
-            _ => c.to_string(),
-        })
-        .collect()
+    s.chars().map(escape_char).collect()
 }
 
 pub fn escape_fstr(s: &str) -> String {
-    // TODO
     s.chars()
         .map(|c| match c {
             '{' => "{{".to_string(),
             '}' => "}}".to_string(),
-            '\n' => "\\n".to_string(),
-            '"' => "\\\"".to_string(),
-            _ => c.to_string(),
+            _ => escape_char(c),
         })
         .collect()
 }
@@ -116,7 +132,7 @@ pub fn is_valid_ident(s: &str) -> bool {
     if !s
         .chars()
         .next()
-        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
     {
         return false;
     }
@@ -130,6 +146,10 @@ where
 {
     input: &'input mut InputRef<'src, 'parse, TInput, TExtra<'src>>,
     keywords: HashSet<String>,
+    /// The whitespace character the first indented line used; every other line must match it.
+    indent_char: Option<char>,
+    type_ignores: Vec<Spanned<&'src str>>,
+    warnings: Vec<Spanned<String>>,
 }
 
 impl<'src: 'parse, 'parse, 'input, TInput> TokenizeCtx<'src, 'parse, 'input, TInput>
@@ -140,12 +160,18 @@ where
         static KEYWORDS: &[&str] = &[
             "match", "if", "then", "else", "import", "export", "as", "class", "while", "for", "in",
             "break", "continue", "with", "yield", "global", "nonlocal", "return", "raise", "try",
-            "except", "finally", "and", "or", "not", "await",
+            "except", "finally", "and", "or", "not", "await", "pass",
         ];
 
         let keywords = HashSet::<String>::from_iter(KEYWORDS.iter().map(|s| s.to_string()));
 
-        TokenizeCtx { input, keywords }
+        TokenizeCtx {
+            input,
+            keywords,
+            indent_char: None,
+            type_ignores: Vec::new(),
+            warnings: Vec::new(),
+        }
     }
 
     fn cursor(&self) -> Cursor<'src, 'parse, TInput> {
@@ -221,8 +247,21 @@ where
                 break;
             }
 
-            indent_level += 1;
+            let char_start = self.cursor();
             self.next();
+
+            match self.indent_char {
+                None => self.indent_char = Some(c),
+                Some(indent_char) if indent_char != c => {
+                    return Err(Rich::custom(
+                        self.span_since(&char_start),
+                        "inconsistent use of tabs and spaces in indentation",
+                    ));
+                }
+                _ => {}
+            }
+
+            indent_level += 1;
         }
 
         self.parse_nonsemantic()?;
@@ -232,6 +271,16 @@ where
     fn parse_ident_or_token(&mut self) -> TResult<'src, Spanned<Token<'src>>> {
         let start = self.cursor();
 
+        // `$$` escapes the placeholder: an ordinary identifier, spelled with the reserved
+        // prefix so that it is the same Python name in every position and can't collide
+        if self.peek() == Some('$') {
+            self.next();
+            if self.next() == Some('$') {
+                return Ok((Token::Ident(DOLLAR_IDENT), self.span_since(&start)));
+            }
+            return Err(Rich::custom(self.span_since(&start), "expected identifier"));
+        }
+
         let c = self.peek();
         if c.is_none_or(|c| !c.is_ascii_alphabetic() && c != '_') {
             return Err(Rich::custom(self.span_since(&start), "expected identifier"));
@@ -314,7 +363,10 @@ where
         let mut after_dot = false;
 
         while let Some(c) = self.peek() {
-            if self.look_ahead(|x| x.parse_seq("..")).is_ok() {
+            // `1..2` is a range and `1.(f)` is a then-call, not float literals
+            if self.look_ahead(|x| x.parse_seq("..")).is_ok()
+                || self.look_ahead(|x| x.parse_seq(".(")).is_ok()
+            {
                 break;
             }
 
@@ -344,6 +396,15 @@ where
             ));
         }
 
+        // imaginary suffix, only directly after a digit so `t.0.j` stays an attribute
+        if self
+            .slice_since(&start)
+            .ends_with(|c: char| c.is_ascii_digit())
+            && matches!(self.peek(), Some('j' | 'J'))
+        {
+            self.next();
+        }
+
         Ok((
             (
                 Token::Num(self.slice_since(&start)),
@@ -384,7 +445,7 @@ where
 
         self.parse_block_comment_begin()?;
 
-        while let Some(_) = self.peek() {
+        while self.peek().is_some() {
             if self.try_parse(|x| x.parse_block_comment_end()).is_ok() {
                 return Ok(());
             }
@@ -399,10 +460,10 @@ where
             self.next();
         }
 
-        return Err(Rich::custom(
+        Err(Rich::custom(
             self.span_since(&start),
             "unterminated block comment",
-        ));
+        ))
     }
 
     fn parse_newline(&mut self) -> TResult<'src, ()> {
@@ -425,7 +486,7 @@ where
         }
 
         if err {
-            return Err(Rich::custom(self.span_since(&start), "expected newline"));
+            Err(Rich::custom(self.span_since(&start), "expected newline"))
         } else {
             Ok(())
         }
@@ -440,10 +501,10 @@ where
             return Ok(());
         }
 
-        return Err(Rich::custom(
+        Err(Rich::custom(
             self.span_since(&self.cursor()),
             "expected newline or end of file",
-        ));
+        ))
     }
 
     fn parse_nonsemantic(&mut self) -> TResult<'src, ()> {
@@ -456,9 +517,21 @@ where
             {
                 self.parse_block_comment()?;
             } else if c == '#' {
+                let start = self.cursor();
                 while self.look_ahead(TokenizeCtx::parse_newline_or_eof).is_err() {
                     self.next();
                 }
+
+                // backtracking can lex a comment again, but comments are always met in order
+                let span = self.span_since(&start);
+                if let Some(tag) = type_ignore_tag(self.slice_since(&start))
+                    && self
+                        .type_ignores
+                        .last()
+                        .is_none_or(|(_, last)| last.start < span.start)
+                {
+                    self.type_ignores.push((tag, span));
+                }
             } else {
                 break;
             }
@@ -475,17 +548,15 @@ where
             ));
         }
 
-        loop {
-            self.parse_nonsemantic()?;
+        self.parse_nonsemantic()?;
 
-            if self.try_parse(TokenizeCtx::parse_newline_or_eof).is_ok() {
-                return Ok(());
-            } else {
-                return Err(Rich::custom(
-                    self.span_since(&self.cursor()),
-                    "expected empty line",
-                ));
-            }
+        if self.try_parse(TokenizeCtx::parse_newline_or_eof).is_ok() {
+            Ok(())
+        } else {
+            Err(Rich::custom(
+                self.span_since(&self.cursor()),
+                "expected empty line",
+            ))
         }
     }
 
@@ -504,62 +575,262 @@ where
         Ok(())
     }
 
-    fn parse_escaped_char(&mut self) -> TResult<'src, char> {
+    /// Record a warning, unless backtracking already recorded it.
+    fn warn(&mut self, message: String, span: Span) {
+        if self
+            .warnings
+            .last()
+            .is_none_or(|(_, last)| last.start < span.start)
+        {
+            self.warnings.push((message, span));
+        }
+    }
+
+    /// Push the next character of a non-raw string onto `s`, decoding escape sequences. As in
+    /// Python, a backslash before a newline continues the string on the next line, and an
+    /// unknown escape is kept as written, with a warning.
+    fn parse_escaped_char(&mut self, s: &mut String) -> TResult<'src, ()> {
         let start = self.cursor();
 
-        match self.next() {
+        let c = match self.next() {
             Some('\\') => {
-                if let Some(next) = self.next() {
-                    match next {
-                        'n' => return Ok('\n'),
-                        't' => return Ok('\t'),
-                        'r' => return Ok('\r'),
-                        c => {
-                            return Ok(c);
+                if self.try_parse(|x| x.parse_newline()).is_ok() {
+                    return Ok(());
+                }
+
+                let Some(next) = self.next() else {
+                    return Err(Rich::custom(self.span_since(&start), "unterminated escape"));
+                };
+
+                match next {
+                    '\\' | '\'' | '"' => next,
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'a' => '\x07',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'v' => '\x0b',
+                    '0'..='7' => {
+                        let mut value = next.to_digit(8).unwrap();
+                        for _ in 0..2 {
+                            match self.peek().and_then(|c| c.to_digit(8)) {
+                                Some(d) => {
+                                    value = value * 8 + d;
+                                    self.next();
+                                }
+                                None => break,
+                            }
                         }
+                        char::from_u32(value).unwrap()
+                    }
+                    'x' => self.parse_hex_escape(&start, 2)?,
+                    'u' => self.parse_hex_escape(&start, 4)?,
+                    'U' => self.parse_hex_escape(&start, 8)?,
+                    'N' => self.parse_named_escape(&start)?,
+                    c => {
+                        let span = self.span_since(&start);
+                        self.warn(format!("invalid escape sequence '\\{c}'"), span);
+                        s.push('\\');
+                        c
                     }
                 }
+            }
+            Some(c) => c,
+            None => return Err(Rich::custom(self.span_since(&start), "unterminated string")),
+        };
+
+        s.push(c);
+        Ok(())
+    }
+
+    fn parse_named_escape(&mut self, start: &Cursor<'src, 'parse, TInput>) -> TResult<'src, char> {
+        let mut name = String::new();
+        if self.next() == Some('{') {
+            while let Some(c) = self.next() {
+                if c == '}' {
+                    return unicode_names2::character(&name).ok_or_else(|| {
+                        Rich::custom(
+                            self.span_since(start),
+                            format!("unknown unicode character name '{name}'"),
+                        )
+                    });
+                }
+                if c == '"' || c == '\n' {
+                    break;
+                }
+                name.push(c);
+            }
+        }
+
+        Err(Rich::custom(
+            self.span_since(start),
+            "malformed \\N{...} escape",
+        ))
+    }
+
+    fn parse_hex_escape(
+        &mut self,
+        start: &Cursor<'src, 'parse, TInput>,
+        digits: usize,
+    ) -> TResult<'src, char> {
+        let mut value = 0;
+        for _ in 0..digits {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(d) => {
+                    value = value * 16 + d;
+                    self.next();
+                }
+                None => {
+                    return Err(Rich::custom(
+                        self.span_since(start),
+                        format!("truncated escape, expected {digits} hex digits"),
+                    ));
+                }
+            }
+        }
+
+        char::from_u32(value).ok_or_else(|| {
+            Rich::custom(
+                self.span_since(start),
+                format!("invalid unicode code point {value:#x}"),
+            )
+        })
+    }
+
+    /// Consumes the `r`/`b` prefix of a non-format string, returning whether it is raw and
+    /// whether it is bytes. Any case and order is accepted.
+    fn parse_str_prefix(&mut self) -> TResult<'src, (bool, bool)> {
+        let start = self.cursor();
+
+        while self.peek().is_some_and(|c| "rRbBfF".contains(c)) {
+            self.next();
+        }
+
+        let prefix = self.slice_since(&start).to_ascii_lowercase();
+        match prefix.as_str() {
+            "" => Ok((false, false)),
+            "r" => Ok((true, false)),
+            "b" => Ok((false, true)),
+            "rb" | "br" => Ok((true, true)),
+            _ if prefix.contains('f') && prefix.contains('b') => Err(Rich::custom(
+                self.span_since(&start),
+                "bytes literals cannot be f-strings",
+            )),
+            _ => Err(Rich::custom(
+                self.span_since(&start),
+                format!("invalid string prefix '{}'", self.slice_since(&start)),
+            )),
+        }
+    }
 
-                Err(Rich::custom(self.span_since(&start), "unterminated escape"))
+    fn str_token(s: String, bytes: bool) -> Token<'src> {
+        if bytes {
+            Token::Bytes(s.chars().map(|c| c as u8).collect())
+        } else {
+            Token::Str(s)
+        }
+    }
+
+    /// Bytes may only spell ASCII characters literally.
+    fn check_bytes_char(&mut self) -> TResult<'src, ()> {
+        let start = self.cursor();
+
+        match self.peek() {
+            Some(c) if !c.is_ascii() => {
+                self.next();
+                Err(Rich::custom(
+                    self.span_since(&start),
+                    "bytes literals can only contain ASCII characters",
+                ))
             }
-            Some(c) => Ok(c),
-            None => Err(Rich::custom(self.span_since(&start), "unterminated string")),
+            _ => Ok(()),
         }
     }
 
+    /// Bytes have no unicode escapes; like Python, `\u`, `\U` and `\N` are kept as they are.
+    fn parse_bytes_unicode_escape(&mut self, s: &mut String) -> TResult<'src, ()> {
+        let escape = self.look_ahead(|x| {
+            x.next();
+            Ok(x.next())
+        })?;
+
+        if self.peek() == Some('\\') && matches!(escape, Some('u' | 'U' | 'N')) {
+            s.push(self.next().unwrap());
+            s.push(self.next().unwrap());
+            return Ok(());
+        }
+
+        Err(Rich::custom(
+            self.span_since(&self.cursor()),
+            "expected a unicode escape",
+        ))
+    }
+
     fn parse_regular_str(&mut self) -> TResult<'src, Spanned<Token<'src>>> {
         let start = self.cursor();
 
+        let (raw, bytes) = self.parse_str_prefix()?;
         self.parse_seq("\"")?;
+        let open = self.span_since(&start);
 
         let mut s = String::new();
         loop {
             if self.try_parse(|x| x.parse_seq("\"")).is_ok() {
-                return Ok((Token::Str(s), self.span_since(&start)));
+                return Ok((Self::str_token(s, bytes), self.span_since(&start)));
             }
 
-            if self.try_parse(|x| x.parse_newline()).is_ok() {
-                return Err(Rich::custom(self.span_since(&start), "unterminated string"));
+            if self.peek().is_none() || self.try_parse(|x| x.parse_newline()).is_ok() {
+                return Err(Rich::custom(open, "unterminated string"));
             }
 
-            s.push(self.parse_escaped_char()?);
+            if bytes {
+                self.check_bytes_char()?;
+            }
+
+            if raw {
+                // backslashes are kept, but still stop a following quote or newline from
+                // ending the string
+                let c = self.next().unwrap();
+                s.push(c);
+                if c == '\\' && matches!(self.peek(), Some('"' | '\\' | '\r' | '\n')) {
+                    s.push(self.next().unwrap());
+                    if s.ends_with('\r') && self.peek() == Some('\n') {
+                        s.push(self.next().unwrap());
+                    }
+                }
+            } else if !(bytes
+                && self
+                    .try_parse(|x| x.parse_bytes_unicode_escape(&mut s))
+                    .is_ok())
+            {
+                self.parse_escaped_char(&mut s)?;
+            }
         }
     }
 
     fn parse_verbatim_str(&mut self) -> TResult<'src, Spanned<Token<'src>>> {
         let start = self.cursor();
 
+        // verbatim strings are already raw
+        let (_, bytes) = self.parse_str_prefix()?;
         self.parse_seq("\"\"\"")?;
+        let open = self.span_since(&start);
 
         let mut s = String::new();
         loop {
             if self.try_parse(|x| x.parse_seq("\"\"\"")).is_ok() {
-                return Ok((Token::Str(s), self.span_since(&start)));
+                return Ok((Self::str_token(s, bytes), self.span_since(&start)));
             }
 
-            s.push(self.next().ok_or_else(|| {
-                Rich::custom(self.span_since(&start), "unterminated verbatim string")
-            })?);
+            if bytes && self.peek().is_some_and(|c| !c.is_ascii()) {
+                self.check_bytes_char()?;
+            }
+
+            s.push(
+                self.next()
+                    .ok_or_else(|| Rich::custom(open, "unterminated verbatim string"))?,
+            );
         }
     }
 
@@ -572,6 +843,9 @@ where
             self.parse_seq("f\"")?;
         }
 
+        // errors about the string as a whole point at its opening delimiter
+        let open = self.span_since(&marker);
+
         let mut tokens = vec![];
         let mut current_str = String::new();
 
@@ -580,7 +854,7 @@ where
                 .try_parse(|x| x.parse_seq(if verbatim { "\"\"\"" } else { "\"" }))
                 .is_ok()
             {
-                if tokens.len() == 0 {
+                if tokens.is_empty() {
                     tokens.push((Token::FstrBegin(current_str), self.span_since(&marker)));
                 } else {
                     tokens.push((Token::FstrContinue(current_str), self.span_since(&marker)));
@@ -589,10 +863,14 @@ where
                 return Ok(TokenList(tokens));
             }
 
-            if !verbatim && self.try_parse(|x| x.parse_newline()).is_ok() {
+            if self.peek().is_none() || !verbatim && self.try_parse(|x| x.parse_newline()).is_ok() {
                 return Err(Rich::custom(
-                    self.span_since(&marker),
-                    "unterminated fstring",
+                    open,
+                    if verbatim {
+                        "unterminated verbatim fstring"
+                    } else {
+                        "unterminated fstring"
+                    },
                 ));
             }
 
@@ -609,7 +887,7 @@ where
             }
 
             if self.try_parse(|x| x.parse_seq("{")).is_ok() {
-                if tokens.len() == 0 {
+                if tokens.is_empty() {
                     tokens.push((Token::FstrBegin(current_str), self.span_since(&marker)));
                 } else {
                     tokens.push((Token::FstrContinue(current_str), self.span_since(&marker)));
@@ -628,16 +906,13 @@ where
 
                 tokens.push((
                     Token::Symbol("BEGIN_BLOCK"),
-                    Span::new(expr_span.context, expr_span.start..expr_span.start),
+                    Span::new((), expr_span.start..expr_span.start),
                 ));
                 tokens.extend(expr.0);
-                tokens.push((
-                    Token::Eol,
-                    Span::new(expr_span.context, expr_span.end..expr_span.end),
-                ));
+                tokens.push((Token::Eol, Span::new((), expr_span.end..expr_span.end)));
                 tokens.push((
                     Token::Symbol("END_BLOCK"),
-                    Span::new(expr_span.context, expr_span.end..expr_span.end),
+                    Span::new((), expr_span.end..expr_span.end),
                 ));
 
                 marker = self.cursor();
@@ -646,11 +921,12 @@ where
             }
 
             if verbatim {
-                current_str.push(self.next().ok_or_else(|| {
-                    Rich::custom(self.span_since(&marker), "unterminated verbatim fstring")
-                })?);
+                current_str.push(
+                    self.next()
+                        .ok_or_else(|| Rich::custom(open, "unterminated verbatim fstring"))?,
+                );
             } else {
-                current_str.push(self.parse_escaped_char()?);
+                self.parse_escaped_char(&mut current_str)?;
             }
         }
     }
@@ -662,6 +938,10 @@ where
             return Ok(());
         }
 
+        while self.peek().is_some_and(|c| "rRbBfF".contains(c)) {
+            self.next();
+        }
+
         if self.try_parse(|x| x.parse_seq("\"")).is_ok() {
             return Ok(());
         }
@@ -673,16 +953,6 @@ where
     }
 
     fn parse_str(&mut self) -> TResult<'src, TokenList<'src>> {
-        if self.look_ahead(|x| x.parse_seq("\"\"\"")).is_ok() {
-            let token = self.parse_verbatim_str()?;
-            return Ok(TokenList(vec![token]));
-        }
-
-        if self.look_ahead(|x| x.parse_seq("\"")).is_ok() {
-            let token = self.parse_regular_str()?;
-            return Ok(TokenList(vec![token]));
-        }
-
         if self.look_ahead(|x| x.parse_seq("f\"\"\"")).is_ok() {
             let tokens = self.parse_fstr(true)?;
             return Ok(tokens);
@@ -693,10 +963,21 @@ where
             return Ok(tokens);
         }
 
-        Err(Rich::custom(
-            self.span_since(&self.cursor()),
-            "expected string start",
-        ))
+        let verbatim = self
+            .look_ahead(|x| {
+                while x.peek().is_some_and(|c| "rRbBfF".contains(c)) {
+                    x.next();
+                }
+                x.parse_seq("\"\"\"")
+            })
+            .is_ok();
+
+        let token = if verbatim {
+            self.parse_verbatim_str()?
+        } else {
+            self.parse_regular_str()?
+        };
+        Ok(TokenList(vec![token]))
     }
 
     fn parse_block(
@@ -709,13 +990,14 @@ where
         // TODO should parse_empty_line be part of parse_indentation?
         while self.try_parse(TokenizeCtx::parse_empty_line).is_ok() {}
 
-        let (indent_level, indent_span) =
-            self.try_parse(|x| x.parse_indentation()).map_err(|_| {
-                Rich::custom(
-                    self.span_since(&self.cursor()),
-                    "expected indentation at the beginning of parse_block",
-                )
-            })?;
+        if self.peek().is_none() {
+            return Err(Rich::custom(
+                self.span_since(&self.cursor()),
+                "expected indentation at the beginning of parse_block",
+            ));
+        }
+
+        let (indent_level, indent_span) = self.try_parse(|x| x.parse_indentation())?;
 
         match block_type {
             NewBlockType::BeginInput => {}
@@ -761,6 +1043,18 @@ where
 
                         tok = token;
                     } else if let Ok(token) = self.try_parse(TokenizeCtx::parse_ident_or_token) {
+                        if let Token::Ident(ident) = token.0
+                            && ident.starts_with(RESERVED_IDENT_PREFIX)
+                            && self.slice_since(&start_curs) == ident
+                        {
+                            return Err(Rich::custom(
+                                token.1,
+                                format!(
+                                    "identifiers starting with '{RESERVED_IDENT_PREFIX}' are reserved"
+                                ),
+                            ));
+                        }
+
                         tok = token;
                     } else if let Ok(token) = self.try_parse(TokenizeCtx::parse_symbol) {
                         tok = token;
@@ -810,15 +1104,13 @@ where
 
                 expect_new_block = false;
 
-                if let Some(last_token) = tokens.last() {
-                    if let Token::Symbol(s) = last_token.0 {
-                        if s == "=>"
-                            || s == ":"
-                            || OPEN_DELIMS.contains(&s.chars().next().unwrap_or('\0'))
-                        {
-                            expect_new_block = true;
-                        }
-                    }
+                if let Some(last_token) = tokens.last()
+                    && let Token::Symbol(s) = last_token.0
+                    && (s == "=>"
+                        || s == ":"
+                        || OPEN_DELIMS.contains(&s.chars().next().unwrap_or('\0')))
+                {
+                    expect_new_block = true;
                 }
 
                 self.parse_nonsemantic()?;
@@ -846,17 +1138,11 @@ where
                 if let Ok((new_block, new_block_span)) = new_block {
                     tokens.push((
                         Token::Symbol("BEGIN_BLOCK"),
-                        Span::new(
-                            new_block_span.context,
-                            new_block_span.start..new_block_span.start,
-                        ),
+                        Span::new((), new_block_span.start..new_block_span.start),
                     ));
                     tokens.extend(new_block.0);
 
-                    let end_span = Span::new(
-                        new_block_span.context,
-                        new_block_span.end..new_block_span.end,
-                    );
+                    let end_span = Span::new((), new_block_span.end..new_block_span.end);
 
                     // don't push another eol if the last token is already eol (edge case fix)
                     if tokens.last().is_none_or(|t| t.0 != Token::Eol) {
@@ -878,9 +1164,10 @@ where
 
             while self.try_parse(TokenizeCtx::parse_empty_line).is_ok() {}
 
-            if let Ok((cur_indent_level, cur_indent_span)) =
-                self.look_ahead(|x| x.parse_indentation())
-            {
+            if self.peek().is_some() {
+                let (cur_indent_level, cur_indent_span) =
+                    self.look_ahead(|x| x.parse_indentation())?;
+
                 if cur_indent_level > indent_level {
                     // handle continuation
                     let (new_block, new_block_span) = self
@@ -903,7 +1190,7 @@ where
                     }
                 }
             } else {
-                // couldn't parse indentation - end of file?
+                // end of file
                 break;
             }
         }
@@ -918,12 +1205,9 @@ where
          *   ^ this is a continuation that immediately ends due to ]
          */
         let block_span = if let Some(last_block_token) = tokens.last() {
-            Span::new(
-                indent_span.context,
-                indent_span.start..last_block_token.1.end,
-            )
+            Span::new((), indent_span.start..last_block_token.1.end)
         } else {
-            Span::new(indent_span.context, indent_span.start..indent_span.end)
+            Span::new((), indent_span.start..indent_span.end)
         };
 
         Ok((TokenList(tokens), block_span))
@@ -932,8 +1216,11 @@ where
     fn tokenize_input(&mut self) -> TResult<'src, TokenList<'src>> {
         while self.try_parse(TokenizeCtx::parse_empty_line).is_ok() {}
 
-        if let None = self.peek() {
-            return Ok(TokenList(vec![]));
+        if self.peek().is_none() {
+            return Ok(TokenList(vec![(
+                Token::Eol,
+                self.span_since(&self.cursor()),
+            )]));
         }
 
         let (mut tokens, span) = self.parse_block(0, NewBlockType::BeginInput)?;
@@ -942,20 +1229,138 @@ where
     }
 }
 
-fn lexer<'src, TInput>() -> impl Parser<'src, TInput, TOutput<'src>, TExtra<'src>>
+/// Returns the tag of a `# type: ignore` comment, following the rules of Python's tokenizer.
+fn type_ignore_tag(comment: &str) -> Option<&str> {
+    let rest = comment.strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("type:")?.trim_start();
+    let tag = rest.strip_prefix("ignore")?;
+
+    if tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some(tag.trim_end())
+}
+
+fn lexer<'src, TInput>(
+    indent_char: Option<char>,
+) -> impl Parser<'src, TInput, TOutput<'src>, TExtra<'src>>
 where
     TInput: StrInput<'src, Token = char, Span = SimpleSpan, Slice = &'src str>,
 {
-    custom(|input| {
+    custom(move |input| {
         let mut ctx = TokenizeCtx::new(input);
-        ctx.tokenize_input()
+        ctx.indent_char = indent_char;
+        let tokens = ctx.tokenize_input()?;
+        Ok(TOutput {
+            tokens,
+            type_ignores: ctx.type_ignores,
+            warnings: ctx.warnings,
+        })
     })
 }
 
 pub fn tokenize<'src>(s: &'src str) -> (Option<TokenList<'src>>, Vec<TError<'src>>) {
-    let output = lexer()
-        .parse(s.map_span(|s| Span::new(s.context, s.start()..s.end())))
+    let (output, errs) = tokenize_full(s);
+    (output.map(|output| output.tokens), errs)
+}
+
+pub fn tokenize_full<'src>(s: &'src str) -> (Option<TOutput<'src>>, Vec<TError<'src>>) {
+    lexer(None)
+        .parse(s.map_span(|s| Span::new((), s.start()..s.end())))
+        .into_output_errors()
+}
+
+/// Tokenize `src` after an edit, reusing `old_tokens` up to the last top-level statement that
+/// starts before `edit_start`, the offset of the first byte that differs from the old source.
+///
+/// The result is the same as `tokenize(src)`; on errors, `src` is simply tokenized again.
+pub fn relex<'src>(
+    old_tokens: &TokenList<'_>,
+    src: &'src str,
+    edit_start: usize,
+) -> (Option<TokenList<'src>>, Vec<TError<'src>>) {
+    // a statement at depth 0 is lexed the same no matter what comes before it
+    let mut depth = 0i32;
+    let mut restart = 0;
+    for (i, (token, span)) in old_tokens.0.iter().enumerate() {
+        if span.start >= edit_start {
+            break;
+        }
+
+        if i > 0 && depth == 0 && old_tokens.0[i - 1].0 == Token::Eol && *token != Token::Eol {
+            restart = i;
+        }
+
+        match token {
+            Token::Symbol("BEGIN_BLOCK" | "(" | "[" | "{") => depth += 1,
+            Token::Symbol("END_BLOCK" | ")" | "]" | "}") => depth -= 1,
+            _ => {}
+        }
+    }
+
+    if restart == 0 {
+        return tokenize(src);
+    }
+
+    let offset = old_tokens.0[restart].1.start;
+    let prefix = &old_tokens.0[..restart];
+
+    // every token before the edit borrows the same text from the new source,
+    // except for the zero-width block markers
+    let mut tokens: Vec<Spanned<Token<'src>>> = prefix
+        .iter()
+        .map(|(token, span)| {
+            let text = &src[span.into_range()];
+            let token = match token {
+                Token::Ident(_) if text == "$$" => Token::Ident(DOLLAR_IDENT),
+                Token::Ident(_) => Token::Ident(text),
+                Token::Num(_) => Token::Num(text),
+                Token::Kw(_) => Token::Kw(text),
+                Token::Symbol("BEGIN_BLOCK") => Token::Symbol("BEGIN_BLOCK"),
+                Token::Symbol("END_BLOCK") => Token::Symbol("END_BLOCK"),
+                Token::Symbol(_) => Token::Symbol(text),
+                Token::None => Token::None,
+                Token::Bool(b) => Token::Bool(*b),
+                Token::Str(s) => Token::Str(s.clone()),
+                Token::Bytes(b) => Token::Bytes(b.clone()),
+                Token::FstrBegin(s) => Token::FstrBegin(s.clone()),
+                Token::FstrContinue(s) => Token::FstrContinue(s.clone()),
+                Token::Eol => Token::Eol,
+            };
+            (token, *span)
+        })
+        .collect();
+
+    let (output, errs) = lexer(indent_char(&src[..offset], prefix))
+        .parse(src[offset..].map_span(|s| Span::new((), s.start()..s.end())))
         .into_output_errors();
 
-    output
+    let Some(suffix) = output.filter(|_| errs.is_empty()) else {
+        return tokenize(src);
+    };
+
+    tokens.extend(
+        suffix
+            .tokens
+            .0
+            .into_iter()
+            .map(|(token, span)| (token, Span::new((), span.start + offset..span.end + offset))),
+    );
+
+    (Some(TokenList(tokens)), vec![])
+}
+
+/// The indentation character of the first indented line that starts with one of `tokens`.
+fn indent_char(src: &str, tokens: &[Spanned<Token>]) -> Option<char> {
+    tokens.iter().find_map(|(_, span)| {
+        let line_start = src[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &src[line_start..span.start];
+
+        indent
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+            .then(|| indent.chars().next())
+            .flatten()
+    })
 }
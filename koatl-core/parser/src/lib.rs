@@ -3,5 +3,5 @@ pub mod lexer;
 mod parser;
 pub mod util;
 
-pub use lexer::{Token, TokenList, tokenize};
+pub use lexer::{TOutput, Token, TokenList, relex, tokenize, tokenize_full};
 pub use parser::*;
@@ -12,7 +12,7 @@ where
     I: ValueInput<'tokens, Token = Token<'src>, Span = Span>,
     E: ParserExtra<'tokens, I>,
 {
-    fn spanned(self) -> impl Parser<'tokens, I, Spanned<O>, E> + Clone + Sized
+    fn spanned(self) -> impl Parser<'tokens, I, Spanned<O>, E> + Clone
     where
         Self: Sized + Clone,
     {
@@ -110,7 +110,18 @@ where
 {
     let mut pattern = Recursive::declare();
 
-    let literal_pattern = literal.clone().map(Pattern::Literal).spanned().boxed();
+    let negative_num = symbol("-")
+        .ignore_then(select! {
+            Token::Num(s) => Literal::Num(Cow::Owned(format!("-{s}"))),
+        })
+        .spanned();
+
+    let literal_pattern = negative_num
+        .clone()
+        .or(literal.clone())
+        .map(Pattern::Literal)
+        .spanned()
+        .boxed();
 
     fn to_wildcard<'src>(id: SIdent<'src>) -> Option<SIdent<'src>> {
         if id.0 == "_" { None } else { Some(id) }
@@ -186,11 +197,22 @@ where
             .ignore_then(ident.clone())
             .map(|x| PatternMappingItem::Spread(to_wildcard(x))),
         choice((
+            qualified_ident.clone().try_map(|key, s| {
+                if let Expr::Attribute(..) = key.0 {
+                    Ok(key)
+                } else {
+                    Err(Rich::custom(s, "Expected a dotted name"))
+                }
+            }),
             ident
                 .clone()
                 .map(|(s, span)| Expr::Literal((Literal::Str(s), span)))
                 .spanned(),
-            literal.clone().map(Expr::Literal).spanned(),
+            negative_num
+                .clone()
+                .or(literal.clone())
+                .map(Expr::Literal)
+                .spanned(),
             sexpr
                 .clone()
                 .delimited_by_with_eol(symbol("("), symbol(")")),
@@ -313,7 +335,7 @@ where
         let uni_fn = pattern
             .clone()
             .then(fn_body.clone())
-            .map(|(x, body)| Expr::Fn(vec![ArgDefItem::Arg(x, None)], Box::new(body)))
+            .map(|(x, body)| Expr::Fn(vec![ArgDefItem::Arg(x, None)], Box::new(body), vec![]))
             .spanned()
             .labelled("uni-fn")
             .boxed();
@@ -322,7 +344,7 @@ where
             choice((
                 symbol("*")
                     .ignore_then(ident.clone())
-                    .map(|x| ArgDefItem::ArgSpread(x)),
+                    .map(ArgDefItem::ArgSpread),
                 symbol("**")
                     .ignore_then(ident.clone())
                     .map(ArgDefItem::KwargSpread),
@@ -336,11 +358,18 @@ where
         .labelled("argument-def-list")
         .boxed();
 
+        let type_params = enumeration(ident.clone(), symbol(","))
+            .delimited_by_with_eol(symbol("["), symbol("]"))
+            .or_not();
+
         let multi_fn = arg_list
             .clone()
             .delimited_by_with_eol(symbol("("), symbol(")"))
+            .then(type_params)
             .then(fn_body)
-            .map(|(args, body)| Expr::Fn(args, Box::new(body)))
+            .map(|((args, type_params), body)| {
+                Expr::Fn(args, Box::new(body), type_params.unwrap_or_else(Vec::new))
+            })
             .spanned()
             .labelled("multi-fn")
             .as_context()
@@ -391,12 +420,28 @@ where
     ))
     .boxed();
 
-    let literal = select! {
-        Token::Num(s) => Literal::Num(Cow::Borrowed(s)),
-        Token::Str(s) => Literal::Str(Cow::Owned(s)),
-        Token::Bool(s) => Literal::Bool(s),
-        Token::None => Literal::None
-    }
+    // adjacent string literals are concatenated, as in Python
+    let str_literal = select! { Token::Str(s) => s }
+        .repeated()
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .map(|parts| Literal::Str(Cow::Owned(parts.concat())));
+
+    let bytes_literal = select! { Token::Bytes(b) => b }
+        .repeated()
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .map(|parts| Literal::Bytes(parts.concat()));
+
+    let literal = choice((
+        str_literal,
+        bytes_literal,
+        select! {
+            Token::Num(s) => Literal::Num(Cow::Borrowed(s)),
+            Token::Bool(s) => Literal::Bool(s),
+            Token::None => Literal::None
+        },
+    ))
     .spanned()
     .boxed();
 
@@ -434,7 +479,19 @@ where
     ))
     .boxed();
 
-    let list = enumeration(list_item.clone(), symbol(","))
+    // `[a, b = 0] = xs` gives `b` a default when destructuring
+    let list_literal_item = choice((
+        symbol("*").ignore_then(expr.clone()).map(ListItem::Spread),
+        expr.clone()
+            .then(symbol("=").ignore_then(expr.clone()).or_not())
+            .map(|(target, default)| match default {
+                Some(default) => ListItem::Default(target, default),
+                None => ListItem::Item(target),
+            }),
+    ))
+    .boxed();
+
+    let list = enumeration(list_literal_item, symbol(","))
         .delimited_by_with_eol(symbol("["), symbol("]"))
         .map(Expr::List)
         .labelled("list")
@@ -466,16 +523,34 @@ where
                 //         "Spread operator must be in a list or tuple",
                 //     ));
                 // }
-                ListItem::Item(..) => {
-                    items.push(first);
-                }
-                ListItem::Spread(..) => {
+                ListItem::Item(..) | ListItem::Spread(..) | ListItem::Default(..) => {
                     items.push(first);
                 }
             }
             items.extend(rest);
 
-            Ok((Expr::Tuple(items), e.span()))
+            let span: Span = e.span();
+
+            // `a, b match: ...` matches on the whole tuple rather than on `b`;
+            // a parenthesized `a, (b match: ...)` ends before the tuple does.
+            if last_comma.is_none()
+                && let Some(ListItem::Item((Expr::Match(_, _), match_span))) = items.last()
+                && match_span.end == span.end
+            {
+                let Some(ListItem::Item((Expr::Match(scrutinee, cases), _))) = items.pop() else {
+                    unreachable!();
+                };
+
+                let tuple_span = Span::new((), span.start..scrutinee.1.end);
+                items.push(ListItem::Item(*scrutinee));
+
+                return Ok((
+                    Expr::Match(Box::new((Expr::Tuple(items), tuple_span)), cases),
+                    span,
+                ));
+            }
+
+            Ok((Expr::Tuple(items), span))
         },
     )
     .labelled("nary-tuple")
@@ -496,8 +571,25 @@ where
             ))
             .then_ignore(symbol(":"))
             .then(expr.clone())
-            .map(|(key, value)| MappingItem::Item(key, value)),
-            ident.clone().map(MappingItem::Ident),
+            .then(symbol("=").ignore_then(expr.clone()).or_not())
+            .map(|((key, value), default)| match default {
+                Some(default) => MappingItem::Default(key, value, default),
+                None => MappingItem::Item(key, value),
+            }),
+            ident
+                .clone()
+                .then(symbol("=").ignore_then(expr.clone()).or_not())
+                .map(|(ident, default)| match default {
+                    Some(default) => {
+                        let span = ident.1;
+                        MappingItem::Default(
+                            (Expr::Literal((Literal::Str(ident.0.clone()), span)), span),
+                            (Expr::Ident(ident), span),
+                            default,
+                        )
+                    }
+                    None => MappingItem::Ident(ident),
+                }),
         )),
         symbol(","),
     )
@@ -544,8 +636,19 @@ where
 
     let class_ = just(Token::Kw("class"))
         .ignore_then(
+            enumeration(ident.clone(), symbol(","))
+                .delimited_by_with_eol(symbol("["), symbol("]"))
+                .or_not(),
+        )
+        .then(
             enumeration(
                 choice((
+                    symbol("*")
+                        .ignore_then(expr.clone())
+                        .map(CallItem::ArgSpread),
+                    symbol("**")
+                        .ignore_then(expr.clone())
+                        .map(CallItem::KwargSpread),
                     ident
                         .clone()
                         .then_ignore(symbol("="))
@@ -562,7 +665,13 @@ where
         )
         .then_ignore(just(START_BLOCK))
         .then(expr_or_inline_stmt_or_block.clone())
-        .map(|(arglist, block)| Expr::Class(arglist.unwrap_or_else(|| Vec::new()), Box::new(block)))
+        .map(|((type_params, arglist), block)| {
+            Expr::Class(
+                arglist.unwrap_or_else(Vec::new),
+                Box::new(block),
+                type_params.unwrap_or_else(Vec::new),
+            )
+        })
         .spanned()
         .labelled("class")
         .boxed();
@@ -584,6 +693,15 @@ where
         .labelled("if")
         .boxed();
 
+    let classic_while = just(Token::Kw("while"))
+        .ignore_then(expr.clone())
+        .then_ignore(just(START_BLOCK))
+        .then(expr_or_inline_stmt_or_block.clone())
+        .map(|(cond, body)| Expr::While(Box::new(cond), Box::new(body)))
+        .spanned()
+        .labelled("while")
+        .boxed();
+
     let qualified_ident = ident_expr
         .clone()
         .foldl_with(
@@ -637,6 +755,7 @@ where
         Await,
         Yield,
         YieldFrom,
+        Raise,
     }
 
     let control_kw = choice((
@@ -650,6 +769,7 @@ where
                     ControlKw::Yield
                 }
             }),
+        just(Token::Kw("raise")).map(|_| ControlKw::Raise),
     ))
     .repeated()
     .at_least(1)
@@ -658,6 +778,7 @@ where
             ControlKw::Await => Expr::Await(Box::new(expr)),
             ControlKw::Yield => Expr::Yield(Box::new(expr)),
             ControlKw::YieldFrom => Expr::YieldFrom(Box::new(expr)),
+            ControlKw::Raise => Expr::Raise(Box::new(expr)),
         };
         (expr, e.span())
     })
@@ -669,6 +790,7 @@ where
             ident_expr.clone(),
             classic_if,
             classic_match,
+            classic_while,
             control_kw,
             class_,
             literal_expr.clone(),
@@ -690,6 +812,7 @@ where
         Extension(SIdent<'a>),
         Then(SExpr<'a>),
         Attribute(SIdent<'a>),
+        DynAttribute(SExpr<'a>),
     }
 
     let call_args = enumeration(
@@ -746,6 +869,15 @@ where
         .labelled("attr")
         .boxed();
 
+    let dyn_attribute = symbol(".")
+        .ignore_then(
+            expr.clone()
+                .delimited_by_with_eol(symbol("["), symbol("]"))
+                .map(Postfix::DynAttribute),
+        )
+        .labelled("attr")
+        .boxed();
+
     let extension = symbol("!")
         .ignore_then(ident.clone())
         .map(Postfix::Extension)
@@ -758,7 +890,14 @@ where
             symbol("?")
                 .to(1)
                 .or_not()
-                .then(choice((call, subscript, attribute, then, extension)))
+                .then(choice((
+                    call,
+                    subscript,
+                    attribute,
+                    dyn_attribute,
+                    then,
+                    extension,
+                )))
                 .repeated(),
             |expr, (coal, op), e| -> SExpr {
                 (
@@ -767,6 +906,9 @@ where
                             Postfix::Call(args) => Expr::Call(Box::new(expr), args),
                             Postfix::Subscript(args) => Expr::Subscript(Box::new(expr), args),
                             Postfix::Attribute(attr) => Expr::Attribute(Box::new(expr), attr),
+                            Postfix::DynAttribute(rhs) => {
+                                Expr::DynAttribute(Box::new(expr), Box::new(rhs))
+                            }
                             Postfix::Then(rhs) => Expr::Then(Box::new(expr), Box::new(rhs)),
                             Postfix::Extension(rhs) => Expr::Extension(Box::new(expr), rhs),
                         }
@@ -775,6 +917,9 @@ where
                             Postfix::Call(args) => Expr::MappedCall(Box::new(expr), args),
                             Postfix::Subscript(args) => Expr::MappedSubscript(Box::new(expr), args),
                             Postfix::Attribute(attr) => Expr::MappedAttribute(Box::new(expr), attr),
+                            Postfix::DynAttribute(rhs) => {
+                                Expr::MappedDynAttribute(Box::new(expr), Box::new(rhs))
+                            }
                             Postfix::Then(rhs) => Expr::MappedThen(Box::new(expr), Box::new(rhs)),
                             Postfix::Extension(rhs) => Expr::MappedExtension(Box::new(expr), rhs),
                         }
@@ -896,9 +1041,17 @@ where
             .then(
                 just(Token::Kw("except"))
                     .ignore_then(closed_pattern.clone())
+                    .then(symbol("=>").ignore_then(not_or_try.clone()).or_not())
                     .or_not(),
             )
-            .map(|(expr, typs)| Expr::Checked(Box::new(expr), typs.map(Box::new)))
+            .map(|(expr, except)| {
+                let (typs, handler) = except.unzip();
+                Expr::Checked(
+                    Box::new(expr),
+                    typs.map(Box::new),
+                    handler.flatten().map(Box::new),
+                )
+            })
             .spanned()
             .labelled("checked")
             .boxed(),
@@ -997,7 +1150,7 @@ where
                 .then(default_case.or_not()),
         ))
         .map(|(mut cases, default)| {
-            cases.extend(default.into_iter());
+            cases.extend(default);
             cases
         }),
     );
@@ -1127,11 +1280,19 @@ where
 
     let inline_expr_stmt = expr.clone().map(Stmt::Expr).boxed();
 
-    let while_stmt = just(Token::Kw("while"))
-        .ignore_then(expr.clone())
+    let loop_label = ident
+        .clone()
+        .then_ignore(just(START_BLOCK))
+        .or_not()
+        .boxed();
+
+    let while_stmt = loop_label
+        .clone()
+        .then_ignore(just(Token::Kw("while")))
+        .then(expr.clone())
         .then_ignore(just(START_BLOCK))
         .then(expr_or_inline_stmt_or_block.clone())
-        .map(|(cond, body)| Stmt::While(cond, body))
+        .map(|((label, cond), body)| Stmt::While(label, cond, body))
         .labelled("while statement")
         .boxed();
 
@@ -1166,13 +1327,14 @@ where
         .labelled("try statement")
         .boxed();
 
-    let for_stmt = just(Token::Kw("for"))
-        .ignore_then(group((
+    let for_stmt = loop_label
+        .then_ignore(just(Token::Kw("for")))
+        .then(group((
             nary_pattern.clone().then_ignore(just(Token::Kw("in"))),
             expr.clone().then_ignore(just(START_BLOCK)),
             expr_or_inline_stmt_or_block.clone(),
         )))
-        .map(|(decl, iter, body)| Stmt::For(decl, iter, body))
+        .map(|(label, (decl, iter, body))| Stmt::For(label, decl, iter, body))
         .labelled("for statement")
         .boxed();
 
@@ -1195,6 +1357,47 @@ where
         .labelled("assert statement")
         .boxed();
 
+    let annotate_stmt = ident
+        .clone()
+        .then_ignore(just(START_BLOCK))
+        .then(expr.clone())
+        .map(|(target, annotation)| Stmt::Annotate(target, annotation))
+        .labelled("annotation")
+        .boxed();
+
+    let type_alias_stmt = just(Token::Ident("type"))
+        .ignore_then(ident.clone())
+        .then_ignore(symbol("="))
+        .then(expr.clone())
+        .map(|(name, value)| Stmt::TypeAlias(name, value))
+        .labelled("type alias")
+        .boxed();
+
+    let del_stmt = just(Token::Ident("del"))
+        .ignore_then(nary_tuple.clone())
+        .map(Stmt::Del)
+        .labelled("del statement")
+        .boxed();
+
+    let inline_del_stmt = just(Token::Ident("del"))
+        .ignore_then(expr.clone())
+        .map(Stmt::Del)
+        .labelled("inline del statement")
+        .boxed();
+
+    // `defer` is a contextual keyword; followed by a symbol, as in `defer(x)`, `defer.x` or
+    // `defer = x`, it is an ordinary name
+    let defer_stmt = just(Token::Ident("defer"))
+        .then_ignore(
+            select! { Token::Symbol(_) => () }
+                .or(just(Token::Eol).ignored())
+                .not(),
+        )
+        .ignore_then(expr.clone())
+        .map(Stmt::Defer)
+        .labelled("defer statement")
+        .boxed();
+
     let raise_stmt = just(Token::Kw("raise"))
         .ignore_then(nary_tuple.clone().or_not())
         .map(Stmt::Raise)
@@ -1208,15 +1411,22 @@ where
         .boxed();
 
     let break_stmt = just(Token::Kw("break"))
-        .map(|_| Stmt::Break)
+        .ignore_then(ident.clone().or_not())
+        .map(Stmt::Break)
         .labelled("break statement")
         .boxed();
 
     let continue_stmt = just(Token::Kw("continue"))
-        .map(|_| Stmt::Continue)
+        .ignore_then(ident.clone().or_not())
+        .map(Stmt::Continue)
         .labelled("continue statement")
         .boxed();
 
+    let pass_stmt = just(Token::Kw("pass"))
+        .map(|_| Stmt::Pass)
+        .labelled("pass statement")
+        .boxed();
+
     let import_stmt = just(Token::Kw("export"))
         .to(1)
         .or_not()
@@ -1264,15 +1474,20 @@ where
     stmt.define(
         choice((
             assign_stmt.then_ignore(just(Token::Eol)),
+            del_stmt.then_ignore(just(Token::Eol)),
+            type_alias_stmt.then_ignore(just(Token::Eol)),
+            assert_stmt.then_ignore(just(Token::Eol)),
+            defer_stmt.clone().then_ignore(just(Token::Eol)),
+            while_stmt.clone().then_ignore(just(Token::Eol)),
+            raise_stmt.then_ignore(just(Token::Eol)),
             expr_stmt.then_ignore(just(Token::Eol)),
             module_stmt.then_ignore(just(Token::Eol)),
-            while_stmt.clone().then_ignore(just(Token::Eol)),
             for_stmt.clone().then_ignore(just(Token::Eol)),
+            annotate_stmt.then_ignore(just(Token::Eol)),
             return_stmt.then_ignore(just(Token::Eol)),
-            assert_stmt.then_ignore(just(Token::Eol)),
-            raise_stmt.then_ignore(just(Token::Eol)),
             break_stmt.clone().then_ignore(just(Token::Eol)),
             continue_stmt.clone().then_ignore(just(Token::Eol)),
+            pass_stmt.clone().then_ignore(just(Token::Eol)),
             import_stmt.then_ignore(just(Token::Eol)),
             try_stmt.then_ignore(just(Token::Eol)),
         ))
@@ -1284,20 +1499,26 @@ where
     inline_stmt.define(
         choice((
             inline_assign_stmt,
-            inline_expr_stmt,
+            inline_del_stmt,
+            defer_stmt,
             while_stmt,
+            inline_raise_stmt,
+            inline_expr_stmt,
             for_stmt,
             inline_return_stmt,
-            inline_raise_stmt,
             break_stmt,
             continue_stmt,
+            pass_stmt,
         ))
         .labelled("inline-statement")
         .spanned()
         .boxed(),
     );
 
-    stmts.labelled("program")
+    // empty input lexes to a lone Eol
+    let empty_program = just(Token::Eol).then(end()).to(Vec::new());
+
+    choice((empty_program, stmts)).labelled("program")
 }
 
 pub fn parse_tokens<'tokens, 'src: 'tokens>(
@@ -42,8 +42,24 @@ impl AstBuilder {
         (Stmt::Assert(expr, msg), self.span)
     }
 
+    pub fn annotate<'src>(&self, target: SIdent<'src>, annotation: SExpr<'src>) -> SStmt<'src> {
+        (Stmt::Annotate(target, annotation), self.span)
+    }
+
+    pub fn type_alias<'src>(&self, name: SIdent<'src>, value: SExpr<'src>) -> SStmt<'src> {
+        (Stmt::TypeAlias(name, value), self.span)
+    }
+
+    pub fn del<'src>(&self, target: SExpr<'src>) -> SStmt<'src> {
+        (Stmt::Del(target), self.span)
+    }
+
+    pub fn defer<'src>(&self, expr: SExpr<'src>) -> SStmt<'src> {
+        (Stmt::Defer(expr), self.span)
+    }
+
     pub fn while_<'src>(&self, test: SExpr<'src>, body: SExpr<'src>) -> SStmt<'src> {
-        (Stmt::While(test, body), self.span)
+        (Stmt::While(None, test, body), self.span)
     }
 
     pub fn for_<'src>(
@@ -52,7 +68,7 @@ impl AstBuilder {
         iter: SExpr<'src>,
         body: SExpr<'src>,
     ) -> SStmt<'src> {
-        (Stmt::For(target, iter, body), self.span)
+        (Stmt::For(None, target, iter, body), self.span)
     }
 
     pub fn import<'src>(&self, import: ImportStmt<'src>) -> SStmt<'src> {
@@ -73,11 +89,11 @@ impl AstBuilder {
     }
 
     pub fn break_<'src>(&self) -> SStmt<'src> {
-        (Stmt::Break, self.span)
+        (Stmt::Break(None), self.span)
     }
 
     pub fn continue_<'src>(&self) -> SStmt<'src> {
-        (Stmt::Continue, self.span)
+        (Stmt::Continue(None), self.span)
     }
 
     // Expression builders
@@ -129,8 +145,12 @@ impl AstBuilder {
         (Expr::Match(Box::new(subject), cases), self.span)
     }
 
+    pub fn while_expr<'src>(&self, test: SExpr<'src>, body: SExpr<'src>) -> SExpr<'src> {
+        (Expr::While(Box::new(test), Box::new(body)), self.span)
+    }
+
     pub fn class<'src>(&self, bases: Vec<SCallItem<'src>>, body: SExpr<'src>) -> SExpr<'src> {
-        (Expr::Class(bases, Box::new(body)), self.span)
+        (Expr::Class(bases, Box::new(body), vec![]), self.span)
     }
 
     pub fn call<'src>(&self, func: SExpr<'src>, args: Vec<SCallItem<'src>>) -> SExpr<'src> {
@@ -157,7 +177,7 @@ impl AstBuilder {
     }
 
     pub fn function<'src>(&self, args: Vec<ArgDefItem<'src>>, body: SExpr<'src>) -> SExpr<'src> {
-        (Expr::Fn(args, Box::new(body)), self.span)
+        (Expr::Fn(args, Box::new(body), vec![]), self.span)
     }
 
     pub fn fstring<'src>(
@@ -196,6 +216,10 @@ impl AstBuilder {
         ListItem::Spread(expr)
     }
 
+    pub fn list_default<'src>(&self, target: SExpr<'src>, default: SExpr<'src>) -> ListItem<'src> {
+        ListItem::Default(target, default)
+    }
+
     pub fn mapping_item<'src>(&self, key: SExpr<'src>, value: SExpr<'src>) -> MappingItem<'src> {
         MappingItem::Item(key, value)
     }
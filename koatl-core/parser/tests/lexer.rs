@@ -2,7 +2,7 @@
 
 #[cfg(test)]
 mod tests {
-    use parser::{Token, tokenize};
+    use parser::{Token, relex, tokenize, tokenize_full};
 
     #[test]
     fn test_tokenize_simple_identifier() {
@@ -77,6 +77,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tokenize_number_then() {
+        let input = "3.(f)";
+        let (result, errors) = tokenize(input);
+
+        assert!(errors.is_empty(), "Expected no errors, got: {:?}", errors);
+        assert!(result.is_some(), "Expected tokenization result");
+
+        let tokens = result.unwrap();
+
+        match &tokens.0[0].0 {
+            Token::Num(num) => assert_eq!(*num, "3"),
+            _ => panic!("Expected number token, got: {:?}", tokens.0[0].0),
+        }
+    }
+
     #[test]
     fn test_tokenize_string() {
         let input = r#""hello world""#;
@@ -221,4 +237,244 @@ mod tests {
         // This should produce an error due to invalid indentation
         assert!(!errors.is_empty(), "Expected indentation error");
     }
+
+    #[test]
+    fn test_unterminated_string_span() {
+        for input in ["x = \"abc\n", "x = \"abc"] {
+            let (result, errors) = tokenize(input);
+
+            assert!(result.is_none());
+            assert_eq!(errors.len(), 1, "Expected one error, got: {:?}", errors);
+            assert_eq!(errors[0].span().into_range(), 4..5);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_fstring_span() {
+        for input in ["x = f\"a{1}bc\n", "x = f\"a{1}bc"] {
+            let (result, errors) = tokenize(input);
+
+            assert!(result.is_none());
+            assert_eq!(errors.len(), 1, "Expected one error, got: {:?}", errors);
+            assert_eq!(errors[0].span().into_range(), 4..6);
+        }
+
+        let (result, errors) = tokenize("x = f\"\"\"a{1}bc");
+        assert_eq!(errors.len(), 1, "Expected one error, got: {:?}", errors);
+        assert_eq!(errors[0].span().into_range(), 4..8);
+    }
+
+    #[test]
+    fn test_mixed_tab_and_space_indentation() {
+        let input = "f = () =>\n    a\ng = () =>\n\tb\n";
+        let (result, errors) = tokenize(input);
+
+        assert!(result.is_none());
+        assert_eq!(errors.len(), 1, "Expected one error, got: {:?}", errors);
+        assert!(errors[0].to_string().contains("tabs and spaces"));
+        assert_eq!(errors[0].span().into_range(), 26..27);
+
+        let (result, errors) = tokenize("f = () =>\n \tb\n");
+        assert_eq!(errors.len(), 1, "Expected one error, got: {:?}", errors);
+        assert_eq!(errors[0].span().into_range(), 11..12);
+    }
+
+    #[test]
+    fn test_tab_indentation() {
+        let (result, errors) = tokenize("f = () =>\n\tb\n\tif c:\n\t\td\n");
+
+        assert!(errors.is_empty(), "Expected no errors, got: {:?}", errors);
+    }
+
+    #[test]
+    fn test_imaginary_number() {
+        let (result, errors) = tokenize("x = 3j + 1.5J");
+
+        assert!(errors.is_empty(), "Expected no errors, got: {:?}", errors);
+        let tokens = result.unwrap();
+        assert!(tokens.0.iter().any(|(t, _)| *t == Token::Num("3j")));
+        assert!(tokens.0.iter().any(|(t, _)| *t == Token::Num("1.5J")));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let cases = [
+            (r#""a\nb\tc""#, "a\nb\tc"),
+            (r#""\\ \' \"""#, "\\ ' \""),
+            (r#""\a\b\f\v\r""#, "\x07\x08\x0c\x0b\r"),
+            (r#""\0""#, "\0"),
+            (r#""\101\1010""#, "AA0"),
+            (r#""\x41\xe9""#, "A\u{e9}"),
+            (r#""\u00e9\u4e2d""#, "\u{e9}\u{4e2d}"),
+            (r#""\U0001F600""#, "\u{1F600}"),
+            (r#""\N{LATIN SMALL LETTER E WITH ACUTE}""#, "\u{e9}"),
+            // unknown escapes are kept, and a backslash-newline continues the string
+            (r#""\q\d""#, "\\q\\d"),
+            ("\"ab\\\ncd\"", "abcd"),
+            ("\"ab\\\r\ncd\"", "abcd"),
+            ("r\"ab\\\ncd\"", "ab\\\ncd"),
+        ];
+
+        for (input, expected) in cases {
+            let (result, errors) = tokenize(input);
+
+            assert!(
+                errors.is_empty(),
+                "{input}: expected no errors, got: {:?}",
+                errors
+            );
+            match &result.unwrap().0[0].0 {
+                Token::Str(s) => assert_eq!(s, expected, "{input}"),
+                t => panic!("{input}: expected a string, got {t}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_string_escapes() {
+        let cases = [
+            (r#""\x4""#, "expected 2 hex digits"),
+            (r#""\u12""#, "expected 4 hex digits"),
+            (r#""\UFFFFFFFF""#, "invalid unicode code point"),
+            (r#""\N{NOT A REAL NAME}""#, "unknown unicode character name"),
+            (r#""\N{oops""#, "malformed"),
+        ];
+
+        for (input, message) in cases {
+            let (result, errors) = tokenize(input);
+
+            assert!(result.is_none(), "{input}: expected failure");
+            assert_eq!(
+                errors.len(),
+                1,
+                "{input}: expected one error, got: {:?}",
+                errors
+            );
+            assert!(
+                errors[0].to_string().contains(message),
+                "{input}: got {}",
+                errors[0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_escape_warning() {
+        let src = r#"x = "a\q" + f"\d{x}\d""#;
+        let (result, errors) = tokenize_full(src);
+
+        assert!(errors.is_empty(), "expected no errors, got: {:?}", errors);
+        let warnings = result.unwrap().warnings;
+        let spans = warnings
+            .iter()
+            .map(|(message, span)| {
+                assert!(
+                    message.contains("invalid escape sequence"),
+                    "got: {message}"
+                );
+                &src[span.into_range()]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [r"\q", r"\d", r"\d"]);
+    }
+
+    #[test]
+    fn test_raw_and_bytes_strings() {
+        let cases = [
+            (r#"rb"\d""#, Token::Bytes(br"\d".to_vec())),
+            (r#"bR"\d""#, Token::Bytes(br"\d".to_vec())),
+            (r#"b"\x00a\n""#, Token::Bytes(b"\x00a\n".to_vec())),
+            (r#"r"\n\"""#, Token::Str(r#"\n\""#.to_string())),
+            (r#"R"\\""#, Token::Str(r"\\".to_string())),
+            // raw literals have no escapes, and bytes keep unicode escapes verbatim
+            (r#"rb"\u0041""#, Token::Bytes(br"\u0041".to_vec())),
+            (r#"rb"\N""#, Token::Bytes(br"\N".to_vec())),
+            (r#"b"\u0041""#, Token::Bytes(br"\u0041".to_vec())),
+            (r#"b"\N{DASH}\x41""#, Token::Bytes(br"\N{DASH}A".to_vec())),
+        ];
+
+        for (input, expected) in cases {
+            let (result, errors) = tokenize(input);
+
+            assert!(
+                errors.is_empty(),
+                "{input}: expected no errors, got: {:?}",
+                errors
+            );
+            assert_eq!(result.unwrap().0[0].0, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_string_prefixes() {
+        let cases = [
+            (r#"fb"x""#, "cannot be f-strings"),
+            (r#"Bf"x""#, "cannot be f-strings"),
+            (r#"rr"x""#, "invalid string prefix"),
+            (r#"b"é""#, "only contain ASCII"),
+            (r#"rb"é""#, "only contain ASCII"),
+        ];
+
+        for (input, message) in cases {
+            let (result, errors) = tokenize(input);
+
+            assert!(result.is_none(), "{input}: expected failure");
+            assert!(
+                errors[0].to_string().contains(message),
+                "{input}: got {}",
+                errors[0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_relex_matches_full_tokenize() {
+        let old_src = "x = $$ + 1\nf = (a) =>\n    y = [\n        a,\n    ]\n    y\n\nz = f\"{x}\" + \"s\"\nw = z\n";
+        let (old_tokens, errors) = tokenize(old_src);
+        assert!(errors.is_empty(), "{errors:?}");
+        let old_tokens = old_tokens.unwrap();
+
+        // (offset, deleted length, inserted text)
+        let edits = [
+            (old_src.find("z = f").unwrap() + 2, 0, "+"),
+            (old_src.find("w = z").unwrap() + 4, 1, "zz + 1"),
+            (old_src.find("w = z").unwrap(), 0, "    "),
+            (old_src.find("z = f").unwrap(), 0, "\tq = 2\n"),
+            (old_src.find("    y\n").unwrap() + 4, 1, "y + 2"),
+            (old_src.find("a,").unwrap(), 2, "a, b"),
+            (old_src.len(), 0, "v = 3\n"),
+            (0, 0, "u = 0\n"),
+        ];
+
+        for (start, len, text) in edits {
+            let new_src = format!("{}{}{}", &old_src[..start], text, &old_src[start + len..]);
+            let (full, full_errors) = tokenize(&new_src);
+            let (incremental, incremental_errors) = relex(&old_tokens, &new_src, start);
+
+            assert_eq!(
+                full.map(|t| t.0),
+                incremental.map(|t| t.0),
+                "edit at {start}: {new_src:?}"
+            );
+            assert_eq!(full_errors.len(), incremental_errors.len());
+        }
+    }
+
+    #[test]
+    fn test_tokenize_escaped_placeholder() {
+        let (result, errors) = tokenize("$$ $");
+
+        assert!(errors.is_empty(), "Expected no errors, got: {:?}", errors);
+        let tokens = result.unwrap();
+
+        match &tokens.0[0].0 {
+            Token::Ident(name) => assert_eq!(*name, "_tl_dollar"),
+            _ => panic!("Expected identifier token, got: {:?}", tokens.0[0].0),
+        }
+
+        match &tokens.0[1].0 {
+            Token::Symbol(sym) => assert_eq!(*sym, "$"),
+            _ => panic!("Expected symbol token, got: {:?}", tokens.0[1].0),
+        }
+    }
 }
@@ -3,21 +3,24 @@ pub mod parser;
 pub mod py;
 pub mod transform;
 
-use ::parser::ast::SStmt;
-use parser::ast::Span;
-use parser::{TokenList, parse_tokens, tokenize};
+use ::parser::ast::{SStmt, Stmt};
+use parser::ast::{Span, Spanned};
+use parser::{TOutput, Token, TokenList, parse_tokens, tokenize_full};
 
-use crate::py::ast::{PyAccessCtx, PyImportAlias, PyListItem, PyLiteral};
+use crate::linecol::LineColCache;
+use crate::py::ast::{PyAccessCtx, PyImportAlias, PyListItem, PyLiteral, SPyExpr};
 use crate::py::util::PyAstBuilder;
 use crate::py::{ast::PyBlock, emit::EmitCtx};
-use crate::transform::transform_ast;
+use crate::transform::{TfErrs, transform_ast, transform_expr_ast};
 use ariadne::{Color, Label, Report, ReportKind, sources};
+use std::time::{Duration, Instant};
 
 pub enum TlErrKind {
     Tokenize,
     Parse,
     Transform,
     Emit,
+    Warning,
 }
 
 pub struct TlErr {
@@ -34,6 +37,38 @@ pub struct TranspileOptions {
     pub inject_runtime: bool,
     pub set_exports: bool,
     pub allow_await: bool,
+    /// The string emitted per indentation level by `transpile_to_source`.
+    pub indent: String,
+    /// Report every placeholder that no expression can capture, instead of stopping at the first.
+    pub strict: bool,
+    /// Lower or-patterns made only of literals to a membership guard.
+    pub fold_literal_or_patterns: bool,
+    /// The module star-imported when `inject_prelude` is set.
+    pub prelude_module: String,
+    /// The module imported from when `inject_runtime` is set.
+    pub runtime_module: String,
+    /// Import only the runtime's `__tl__` namespace, and only if the module uses a helper from it.
+    pub import_used_helpers_only: bool,
+    /// Mixed into every generated temporary name, so that outputs transpiled with different salts
+    /// can share a namespace without collisions. Only ASCII letters, digits and `_` are accepted.
+    pub temp_var_salt: Option<String>,
+    /// Accept `match` and `matches` expressions. Embedders can clear these `allow_*` flags to
+    /// restrict the accepted language; disabled constructs are reported as transform errors.
+    pub allow_match: bool,
+    /// Accept `await` where `allow_await` would otherwise permit it.
+    pub allow_async: bool,
+    /// Make the first parameter of every method in a class body, e.g. `self`, positional-only.
+    /// Static methods are left alone, but only when decorated with the bare identifier
+    /// `staticmethod`; an aliased or attribute decorator like `builtins.staticmethod` isn't
+    /// recognized.
+    pub posonly_self: bool,
+    /// Return a `Record` keyed by name from `return a, b`, when every returned item is a plain
+    /// identifier.
+    pub record_returns: bool,
+    /// Print the value of every top-level expression statement, like a notebook echoing each line.
+    pub echo_expressions: bool,
+    /// The `(major, minor)` Python version to emit code for; `type` aliases need 3.12.
+    pub python_version: (u8, u8),
 }
 
 impl TranspileOptions {
@@ -43,6 +78,19 @@ impl TranspileOptions {
             inject_runtime: true,
             set_exports: false,
             allow_await: false,
+            indent: "  ".to_string(),
+            strict: false,
+            fold_literal_or_patterns: false,
+            prelude_module: "koatl.prelude".to_string(),
+            runtime_module: "koatl.runtime".to_string(),
+            import_used_helpers_only: false,
+            temp_var_salt: None,
+            allow_match: true,
+            allow_async: true,
+            posonly_self: false,
+            record_returns: false,
+            echo_expressions: false,
+            python_version: (3, 8),
         }
     }
 
@@ -55,12 +103,9 @@ impl TranspileOptions {
     }
 
     pub fn module() -> Self {
-        TranspileOptions {
-            allow_await: false,
-            inject_prelude: true,
-            inject_runtime: true,
-            set_exports: true,
-        }
+        let mut opt = TranspileOptions::script();
+        opt.set_exports = true;
+        opt
     }
 
     pub fn prelude() -> Self {
@@ -70,22 +115,77 @@ impl TranspileOptions {
     }
 }
 
+/// A transpiled module, with what emitters need besides the Python AST itself.
+pub struct TranspileOutput<'src> {
+    pub py_ast: PyBlock<'src>,
+    /// Suspicious but valid code, as `TlErrKind::Warning` errors.
+    pub warnings: Vec<TlErr>,
+    /// The 1-based line and tag of every `# type: ignore` comment.
+    pub type_ignores: Vec<(usize, &'src str)>,
+}
+
 pub fn transpile_to_py_ast<'src>(
     src: &'src str,
     options: TranspileOptions,
 ) -> TlResult<PyBlock<'src>> {
-    let tl_ast = parse_tl(src)?;
+    transpile_to_py_ast_with_warnings(src, options).map(|output| output.py_ast)
+}
 
-    let output = transform_ast(&src, &tl_ast, options.allow_await).map_err(|e| {
-        e.0.into_iter()
-            .map(|e| TlErr {
-                kind: TlErrKind::Transform,
-                message: e.message,
-                span: e.span,
+/// Like `transpile_to_py_ast`, but also return the warnings and `# type: ignore` comments.
+pub fn transpile_to_py_ast_with_warnings<'src>(
+    src: &'src str,
+    options: TranspileOptions,
+) -> TlResult<TranspileOutput<'src>> {
+    transpile_to_py_ast_timed(src, options, &mut PhaseTimings::default())
+}
+
+/// Transpile `src` without emitting it, and return the transpile warnings together with the
+/// lint findings from `py::lint::lint`, ordered by position.
+pub fn lint(src: &str, options: TranspileOptions) -> TlResult<Vec<TlErr>> {
+    let TranspileOutput {
+        mut py_ast,
+        mut warnings,
+        ..
+    } = transpile_to_py_ast_with_warnings(src, options)?;
+
+    warnings.extend(
+        py::lint::lint(&mut py_ast)
+            .into_iter()
+            .map(|(message, span)| TlErr {
+                kind: TlErrKind::Warning,
+                message,
+                span: Some(span),
                 contexts: vec![],
-            })
-            .collect::<Vec<_>>()
-    })?;
+            }),
+    );
+    warnings.sort_by_key(|w| w.span.map(|s| s.start));
+
+    Ok(warnings)
+}
+
+/// Wall-clock time spent in each phase of transpilation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseTimings {
+    pub lex: Duration,
+    pub parse: Duration,
+    pub transform: Duration,
+}
+
+/// Like `transpile_to_py_ast_with_warnings`, but also record how long each phase took.
+/// Phases that were not reached because of an earlier error are left at zero.
+pub fn transpile_to_py_ast_timed<'src>(
+    src: &'src str,
+    options: TranspileOptions,
+    timings: &mut PhaseTimings,
+) -> TlResult<TranspileOutput<'src>> {
+    let ParsedModule {
+        tl_ast,
+        type_ignores,
+        warnings: lex_warnings,
+    } = parse_tl_timed(src, timings)?;
+
+    let start = Instant::now();
+    let output = transform_ast(src, &tl_ast, &options).map_err(transform_errs)?;
 
     let mut py_ast = output.py_block;
 
@@ -99,7 +199,7 @@ pub fn transpile_to_py_ast<'src>(
         py_ast.0.insert(
             0,
             a.import_from(
-                Some("koatl.prelude".into()),
+                Some(options.prelude_module.clone().into()),
                 vec![PyImportAlias {
                     name: "*".into(),
                     as_name: None,
@@ -109,13 +209,23 @@ pub fn transpile_to_py_ast<'src>(
         );
     }
 
-    if options.inject_runtime {
+    let runtime_import = if !options.inject_runtime {
+        None
+    } else if !options.import_used_helpers_only {
+        Some("*")
+    } else if !output.runtime_helpers.is_empty() || options.set_exports {
+        Some("__tl__")
+    } else {
+        None
+    };
+
+    if let Some(name) = runtime_import {
         py_ast.0.insert(
             0,
             a.import_from(
-                Some("koatl.runtime".into()),
+                Some(options.runtime_module.clone().into()),
                 vec![PyImportAlias {
-                    name: "*".into(),
+                    name: name.into(),
                     as_name: None,
                 }],
                 0,
@@ -157,13 +267,81 @@ pub fn transpile_to_py_ast<'src>(
         )));
     }
 
-    Ok(py_ast)
+    let warnings = lex_warnings
+        .into_iter()
+        .chain(output.warnings.0.into_iter().map(|e| TlErr {
+            kind: TlErrKind::Warning,
+            message: e.message,
+            span: e.span,
+            contexts: vec![],
+        }))
+        .collect();
+
+    timings.transform = start.elapsed();
+
+    let type_ignores = if type_ignores.is_empty() {
+        vec![]
+    } else {
+        let line_cache = LineColCache::new(src);
+        type_ignores
+            .into_iter()
+            .map(|(tag, span)| (line_cache.linecol(span.start).0, tag))
+            .collect()
+    };
+
+    Ok(TranspileOutput {
+        py_ast,
+        warnings,
+        type_ignores,
+    })
+}
+
+/// Transpile input that consists of exactly one expression, e.g. for templating.
+pub fn transpile_expr_to_py_ast<'src>(
+    src: &'src str,
+    options: TranspileOptions,
+) -> TlResult<SPyExpr<'src>> {
+    let tl_ast = parse_tl(src)?;
+
+    let expr = match tl_ast.as_slice() {
+        [(Stmt::Expr(expr), _)] => expr,
+        _ => {
+            let span = tl_ast
+                .iter()
+                .enumerate()
+                .find(|(i, stmt)| *i > 0 || !matches!(stmt.0, Stmt::Expr(..)))
+                .map(|(_, stmt)| stmt.1);
+
+            return Err(vec![TlErr {
+                kind: TlErrKind::Parse,
+                message: "Expected a single expression".to_string(),
+                span,
+                contexts: vec![],
+            }]);
+        }
+    };
+
+    transform_expr_ast(src, expr, &options).map_err(transform_errs)
 }
 
 pub fn transpile_to_source(src: &str, options: TranspileOptions) -> TlResult<EmitCtx> {
+    let indent = options.indent.clone();
     let mut py_ast = transpile_to_py_ast(src, options)?;
 
+    emit_py_source(&mut py_ast, &indent)
+}
+
+/// Transpile and pretty-print the resulting Python AST with `{:#?}`, including spans.
+/// The output is deterministic, which makes it suitable for snapshot tests.
+pub fn transpile_to_debug_string(src: &str, options: TranspileOptions) -> TlResult<String> {
+    transpile_to_py_ast(src, options).map(|py_ast| format!("{py_ast:#?}"))
+}
+
+/// Emit Python source for an already transpiled AST, so callers that also need the AST
+/// don't have to transpile twice.
+pub fn emit_py_source(py_ast: &mut PyBlock, indent: &str) -> TlResult<EmitCtx> {
     let mut ctx = EmitCtx::new();
+    ctx.indent = indent.to_string();
     py_ast.emit_to(&mut ctx, 0).map_err(|e| {
         e.0.into_iter()
             .map(|e| TlErr {
@@ -178,6 +356,18 @@ pub fn transpile_to_source(src: &str, options: TranspileOptions) -> TlResult<Emi
     Ok(ctx)
 }
 
+fn transform_errs(errs: TfErrs) -> Vec<TlErr> {
+    errs.0
+        .into_iter()
+        .map(|e| TlErr {
+            kind: TlErrKind::Transform,
+            message: e.message,
+            span: e.span,
+            contexts: vec![],
+        })
+        .collect()
+}
+
 pub fn format_errs(errs: &[TlErr], filename: &str, src: &str) -> Vec<u8> {
     let filename = filename.to_string();
     let mut writer = Vec::<u8>::new();
@@ -185,7 +375,12 @@ pub fn format_errs(errs: &[TlErr], filename: &str, src: &str) -> Vec<u8> {
     for e in errs {
         let range = e.span.map(|e| e.into_range()).unwrap_or(0..0);
 
-        Report::build(ReportKind::Error, (filename.clone(), range.clone()))
+        let kind = match e.kind {
+            TlErrKind::Warning => ReportKind::Warning,
+            _ => ReportKind::Error,
+        };
+
+        Report::build(kind, (filename.clone(), range.clone()))
             .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
             .with_message(&e.message)
             .with_label(
@@ -212,10 +407,103 @@ pub fn format_errs(errs: &[TlErr], filename: &str, src: &str) -> Vec<u8> {
     writer
 }
 
+/// An error or warning located by 0-based line and column positions, for editor integrations.
+pub struct Diagnostic<'a> {
+    pub message: &'a str,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+    pub severity: &'static str,
+}
+
+/// Locate each error with `line_cache`, which decides how columns count tabs.
+pub fn diagnostics<'a>(errs: &'a [TlErr], line_cache: &LineColCache) -> Vec<Diagnostic<'a>> {
+    let linecol = |cursor: usize| {
+        let (line, col) = line_cache.linecol(cursor);
+        (line - 1, col)
+    };
+
+    errs.iter()
+        .map(|e| {
+            let range = e.span.map(|e| e.into_range()).unwrap_or(0..0);
+            let (start_line, start_col) = linecol(range.start);
+            let (end_line, end_col) = linecol(range.end);
+
+            Diagnostic {
+                message: &e.message,
+                start_line,
+                start_col,
+                end_line,
+                end_col,
+                severity: match e.kind {
+                    TlErrKind::Warning => "warning",
+                    _ => "error",
+                },
+            }
+        })
+        .collect()
+}
+
+/// Brackets and blocks nested deeper than this are rejected before parsing, since the
+/// parser slows down sharply on deeply bracketed input.
+const MAX_NESTING_DEPTH: usize = 200;
+
+/// Run `f`, first growing the stack if little of it is left. The recursive transform and emit
+/// paths go through this, so long operator chains don't overflow the stack.
+pub fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(128 * 1024, 2 * 1024 * 1024, f)
+}
+
+fn check_nesting_depth(tokens: &TokenList) -> TlResult<()> {
+    let mut depth = 0usize;
+
+    for (token, span) in &tokens.0 {
+        match token {
+            Token::Symbol("(" | "[" | "{" | "BEGIN_BLOCK") => {
+                depth += 1;
+                if depth > MAX_NESTING_DEPTH {
+                    return Err(vec![TlErr {
+                        kind: TlErrKind::Parse,
+                        message: format!(
+                            "Too many nested brackets or blocks (the limit is {MAX_NESTING_DEPTH})"
+                        ),
+                        span: Some(*span),
+                        contexts: vec![],
+                    }]);
+                }
+            }
+            Token::Symbol(")" | "]" | "}" | "END_BLOCK") => {
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 pub fn parse_tl<'src>(src: &'src str) -> TlResult<Vec<SStmt<'src>>> {
+    parse_tl_timed(src, &mut PhaseTimings::default()).map(|parsed| parsed.tl_ast)
+}
+
+/// A parsed module, with what the lexer collected besides the tokens.
+struct ParsedModule<'src> {
+    tl_ast: Vec<SStmt<'src>>,
+    /// The tag and span of every `# type: ignore` comment.
+    type_ignores: Vec<Spanned<&'src str>>,
+    warnings: Vec<TlErr>,
+}
+
+fn parse_tl_timed<'src>(
+    src: &'src str,
+    timings: &mut PhaseTimings,
+) -> TlResult<ParsedModule<'src>> {
     let mut errs = vec![];
 
-    let (tokens, token_errs) = tokenize(&src);
+    let start = Instant::now();
+    let (output, token_errs) = tokenize_full(src);
+    timings.lex = start.elapsed();
     errs.extend(token_errs.into_iter().map(|e| {
         TlErr {
             kind: TlErrKind::Tokenize,
@@ -228,13 +516,21 @@ pub fn parse_tl<'src>(src: &'src str) -> TlResult<Vec<SStmt<'src>>> {
         }
     }));
 
-    let tokens: TokenList<'src> = match tokens {
-        Some(tokens) => tokens,
-        None => return Err(errs),
+    let Some(TOutput {
+        tokens,
+        type_ignores,
+        warnings,
+    }) = output
+    else {
+        return Err(errs);
     };
+
+    check_nesting_depth(&tokens)?;
     // println!("tokens: {tokens}");
 
-    let (tl_ast, parser_errs) = parse_tokens(&src, &tokens);
+    let start = Instant::now();
+    let (tl_ast, parser_errs) = parse_tokens(src, &tokens);
+    timings.parse = start.elapsed();
     errs.extend(parser_errs.into_iter().map(|e| {
         TlErr {
             kind: TlErrKind::Parse,
@@ -247,8 +543,22 @@ pub fn parse_tl<'src>(src: &'src str) -> TlResult<Vec<SStmt<'src>>> {
         }
     }));
 
-    let tl_ast: Vec<SStmt<'src>> = tl_ast.ok_or_else(|| errs)?;
+    let tl_ast: Vec<SStmt<'src>> = tl_ast.ok_or(errs)?;
     // println!("AST: {ast:?}");
 
-    Ok(tl_ast)
+    let warnings = warnings
+        .into_iter()
+        .map(|(message, span)| TlErr {
+            kind: TlErrKind::Warning,
+            message,
+            span: Some(span),
+            contexts: vec![],
+        })
+        .collect();
+
+    Ok(ParsedModule {
+        tl_ast,
+        type_ignores,
+        warnings,
+    })
 }
@@ -1,28 +1,65 @@
 pub struct LineColCache {
     line_starts: Vec<usize>,
+    tabs: Vec<usize>,
+    tab_width: Option<usize>,
 }
 
 impl LineColCache {
     pub fn new(source: &str) -> Self {
         let mut line_starts = vec![0];
+        let mut tabs = vec![];
 
         for (i, c) in source.char_indices() {
             if c == '\n' {
                 line_starts.push(i + 1);
+            } else if c == '\t' {
+                tabs.push(i);
             }
         }
 
         line_starts.push(usize::MAX);
 
-        Self { line_starts }
+        Self {
+            line_starts,
+            tabs,
+            tab_width: None,
+        }
+    }
+
+    /// Like `new`, but columns count each tab up to the next multiple of `tab_width`, the way
+    /// an editor displays it. Columns are byte offsets otherwise, which the Python AST needs.
+    pub fn with_tab_width(source: &str, tab_width: usize) -> Self {
+        Self {
+            tab_width: Some(tab_width.max(1)),
+            ..Self::new(source)
+        }
     }
 
     pub fn linecol(&self, cursor: usize) -> (usize, usize) {
         for (i, pos) in self.line_starts.iter().enumerate() {
             if *pos > cursor {
-                return (i, cursor - self.line_starts[i - 1]);
+                let line_start = self.line_starts[i - 1];
+                return (i, self.column(line_start, cursor));
             }
         }
         panic!();
     }
+
+    fn column(&self, line_start: usize, cursor: usize) -> usize {
+        let Some(tab_width) = self.tab_width else {
+            return cursor - line_start;
+        };
+
+        let first = self.tabs.partition_point(|&t| t < line_start);
+        let mut col = 0;
+        let mut pos = line_start;
+
+        for &tab in self.tabs[first..].iter().take_while(|&&t| t < cursor) {
+            col += tab - pos;
+            col = (col / tab_width + 1) * tab_width;
+            pos = tab + 1;
+        }
+
+        col + (cursor - pos)
+    }
 }
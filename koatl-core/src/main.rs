@@ -39,18 +39,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             errs.into_iter().for_each(|e| {
                 let range = e.span.map(|e| e.into_range()).unwrap_or(0..0);
                 let err_prefix = match e.kind {
+                    TlErrKind::Tokenize => "Tokenizer Error: ",
                     TlErrKind::Parse => "Parser Error: ",
                     TlErrKind::Transform => "Transformation Error: ",
                     TlErrKind::Emit => "Emission Error: ",
+                    TlErrKind::Warning => "Warning: ",
                 };
 
-                Report::build(ReportKind::Error, (filename.clone(), range.clone()))
+                let (report_kind, color) = match e.kind {
+                    TlErrKind::Warning => (ReportKind::Warning, Color::Yellow),
+                    _ => (ReportKind::Error, Color::Red),
+                };
+
+                Report::build(report_kind, (filename.clone(), range.clone()))
                     .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
                     .with_message(err_prefix.to_string() + &e.message)
                     .with_label(
                         Label::new((filename.clone(), range.clone()))
                             .with_message(e.message)
-                            .with_color(Color::Red),
+                            .with_color(color),
                     )
                     .with_labels(e.contexts.into_iter().map(|(label, span)| {
                         Label::new((filename.clone(), span.into_range()))
@@ -34,6 +34,12 @@ pub struct PyMatchCase<'a> {
 #[derive(Debug, Clone)]
 pub struct PyDecorators<'a>(pub Vec<SPyExpr<'a>>);
 
+impl<'a> Default for PyDecorators<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> PyDecorators<'a> {
     pub fn new() -> Self {
         PyDecorators(Vec::new())
@@ -50,6 +56,8 @@ pub enum PyStmt<'a> {
     If(SPyExpr<'a>, PyBlock<'a>, Option<PyBlock<'a>>),
     Match(SPyExpr<'a>, Vec<PyMatchCase<'a>>),
     Assign(SPyExpr<'a>, SPyExpr<'a>),
+    AnnAssign(SPyExpr<'a>, SPyExpr<'a>),
+    TypeAlias(SPyExpr<'a>, SPyExpr<'a>),
     Return(SPyExpr<'a>),
     Raise(Option<SPyExpr<'a>>),
     Assert(SPyExpr<'a>, Option<SPyExpr<'a>>),
@@ -59,15 +67,17 @@ pub enum PyStmt<'a> {
     ImportFrom(Option<PyIdent<'a>>, Vec<PyImportAlias<'a>>, usize),
     FnDef(
         PyIdent<'a>,
-        Vec<PyArgDefItem<'a>>,
+        Vec<SPyArgDefItem<'a>>,
         PyBlock<'a>,
         PyDecorators<'a>,
+        Vec<PyIdent<'a>>,
     ),
     ClassDef(
         PyIdent<'a>,
         Vec<PyCallItem<'a>>,
         PyBlock<'a>,
         PyDecorators<'a>,
+        Vec<PyIdent<'a>>,
     ),
     While(SPyExpr<'a>, PyBlock<'a>),
     For(SPyExpr<'a>, SPyExpr<'a>, PyBlock<'a>),
@@ -83,6 +93,12 @@ pub type SPyStmt<'a> = PySpanned<PyStmt<'a>>;
 #[derive(Debug, Clone)]
 pub struct PyBlock<'a>(pub Vec<SPyStmt<'a>>);
 
+impl<'a> Default for PyBlock<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> PyBlock<'a> {
     pub fn new() -> Self {
         PyBlock(Vec::new())
@@ -120,7 +136,7 @@ impl<'a> IntoIterator for PyBlock<'a> {
 }
 
 impl<'a> PyBlock<'a> {
-    pub fn iter(&self) -> std::slice::Iter<SPyStmt<'a>> {
+    pub fn iter(&self) -> std::slice::Iter<'_, SPyStmt<'a>> {
         self.0.iter()
     }
 }
@@ -152,6 +168,8 @@ pub enum PyBinaryOp {
     Geq,
     Is,
     Nis,
+    In,
+    Nin,
 }
 
 #[derive(Debug, Clone)]
@@ -175,8 +193,12 @@ pub enum PyArgDefItem<'a> {
     Arg(PyIdent<'a>, Option<SPyExpr<'a>>),
     ArgSpread(PyIdent<'a>),
     KwargSpread(PyIdent<'a>),
+    /// Makes the arguments before it positional-only, like `/` in a Python signature.
+    PosOnlyMarker,
 }
 
+pub type SPyArgDefItem<'a> = PySpanned<PyArgDefItem<'a>>;
+
 #[derive(Debug, Clone)]
 pub enum PyListItem<'a> {
     Item(SPyExpr<'a>),
@@ -208,7 +230,7 @@ pub enum PyExpr<'a> {
     Subscript(Box<SPyExpr<'a>>, Box<SPyExpr<'a>>, PyAccessCtx),
 
     IfExpr(Box<SPyExpr<'a>>, Box<SPyExpr<'a>>, Box<SPyExpr<'a>>),
-    Lambda(Vec<PyArgDefItem<'a>>, Box<SPyExpr<'a>>),
+    Lambda(Vec<SPyArgDefItem<'a>>, Box<SPyExpr<'a>>),
 
     List(Vec<PyListItem<'a>>, PyAccessCtx),
     Tuple(Vec<PyListItem<'a>>, PyAccessCtx),
@@ -249,6 +271,7 @@ impl<T> From<(T, Span)> for PySpanned<T> {
 pub enum PyLiteral<'a> {
     Num(PyIdent<'a>),
     Str(PyIdent<'a>),
+    Bytes(Vec<u8>),
     Bool(bool),
     None,
 }
@@ -5,29 +5,37 @@ use parser::{
     lexer::{escape_fstr, escape_str},
 };
 
-use crate::{py::ast::*, transform::TfResult};
+use crate::{grow_stack, py::ast::*, transform::TfResult};
 
 const LOW_PREC: f32 = -100.0;
 const HIGH_PREC: f32 = 100.0;
 
 pub struct EmitCtx {
     pub indentation: usize,
+    pub indent: String,
     pub source: String,
     pub source_line_map: HashMap<usize, Span>,
     pub lineno: usize,
 }
 
+impl Default for EmitCtx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EmitCtx {
     pub fn new() -> Self {
         EmitCtx {
             lineno: 1,
             indentation: 0,
+            indent: "  ".to_string(),
             source: String::new(),
             source_line_map: HashMap::new(),
         }
     }
 
-    pub fn record_source_map(&mut self, span: Span) -> () {
+    pub fn record_source_map(&mut self, span: Span) {
         self.source_line_map.insert(self.lineno, span);
     }
 
@@ -68,7 +76,7 @@ impl EmitCtx {
     }
 
     fn emit_indent(&mut self) -> Span {
-        self.emit(&"  ".repeat(self.indentation))
+        self.emit(&self.indent.repeat(self.indentation))
     }
 
     fn emit_endl(&mut self) -> Span {
@@ -78,7 +86,7 @@ impl EmitCtx {
 }
 
 impl PyImportAlias<'_> {
-    fn emit_to(&mut self, ctx: &mut EmitCtx) -> () {
+    fn emit_to(&mut self, ctx: &mut EmitCtx) {
         if let Some(as_name) = &self.as_name {
             ctx.emit(&format!("{} as {}", self.name, as_name));
         } else {
@@ -130,6 +138,9 @@ impl PyArgDefItem<'_> {
             PyArgDefItem::KwargSpread(name) => {
                 ctx.emit(&format!("**{}", name));
             }
+            PyArgDefItem::PosOnlyMarker => {
+                ctx.emit("/");
+            }
         }
 
         Ok(())
@@ -230,6 +241,7 @@ impl SPyPattern<'_> {
                     ctx.emit(key);
                     ctx.emit("=");
                     value.emit_to(ctx)?;
+                    ctx.emit(", ");
                 }
                 ctx.emit(")");
             }
@@ -258,6 +270,8 @@ impl PyBinaryOp {
             | PyBinaryOp::Geq
             | PyBinaryOp::Is
             | PyBinaryOp::Nis
+            | PyBinaryOp::In
+            | PyBinaryOp::Nin
             | PyBinaryOp::And
             | PyBinaryOp::Or => 0.0,
             PyBinaryOp::Add | PyBinaryOp::Sub => 1.0,
@@ -283,6 +297,8 @@ impl PyBinaryOp {
             PyBinaryOp::Geq => ">=",
             PyBinaryOp::Is => "is",
             PyBinaryOp::Nis => "is not",
+            PyBinaryOp::In => "in",
+            PyBinaryOp::Nin => "not in",
             PyBinaryOp::And => "and",
             PyBinaryOp::Or => "or",
         });
@@ -295,10 +311,10 @@ impl PyLiteral<'_> {
             PyLiteral::Num(num) => {
                 if prec > 15. {
                     ctx.emit("(");
-                    ctx.emit(&num.to_string());
+                    ctx.emit(num.as_ref());
                     ctx.emit(")");
                 } else {
-                    ctx.emit(&num.to_string());
+                    ctx.emit(num.as_ref());
                 }
             }
             PyLiteral::Str(s) => {
@@ -306,6 +322,21 @@ impl PyLiteral<'_> {
                 ctx.emit_escaped_str(s);
                 ctx.emit("\"");
             }
+            PyLiteral::Bytes(b) => {
+                ctx.emit("b\"");
+                for byte in b.iter() {
+                    match byte {
+                        b'\\' => ctx.emit("\\\\"),
+                        b'"' => ctx.emit("\\\""),
+                        b'\n' => ctx.emit("\\n"),
+                        b'\r' => ctx.emit("\\r"),
+                        b'\t' => ctx.emit("\\t"),
+                        0x20..=0x7e => ctx.emit(&(*byte as char).to_string()),
+                        _ => ctx.emit(&format!("\\x{byte:02x}")),
+                    };
+                }
+                ctx.emit("\"");
+            }
             PyLiteral::Bool(b) => {
                 ctx.emit(if *b { "True" } else { "False" });
             }
@@ -328,6 +359,15 @@ impl SPyExpr<'_> {
         ctx: &mut EmitCtx,
         parent_precendence: f32,
         lhs: bool,
+    ) -> TfResult<()> {
+        grow_stack(|| self.emit_sided_to_inner(ctx, parent_precendence, lhs))
+    }
+
+    fn emit_sided_to_inner(
+        &mut self,
+        ctx: &mut EmitCtx,
+        parent_precendence: f32,
+        lhs: bool,
     ) -> TfResult<()> {
         let mut require_paren = false;
         let span_start = ctx.source.len();
@@ -348,7 +388,7 @@ impl SPyExpr<'_> {
 
         match &mut self.value {
             PyExpr::Ident(id, _ctx) => {
-                ctx.emit(&id);
+                ctx.emit(id);
             }
             PyExpr::Tuple(items, _ctx) => {
                 ctx.emit("(");
@@ -452,7 +492,31 @@ impl SPyExpr<'_> {
 
                 obj.emit_to(ctx, prec)?;
                 ctx.emit("[");
-                index.emit_to(ctx, LOW_PREC)?;
+                match &mut index.value {
+                    // slices are only allowed in a tuple if it's not parenthesized
+                    PyExpr::Tuple(items, _)
+                        if items.iter().any(|item| {
+                            matches!(item, PyListItem::Item(e) if matches!(e.value, PyExpr::Slice(..)))
+                        }) =>
+                    {
+                        for (i, item) in items.iter_mut().enumerate() {
+                            if i > 0 {
+                                ctx.emit(", ");
+                            }
+                            match item {
+                                PyListItem::Item(expr) => expr.emit_to(ctx, LOW_PREC)?,
+                                PyListItem::Spread(expr) => {
+                                    ctx.emit("*");
+                                    expr.emit_to(ctx, HIGH_PREC)?;
+                                }
+                            }
+                        }
+                        if items.len() == 1 {
+                            ctx.emit(",");
+                        }
+                    }
+                    _ => index.emit_to(ctx, LOW_PREC)?,
+                }
                 ctx.emit("]");
             }
             PyExpr::Yield(expr) => {
@@ -527,7 +591,7 @@ impl SPyExpr<'_> {
                     if i > 0 {
                         ctx.emit(", ");
                     }
-                    arg.emit_to(ctx)?;
+                    arg.value.emit_to(ctx)?;
                 }
                 ctx.emit(": ");
                 body.emit_to(ctx, HIGH_PREC)?;
@@ -596,11 +660,30 @@ impl SPyStmt<'_> {
                 ctx.emit(":");
                 ctx.emit_endl();
                 body.emit_to(ctx, 1)?;
-                if let Some(orelse) = orelse {
-                    ctx.emit_indent();
-                    ctx.emit("else:");
-                    ctx.emit_endl();
-                    orelse.emit_to(ctx, 1)?;
+
+                let mut orelse = orelse;
+                while let Some(block) = orelse {
+                    // an else block holding only another if is emitted as elif
+                    if let [stmt] = block.0.as_slice()
+                        && matches!(stmt.value, PyStmt::If(..))
+                    {
+                        let PyStmt::If(cond, body, next) = &mut block.0[0].value else {
+                            unreachable!()
+                        };
+                        ctx.emit_indent();
+                        ctx.emit("elif ");
+                        cond.emit_to(ctx, LOW_PREC)?;
+                        ctx.emit(":");
+                        ctx.emit_endl();
+                        body.emit_to(ctx, 1)?;
+                        orelse = next;
+                    } else {
+                        ctx.emit_indent();
+                        ctx.emit("else:");
+                        ctx.emit_endl();
+                        block.emit_to(ctx, 1)?;
+                        break;
+                    }
                 }
             }
             PyStmt::Raise(expr) => {
@@ -611,6 +694,21 @@ impl SPyStmt<'_> {
                 }
                 ctx.emit_endl();
             }
+            PyStmt::TypeAlias(name, value) => {
+                ctx.emit_indent();
+                ctx.emit("type ");
+                name.emit_to(ctx, LOW_PREC)?;
+                ctx.emit(" = ");
+                value.emit_to(ctx, LOW_PREC)?;
+                ctx.emit_endl();
+            }
+            PyStmt::AnnAssign(target, annotation) => {
+                ctx.emit_indent();
+                target.emit_to(ctx, LOW_PREC)?;
+                ctx.emit(": ");
+                annotation.emit_to(ctx, LOW_PREC)?;
+                ctx.emit_endl();
+            }
             PyStmt::Assert(expr, msg) => {
                 ctx.emit_indent();
                 ctx.emit("assert ");
@@ -670,7 +768,7 @@ impl SPyStmt<'_> {
                 ctx.emit("from ");
                 ctx.emit(&".".repeat(*level));
                 if let Some(module) = module {
-                    ctx.emit(&module);
+                    ctx.emit(module);
                 }
                 ctx.emit(" import ");
                 if aliases.is_empty() {
@@ -685,7 +783,7 @@ impl SPyStmt<'_> {
                 }
                 ctx.emit_endl();
             }
-            PyStmt::FnDef(name, args, body, decorators) => {
+            PyStmt::FnDef(name, args, body, decorators, type_params) => {
                 for d in &mut decorators.0 {
                     ctx.emit_indent();
                     ctx.emit("@");
@@ -695,19 +793,24 @@ impl SPyStmt<'_> {
 
                 ctx.emit_indent();
                 ctx.emit("def ");
-                ctx.emit(&name);
+                ctx.emit(name);
+                if !type_params.is_empty() {
+                    ctx.emit("[");
+                    ctx.emit(&type_params.join(", "));
+                    ctx.emit("]");
+                }
                 ctx.emit("(");
                 for (i, arg) in args.iter_mut().enumerate() {
                     if i > 0 {
                         ctx.emit(", ");
                     }
-                    arg.emit_to(ctx)?;
+                    arg.value.emit_to(ctx)?;
                 }
                 ctx.emit("):");
                 ctx.emit_endl();
                 body.emit_to(ctx, 1)?;
             }
-            PyStmt::ClassDef(name, bases, body, decorators) => {
+            PyStmt::ClassDef(name, bases, body, decorators, type_params) => {
                 for d in &mut decorators.0 {
                     ctx.emit_indent();
                     ctx.emit("@");
@@ -717,7 +820,12 @@ impl SPyStmt<'_> {
 
                 ctx.emit_indent();
                 ctx.emit("class ");
-                ctx.emit(&name);
+                ctx.emit(name);
+                if !type_params.is_empty() {
+                    ctx.emit("[");
+                    ctx.emit(&type_params.join(", "));
+                    ctx.emit("]");
+                }
                 ctx.emit("(");
                 for (i, base) in bases.iter_mut().enumerate() {
                     if i > 0 {
@@ -805,9 +913,9 @@ impl SPyStmt<'_> {
                     ctx.emit_indent();
                     ctx.emit("case ");
                     case.pattern.emit_to(ctx)?;
-                    if case.guard.is_some() {
+                    if let Some(guard) = &mut case.guard {
                         ctx.emit(" if ");
-                        case.guard.as_mut().unwrap().emit_to(ctx, LOW_PREC)?;
+                        guard.emit_to(ctx, LOW_PREC)?;
                     }
                     ctx.emit(":");
                     ctx.emit_endl();
@@ -857,4 +965,16 @@ mod tests {
         expr.emit_to(&mut ctx, LOW_PREC).unwrap();
         assert_eq!(ctx.source, "x * (y + z)");
     }
+
+    #[test]
+    fn test_single_element_tuple_to_source() {
+        let a = PyAstBuilder::new(DUMMY_SPAN);
+
+        let mut expr: SPyExpr = a.tuple(vec![a.tuple_item(a.load_ident("x"))], PyAccessCtx::Load);
+
+        let mut ctx = EmitCtx::new();
+
+        expr.emit_to(&mut ctx, LOW_PREC).unwrap();
+        assert_eq!(ctx.source, "(x,)");
+    }
 }
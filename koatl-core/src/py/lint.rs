@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use crate::py::{ast::*, visit::*};
+use parser::ast::Span;
+
+/// Style and correctness checks that need the scoping of the emitted Python: unused local
+/// bindings, bindings that shadow an enclosing scope, and mutable default arguments.
+///
+/// Names starting with `_` are never reported, which also skips the transpiler's temporaries.
+pub fn lint(block: &mut PyBlock<'_>) -> Vec<(String, Span)> {
+    let mut collector = ScopeCollector {
+        scopes: vec![Scope::new(ScopeKind::Module, None)],
+        current: 0,
+        findings: vec![],
+    };
+    collector.visit_block(block);
+
+    let ScopeCollector {
+        scopes,
+        mut findings,
+        ..
+    } = collector;
+
+    // scopes are pushed in pre-order, so every child comes after its parent
+    let mut nested_loads = scopes.iter().map(|s| s.loads.clone()).collect::<Vec<_>>();
+    for i in (1..scopes.len()).rev() {
+        if let Some(parent) = scopes[i].parent {
+            let loads = nested_loads[i].clone();
+            nested_loads[parent].extend(loads);
+        }
+    }
+
+    for (i, scope) in scopes.iter().enumerate() {
+        if scope.kind != ScopeKind::Function {
+            continue;
+        }
+
+        let mut seen = HashSet::new();
+        for (name, span, is_param) in scope.all_bindings() {
+            if name.starts_with('_') || scope.declared.contains(name) || !seen.insert(name) {
+                continue;
+            }
+
+            if !is_param && !nested_loads[i].contains(name) {
+                findings.push((format!("'{name}' is assigned but never used"), span));
+            }
+
+            if enclosing(&scopes, scope).any(|outer| outer.binds(name)) {
+                findings.push((
+                    format!("'{name}' shadows a binding in an enclosing scope"),
+                    span,
+                ));
+            }
+        }
+    }
+
+    findings.sort_by_key(|(_, span)| span.start);
+    findings
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScopeKind {
+    Module,
+    Function,
+    Class,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    parent: Option<usize>,
+    params: Vec<(String, Span)>,
+    bindings: Vec<(String, Span)>,
+    loads: HashSet<String>,
+    declared: HashSet<String>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind, parent: Option<usize>) -> Self {
+        Scope {
+            kind,
+            parent,
+            params: vec![],
+            bindings: vec![],
+            loads: HashSet::new(),
+            declared: HashSet::new(),
+        }
+    }
+
+    fn all_bindings(&self) -> impl Iterator<Item = (&String, Span, bool)> {
+        let params = self.params.iter().map(|(name, span)| (name, *span, true));
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|(name, span)| (name, *span, false));
+        params.chain(bindings)
+    }
+
+    fn binds(&self, name: &str) -> bool {
+        self.all_bindings().any(|(bound, ..)| bound == name)
+    }
+}
+
+/// The scopes whose names are visible from `scope`; class bodies are skipped, as in Python.
+fn enclosing<'s>(scopes: &'s [Scope], scope: &'s Scope) -> impl Iterator<Item = &'s Scope> {
+    std::iter::successors(scope.parent.map(|p| &scopes[p]), |s| {
+        s.parent.map(|p| &scopes[p])
+    })
+    .filter(|s| s.kind != ScopeKind::Class)
+}
+
+struct ScopeCollector {
+    scopes: Vec<Scope>,
+    current: usize,
+    findings: Vec<(String, Span)>,
+}
+
+impl ScopeCollector {
+    fn scope(&mut self) -> &mut Scope {
+        &mut self.scopes[self.current]
+    }
+
+    fn bind(&mut self, name: &str, span: Span) {
+        self.scope().bindings.push((name.to_string(), span));
+    }
+
+    fn in_scope(
+        &mut self,
+        kind: ScopeKind,
+        params: Vec<(String, Span)>,
+        f: impl FnOnce(&mut Self),
+    ) {
+        let parent = self.current;
+        let mut scope = Scope::new(kind, Some(parent));
+        scope.params = params;
+
+        self.scopes.push(scope);
+        self.current = self.scopes.len() - 1;
+        f(self);
+        self.current = parent;
+    }
+
+    /// Visit the defaults in the enclosing scope, and return the parameters bound by `args`.
+    fn arg_defs<'a>(&mut self, args: &mut [SPyArgDefItem<'a>]) -> Vec<(String, Span)> {
+        let mut params = vec![];
+
+        for arg in args {
+            let span = arg.tl_span;
+            match &mut arg.value {
+                PyArgDefItem::Arg(name, default) => {
+                    if let Some(default) = default {
+                        if is_mutable_literal(default) {
+                            self.findings.push((
+                                format!(
+                                    "Mutable default argument '{name}' is shared between calls"
+                                ),
+                                default.tl_span,
+                            ));
+                        }
+                        self.visit_expr(default);
+                    }
+                    params.push((name.to_string(), span));
+                }
+                PyArgDefItem::ArgSpread(name) | PyArgDefItem::KwargSpread(name) => {
+                    params.push((name.to_string(), span));
+                }
+                PyArgDefItem::PosOnlyMarker => {}
+            }
+        }
+
+        params
+    }
+}
+
+impl<'a> PyVisitorMut<'a> for ScopeCollector {
+    fn visit_stmt(&mut self, stmt: &mut SPyStmt<'a>) {
+        let span = stmt.tl_span;
+
+        match &mut stmt.value {
+            PyStmt::FnDef(name, args, body, decorators, _) => {
+                self.bind(name, span);
+                for decorator in &mut decorators.0 {
+                    self.visit_expr(decorator);
+                }
+
+                let params = self.arg_defs(args);
+                self.in_scope(ScopeKind::Function, params, |v| v.visit_block(body));
+            }
+            PyStmt::ClassDef(name, bases, body, decorators, _) => {
+                self.bind(name, span);
+                for decorator in &mut decorators.0 {
+                    self.visit_expr(decorator);
+                }
+                for base in bases {
+                    match base {
+                        PyCallItem::Arg(expr)
+                        | PyCallItem::Kwarg(_, expr)
+                        | PyCallItem::ArgSpread(expr)
+                        | PyCallItem::KwargSpread(expr) => self.visit_expr(expr),
+                    }
+                }
+
+                self.in_scope(ScopeKind::Class, vec![], |v| v.visit_block(body));
+            }
+            PyStmt::Global(names) | PyStmt::Nonlocal(names) => {
+                let names = names.iter().map(|n| n.to_string());
+                self.scope().declared.extend(names);
+            }
+            PyStmt::Import(aliases) => {
+                for alias in aliases.iter() {
+                    let name: &str = match &alias.as_name {
+                        Some(as_name) => as_name,
+                        None => alias.name.split('.').next().unwrap_or_default(),
+                    };
+                    self.bind(name, span);
+                }
+            }
+            PyStmt::ImportFrom(_, aliases, _) => {
+                for alias in aliases.iter() {
+                    let name = alias.as_name.as_ref().unwrap_or(&alias.name);
+                    if name != "*" {
+                        self.bind(name, span);
+                    }
+                }
+            }
+            PyStmt::Try(_, handlers, _) => {
+                for handler in handlers.iter() {
+                    if let Some(name) = &handler.name {
+                        self.bind(name, span);
+                    }
+                }
+                walk_stmt(self, stmt);
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut SPyExpr<'a>) {
+        let span = expr.tl_span;
+
+        match &mut expr.value {
+            PyExpr::Ident(name, PyAccessCtx::Store) => self.bind(name, span),
+            PyExpr::Ident(name, PyAccessCtx::Load | PyAccessCtx::Del) => {
+                self.scope().loads.insert(name.to_string());
+            }
+            PyExpr::Lambda(args, body) => {
+                let params = self.arg_defs(args);
+                self.in_scope(ScopeKind::Function, params, |v| v.visit_expr(body));
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut SPyPattern<'a>) {
+        let span = pattern.tl_span;
+
+        match &pattern.value {
+            PyPattern::As(_, Some(name)) | PyPattern::Mapping(_, Some(name)) => {
+                self.bind(name, span);
+            }
+            PyPattern::Sequence(items) => {
+                for item in items {
+                    if let PyPatternSequenceItem::Spread(Some(name)) = item {
+                        self.bind(name, span);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        walk_pattern(self, pattern);
+    }
+}
+
+/// A list literal or a `{...}` record, which would be shared by every call using the default.
+fn is_mutable_literal(expr: &SPyExpr<'_>) -> bool {
+    match &expr.value {
+        PyExpr::List(..) | PyExpr::Dict(..) => true,
+        PyExpr::Call(func, _) => matches!(
+            &func.value,
+            PyExpr::Attribute(obj, attr, _)
+                if attr == "Record" && matches!(&obj.value, PyExpr::Ident(name, _) if name == "__tl__")
+        ),
+        _ => false,
+    }
+}
@@ -1,3 +1,5 @@
 pub mod ast;
 pub mod emit;
+pub mod lint;
 pub mod util;
+pub mod visit;
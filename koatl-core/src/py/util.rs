@@ -59,11 +59,11 @@ impl PyAstBuilder {
     pub fn fn_def<'src>(
         &self,
         name: impl Into<PyIdent<'src>>,
-        args: Vec<PyArgDefItem<'src>>,
+        args: Vec<SPyArgDefItem<'src>>,
         body: PyBlock<'src>,
     ) -> SPyStmt<'src> {
         (
-            PyStmt::FnDef(name.into(), args, body, PyDecorators::new()),
+            PyStmt::FnDef(name.into(), args, body, PyDecorators::new(), vec![]),
             self.span,
         )
             .into()
@@ -76,7 +76,7 @@ impl PyAstBuilder {
         body: PyBlock<'src>,
     ) -> SPyStmt<'src> {
         (
-            PyStmt::ClassDef(name.into(), bases, body, PyDecorators::new()),
+            PyStmt::ClassDef(name.into(), bases, body, PyDecorators::new(), vec![]),
             self.span,
         )
             .into()
@@ -269,7 +269,7 @@ impl PyAstBuilder {
 
     pub fn lambda<'src>(
         &self,
-        args: Vec<PyArgDefItem<'src>>,
+        args: Vec<SPyArgDefItem<'src>>,
         body: SPyExpr<'src>,
     ) -> SPyExpr<'src> {
         (PyExpr::Lambda(args, Box::new(body)), self.span).into()
@@ -301,16 +301,16 @@ impl PyAstBuilder {
         &self,
         name: impl Into<PyIdent<'src>>,
         default: Option<SPyExpr<'src>>,
-    ) -> PyArgDefItem<'src> {
-        PyArgDefItem::Arg(name.into(), default)
+    ) -> SPyArgDefItem<'src> {
+        (PyArgDefItem::Arg(name.into(), default), self.span).into()
     }
 
-    pub fn arg_def_spread<'src>(&self, name: impl Into<PyIdent<'src>>) -> PyArgDefItem<'src> {
-        PyArgDefItem::ArgSpread(name.into())
+    pub fn arg_def_spread<'src>(&self, name: impl Into<PyIdent<'src>>) -> SPyArgDefItem<'src> {
+        (PyArgDefItem::ArgSpread(name.into()), self.span).into()
     }
 
-    pub fn kwarg_def_spread<'src>(&self, name: impl Into<PyIdent<'src>>) -> PyArgDefItem<'src> {
-        PyArgDefItem::KwargSpread(name.into())
+    pub fn kwarg_def_spread<'src>(&self, name: impl Into<PyIdent<'src>>) -> SPyArgDefItem<'src> {
+        (PyArgDefItem::KwargSpread(name.into()), self.span).into()
     }
 
     // Utility builders for tuple items
@@ -441,6 +441,14 @@ impl PyAstBuilder {
         self.binary(PyBinaryOp::Nis, left, right)
     }
 
+    pub fn in_<'src>(&self, left: SPyExpr<'src>, right: SPyExpr<'src>) -> SPyExpr<'src> {
+        self.binary(PyBinaryOp::In, left, right)
+    }
+
+    pub fn not_in<'src>(&self, left: SPyExpr<'src>, right: SPyExpr<'src>) -> SPyExpr<'src> {
+        self.binary(PyBinaryOp::Nin, left, right)
+    }
+
     pub fn and<'src>(&self, left: SPyExpr<'src>, right: SPyExpr<'src>) -> SPyExpr<'src> {
         self.binary(PyBinaryOp::And, left, right)
     }
@@ -0,0 +1,237 @@
+use crate::py::ast::*;
+
+/// A mutable traversal over the Python AST, for rewriting the output of `transpile_to_py_ast`
+/// before it is emitted.
+///
+/// Every hook defaults to the matching `walk_*` function, which visits the children of the node.
+/// An override that still wants to reach nested nodes should call the `walk_*` function itself.
+pub trait PyVisitorMut<'a> {
+    fn visit_block(&mut self, block: &mut PyBlock<'a>) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut SPyStmt<'a>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &mut SPyExpr<'a>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut SPyPattern<'a>) {
+        walk_pattern(self, pattern);
+    }
+}
+
+pub fn walk_block<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, block: &mut PyBlock<'a>) {
+    for stmt in &mut block.0 {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, stmt: &mut SPyStmt<'a>) {
+    match &mut stmt.value {
+        PyStmt::Expr(expr) | PyStmt::Return(expr) => v.visit_expr(expr),
+        PyStmt::If(cond, body, orelse) => {
+            v.visit_expr(cond);
+            v.visit_block(body);
+            if let Some(orelse) = orelse {
+                v.visit_block(orelse);
+            }
+        }
+        PyStmt::Match(subject, cases) => {
+            v.visit_expr(subject);
+            for case in cases {
+                v.visit_pattern(&mut case.pattern);
+                if let Some(guard) = &mut case.guard {
+                    v.visit_expr(guard);
+                }
+                v.visit_block(&mut case.body);
+            }
+        }
+        PyStmt::Assign(target, value)
+        | PyStmt::AnnAssign(target, value)
+        | PyStmt::TypeAlias(target, value) => {
+            v.visit_expr(target);
+            v.visit_expr(value);
+        }
+        PyStmt::Raise(expr) => {
+            if let Some(expr) = expr {
+                v.visit_expr(expr);
+            }
+        }
+        PyStmt::Assert(cond, msg) => {
+            v.visit_expr(cond);
+            if let Some(msg) = msg {
+                v.visit_expr(msg);
+            }
+        }
+        PyStmt::FnDef(_, args, body, decorators, _) => {
+            for decorator in &mut decorators.0 {
+                v.visit_expr(decorator);
+            }
+            walk_arg_defs(v, args);
+            v.visit_block(body);
+        }
+        PyStmt::ClassDef(_, bases, body, decorators, _) => {
+            for decorator in &mut decorators.0 {
+                v.visit_expr(decorator);
+            }
+            walk_call_items(v, bases);
+            v.visit_block(body);
+        }
+        PyStmt::While(cond, body) => {
+            v.visit_expr(cond);
+            v.visit_block(body);
+        }
+        PyStmt::For(target, iter, body) => {
+            v.visit_expr(target);
+            v.visit_expr(iter);
+            v.visit_block(body);
+        }
+        PyStmt::Try(body, handlers, finally) => {
+            v.visit_block(body);
+            for handler in handlers {
+                if let Some(typ) = &mut handler.typ {
+                    v.visit_expr(typ);
+                }
+                v.visit_block(&mut handler.body);
+            }
+            if let Some(finally) = finally {
+                v.visit_block(finally);
+            }
+        }
+        PyStmt::Del(targets) => {
+            for target in targets {
+                v.visit_expr(target);
+            }
+        }
+        PyStmt::Global(_)
+        | PyStmt::Nonlocal(_)
+        | PyStmt::Import(_)
+        | PyStmt::ImportFrom(..)
+        | PyStmt::Break
+        | PyStmt::Continue
+        | PyStmt::Pass => {}
+    }
+}
+
+pub fn walk_expr<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, expr: &mut SPyExpr<'a>) {
+    match &mut expr.value {
+        PyExpr::Literal(_) | PyExpr::Ident(..) => {}
+        PyExpr::Fstr(parts) => {
+            for part in parts {
+                if let PyFstrPart::Expr(expr, _) = part {
+                    v.visit_expr(expr);
+                }
+            }
+        }
+        PyExpr::Binary(_, lhs, rhs) => {
+            v.visit_expr(lhs);
+            v.visit_expr(rhs);
+        }
+        PyExpr::Unary(_, expr)
+        | PyExpr::Attribute(expr, ..)
+        | PyExpr::Await(expr)
+        | PyExpr::Yield(expr)
+        | PyExpr::YieldFrom(expr) => v.visit_expr(expr),
+        PyExpr::Call(func, args) => {
+            v.visit_expr(func);
+            walk_call_items(v, args);
+        }
+        PyExpr::Subscript(obj, index, _) => {
+            v.visit_expr(obj);
+            v.visit_expr(index);
+        }
+        PyExpr::IfExpr(cond, then, orelse) => {
+            v.visit_expr(cond);
+            v.visit_expr(then);
+            v.visit_expr(orelse);
+        }
+        PyExpr::Lambda(args, body) => {
+            walk_arg_defs(v, args);
+            v.visit_expr(body);
+        }
+        PyExpr::List(items, _) | PyExpr::Tuple(items, _) => {
+            for item in items {
+                match item {
+                    PyListItem::Item(expr) | PyListItem::Spread(expr) => v.visit_expr(expr),
+                }
+            }
+        }
+        PyExpr::Dict(items) => {
+            for item in items {
+                match item {
+                    PyDictItem::Item(key, value) => {
+                        v.visit_expr(key);
+                        v.visit_expr(value);
+                    }
+                    PyDictItem::Spread(expr) => v.visit_expr(expr),
+                }
+            }
+        }
+        PyExpr::Slice(start, stop, step) => {
+            for bound in [start, stop, step].into_iter().flatten() {
+                v.visit_expr(bound);
+            }
+        }
+    }
+}
+
+pub fn walk_pattern<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, pattern: &mut SPyPattern<'a>) {
+    match &mut pattern.value {
+        PyPattern::Value(expr) => v.visit_expr(expr),
+        PyPattern::Singleton(_) => {}
+        PyPattern::As(pattern, _) => {
+            if let Some(pattern) = pattern {
+                v.visit_pattern(pattern);
+            }
+        }
+        PyPattern::Or(patterns) => {
+            for pattern in patterns {
+                v.visit_pattern(pattern);
+            }
+        }
+        PyPattern::Sequence(items) => {
+            for item in items {
+                if let PyPatternSequenceItem::Item(pattern) = item {
+                    v.visit_pattern(pattern);
+                }
+            }
+        }
+        PyPattern::Mapping(items, _) => {
+            for (key, pattern) in items {
+                v.visit_expr(key);
+                v.visit_pattern(pattern);
+            }
+        }
+        PyPattern::Class(cls, patterns, kw_patterns) => {
+            v.visit_expr(cls);
+            for pattern in patterns {
+                v.visit_pattern(pattern);
+            }
+            for (_, pattern) in kw_patterns {
+                v.visit_pattern(pattern);
+            }
+        }
+    }
+}
+
+fn walk_call_items<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, items: &mut [PyCallItem<'a>]) {
+    for item in items {
+        match item {
+            PyCallItem::Arg(expr)
+            | PyCallItem::Kwarg(_, expr)
+            | PyCallItem::ArgSpread(expr)
+            | PyCallItem::KwargSpread(expr) => v.visit_expr(expr),
+        }
+    }
+}
+
+fn walk_arg_defs<'a, V: PyVisitorMut<'a> + ?Sized>(v: &mut V, args: &mut [SPyArgDefItem<'a>]) {
+    for arg in args {
+        if let PyArgDefItem::Arg(_, Some(default)) = &mut arg.value {
+            v.visit_expr(default);
+        }
+    }
+}
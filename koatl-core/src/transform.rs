@@ -1,8 +1,13 @@
 use std::{borrow::Cow, collections::HashSet};
 
 use crate::{
+    TranspileOptions, grow_stack,
     linecol::LineColCache,
-    py::{ast::*, util::PyAstBuilder},
+    py::{
+        ast::*,
+        util::PyAstBuilder,
+        visit::{PyVisitorMut, walk_stmt},
+    },
 };
 use parser::{ast::*, util::AstBuilder};
 
@@ -15,6 +20,12 @@ pub struct TfErr {
 #[derive(Debug)]
 pub struct TfErrs(pub Vec<TfErr>);
 
+impl Default for TfErrs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TfErrs {
     pub fn new() -> Self {
         TfErrs(vec![])
@@ -63,8 +74,28 @@ struct TfCtx<'src> {
     module_star_exports: Vec<PyIdent<'src>>,
 
     allow_top_level_await: bool,
+    strict: bool,
+    fold_literal_or_patterns: bool,
+    temp_var_salt: Option<String>,
+    allow_match: bool,
+    allow_async: bool,
+    posonly_self: bool,
+    record_returns: bool,
+    echo_expressions: bool,
+    python_version: (u8, u8),
+    orphan_placeholder_errs: TfErrs,
+    warnings: TfErrs,
+    runtime_helpers: Vec<&'static str>,
     placeholder_ctx_stack: Vec<PlaceholderCtx>,
     fn_ctx_stack: Vec<FnCtx>,
+    loop_stack: Vec<LoopCtx>,
+    /// The length of `loop_stack` when the innermost finally block being transformed began;
+    /// a break or continue to a loop below it jumps out of the finally block.
+    finally_loop_depth: Option<usize>,
+    /// The span of an if-expression in statement position, whose value may go unused.
+    statement_if: Option<Span>,
+    /// For each enclosing function, the list that its `defer` statements append to, once used.
+    defer_stack: Vec<Option<String>>,
 
     py_kws: HashSet<String>,
 
@@ -84,6 +115,18 @@ impl<'src> TfCtx<'src> {
 
         Ok(TfCtx {
             allow_top_level_await: false,
+            strict: false,
+            fold_literal_or_patterns: false,
+            temp_var_salt: None,
+            allow_match: true,
+            allow_async: true,
+            posonly_self: false,
+            record_returns: false,
+            echo_expressions: false,
+            python_version: (3, 8),
+            orphan_placeholder_errs: TfErrs::new(),
+            warnings: TfErrs::new(),
+            runtime_helpers: Vec::new(),
             source,
             line_cache: LineColCache::new(source),
             exports: Vec::new(),
@@ -91,9 +134,56 @@ impl<'src> TfCtx<'src> {
             module_star_exports: Vec::new(),
             placeholder_ctx_stack: Vec::new(),
             fn_ctx_stack: Vec::new(),
+            loop_stack: Vec::new(),
+            finally_loop_depth: None,
+            statement_if: None,
+            defer_stack: Vec::new(),
         })
     }
 
+    fn with_options(source: &'src str, options: &TranspileOptions) -> TfResult<Self> {
+        if let Some(salt) = &options.temp_var_salt {
+            // the salt ends up in the middle of identifiers like `_t_<salt>_l1c4`
+            if !salt.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(TfErrBuilder::default()
+                    .message(format!(
+                        "Invalid temp_var_salt {salt:?}: only ASCII letters, digits and '_' are allowed"
+                    ))
+                    .build_errs());
+            }
+        }
+
+        let mut ctx = TfCtx::new(source)?;
+        ctx.allow_top_level_await = options.allow_await;
+        ctx.strict = options.strict;
+        ctx.fold_literal_or_patterns = options.fold_literal_or_patterns;
+        ctx.temp_var_salt = options.temp_var_salt.clone();
+        ctx.allow_match = options.allow_match;
+        ctx.allow_async = options.allow_async;
+        ctx.posonly_self = options.posonly_self;
+        ctx.record_returns = options.record_returns;
+        ctx.echo_expressions = options.echo_expressions;
+        ctx.python_version = options.python_version;
+        Ok(ctx)
+    }
+
+    fn check_feature(&self, enabled: bool, feature: &str, span: &Span) -> TfResult<()> {
+        if enabled {
+            Ok(())
+        } else {
+            Err(TfErrBuilder::default()
+                .message(format!("{feature} is disabled by the transpile options"))
+                .span(*span)
+                .build_errs())
+        }
+    }
+
+    fn warn(&mut self, message: impl Into<String>, span: &Span) {
+        self.warnings
+            .0
+            .push(TfErrBuilder::default().message(message).span(*span).build());
+    }
+
     fn escape_ident<'s>(&self, s: &Cow<'s, str>) -> Cow<'s, str> {
         if self.py_kws.contains(s.as_ref()) {
             format!("{s}_").into()
@@ -108,7 +198,32 @@ impl<'src> TfCtx<'src> {
 
     fn temp_var_name(&self, typ: &str, cursor: usize) -> String {
         let (line, col) = self.linecol(cursor);
-        format!("_{}_l{}c{}", typ, line, col)
+        match &self.temp_var_salt {
+            Some(salt) => format!("_{}_{}_l{}c{}", typ, salt, line, col),
+            None => format!("_{}_l{}c{}", typ, line, col),
+        }
+    }
+
+    /// Transform the expression of an expression statement. An if-expression there is a plain
+    /// if statement, so its branches don't need a value.
+    fn transform_stmt_expr(&mut self, expr: &SExpr<'src>) -> TfResult<SPyExprWithPre<'src>> {
+        let outer = self.statement_if;
+        if let Expr::If(..) = expr.0 {
+            self.statement_if = Some(expr.1);
+        }
+
+        let result = expr.transform_with_placeholder_guard(self);
+        self.statement_if = outer;
+        result
+    }
+
+    /// Load a helper from the runtime's `__tl__` namespace, recording that the module uses it.
+    fn tl_builtin(&mut self, a: &PyAstBuilder, name: &'static str) -> SPyExpr<'src> {
+        if !self.runtime_helpers.contains(&name) {
+            self.runtime_helpers.push(name);
+        }
+
+        a.tl_builtin(name)
     }
 }
 
@@ -213,7 +328,7 @@ impl<'src> BlockExt<'src> for Vec<SStmt<'src>> {
         let mut value = PyBlockExpr::Nothing;
 
         match &final_stmt.0 {
-            Stmt::Expr(expr) => match expr.transform_with_placeholder_guard(ctx) {
+            Stmt::Expr(expr) => match ctx.transform_stmt_expr(expr) {
                 Ok(expr_with_aux) => {
                     value = PyBlockExpr::Expr(bind_pre(&mut pre, expr_with_aux));
                 }
@@ -222,7 +337,7 @@ impl<'src> BlockExt<'src> for Vec<SStmt<'src>> {
                     ok = false;
                 }
             },
-            Stmt::Raise(..) | Stmt::Return(..) | Stmt::Break | Stmt::Continue => {
+            Stmt::Raise(..) | Stmt::Return(..) | Stmt::Break(..) | Stmt::Continue(..) => {
                 handle_stmt(final_stmt);
                 value = PyBlockExpr::Never;
             }
@@ -245,6 +360,10 @@ fn destructure_list<'src, 'ast>(
     items: &'ast [ListItem<'src>],
     decl_only: bool,
 ) -> TfResult<DestructureBindings<'src>> {
+    if items.iter().any(|x| matches!(x, ListItem::Default(..))) {
+        return destructure_list_with_defaults(ctx, target, items, decl_only);
+    }
+
     let cursor_var = ctx.temp_var_name("des_curs", target.1.start);
 
     // a, b, *c = cursor_var
@@ -259,7 +378,7 @@ fn destructure_list<'src, 'ast>(
     for item in items.iter() {
         match item {
             ListItem::Item(expr) => {
-                let item_bindings = destructure(ctx, expr, decl_only)?;
+                let item_bindings = destructure_item(ctx, expr, decl_only)?;
                 lhs_items.push(PyListItem::Item(item_bindings.assign_to));
                 post_stmts.extend(item_bindings.post_stmts);
                 decls.extend(item_bindings.declarations);
@@ -273,11 +392,12 @@ fn destructure_list<'src, 'ast>(
                 }
                 seen_spread = true;
 
-                let item_bindings = destructure(ctx, expr, decl_only)?;
+                let item_bindings = destructure_item(ctx, expr, decl_only)?;
                 lhs_items.push(PyListItem::Spread(item_bindings.assign_to));
                 post_stmts.extend(item_bindings.post_stmts);
                 decls.extend(item_bindings.declarations);
             }
+            ListItem::Default(..) => unreachable!(),
         }
     }
 
@@ -296,6 +416,156 @@ fn destructure_list<'src, 'ast>(
     })
 }
 
+/**
+ * `[a, b = x, c = y, *d] = cursor_var` takes the required items first, then each defaulted item
+ * from what is left, evaluating its default only if the sequence is too short:
+ *
+ * [a, *rest_var] = cursor_var
+ * if len(rest_var) > 0: b = rest_var[0]
+ * else: b = x
+ * if len(rest_var) > 1: c = rest_var[1]
+ * else: c = y
+ * d = rest_var[2:]
+ *
+ * Without a trailing spread, leftover items raise a ValueError like plain unpacking does.
+ */
+fn destructure_list_with_defaults<'src, 'ast>(
+    ctx: &mut TfCtx<'src>,
+    target: &'ast SExpr<'src>,
+    items: &'ast [ListItem<'src>],
+    decl_only: bool,
+) -> TfResult<DestructureBindings<'src>> {
+    let cursor_var = ctx.temp_var_name("des_curs", target.1.start);
+    let rest_var = ctx.temp_var_name("des_rest", target.1.start);
+
+    let a = PyAstBuilder::new(target.1);
+
+    let n_required = items
+        .iter()
+        .take_while(|x| matches!(x, ListItem::Item(..)))
+        .count();
+    let (required, optional) = items.split_at(n_required);
+
+    let (defaults, spread) = match optional {
+        [defaults @ .., ListItem::Spread(spread)] => (defaults, Some(spread)),
+        defaults => (defaults, None),
+    };
+
+    let defaults = defaults
+        .iter()
+        .map(|item| match item {
+            ListItem::Default(target, default) => Ok((target, default)),
+            ListItem::Item(expr) | ListItem::Spread(expr) => Err(TfErrBuilder::default()
+                .message(
+                    "Items with default values must come after all other items, \
+                     except a final spread",
+                )
+                .span(expr.1)
+                .build_errs()),
+        })
+        .collect::<TfResult<Vec<_>>>()?;
+
+    let mut lhs_items = vec![];
+    let mut post_stmts = PyBlock::new();
+    let mut decls = vec![];
+
+    for item in required {
+        let ListItem::Item(expr) = item else {
+            unreachable!()
+        };
+        let item_bindings = destructure_item(ctx, expr, decl_only)?;
+        lhs_items.push(PyListItem::Item(item_bindings.assign_to));
+        post_stmts.extend(item_bindings.post_stmts);
+        decls.extend(item_bindings.declarations);
+    }
+    lhs_items.push(PyListItem::Spread(
+        a.ident(rest_var.clone(), PyAccessCtx::Store),
+    ));
+
+    let rest_len = || {
+        a.call(
+            a.load_ident("len"),
+            vec![a.call_arg(a.load_ident(rest_var.clone()))],
+        )
+    };
+
+    for (i, (item_target, default)) in defaults.iter().enumerate() {
+        let item_bindings = destructure_item(ctx, item_target, decl_only)?;
+        decls.extend(item_bindings.declarations);
+
+        let default = default.transform_with_placeholder_guard(ctx)?;
+        let mut else_block = default.pre;
+        else_block.push(a.assign(item_bindings.assign_to.clone(), default.value));
+
+        post_stmts.push(a.if_(
+            a.binary(PyBinaryOp::Gt, rest_len(), a.num(i.to_string())),
+            PyBlock(vec![a.assign(
+                item_bindings.assign_to,
+                a.subscript(
+                    a.load_ident(rest_var.clone()),
+                    a.num(i.to_string()),
+                    PyAccessCtx::Load,
+                ),
+            )]),
+            Some(else_block),
+        ));
+        post_stmts.extend(item_bindings.post_stmts);
+    }
+
+    match spread {
+        Some(spread) => {
+            let item_bindings = destructure_item(ctx, spread, decl_only)?;
+            decls.extend(item_bindings.declarations);
+            post_stmts.push(a.assign(
+                item_bindings.assign_to,
+                a.subscript(
+                    a.load_ident(rest_var.clone()),
+                    a.slice(Some(a.num(defaults.len().to_string())), None, None),
+                    PyAccessCtx::Load,
+                ),
+            ));
+            post_stmts.extend(item_bindings.post_stmts);
+        }
+        None => {
+            let max_len = n_required + defaults.len();
+            post_stmts.push(a.if_(
+                a.binary(
+                    PyBinaryOp::Gt,
+                    rest_len(),
+                    a.num(defaults.len().to_string()),
+                ),
+                PyBlock(vec![a.raise(Some(a.call(
+                    a.load_ident("ValueError"),
+                    vec![a.call_arg(a.str(format!(
+                        "too many values to unpack (expected at most {max_len})"
+                    )))],
+                )))]),
+                None,
+            ));
+        }
+    }
+
+    let mut stmts = PyBlock::new();
+    stmts.push(a.assign(
+        a.list(lhs_items, PyAccessCtx::Store),
+        a.load_ident(cursor_var.clone()),
+    ));
+    stmts.extend(post_stmts);
+
+    Ok(DestructureBindings {
+        post_stmts: stmts,
+        assign_to: a.ident(cursor_var, PyAccessCtx::Store),
+        declarations: decls,
+    })
+}
+
+fn misplaced_default<'src>(target: &SExpr<'src>) -> TfErrs {
+    TfErrBuilder::default()
+        .message("Default values are only allowed in a list or mapping being destructured")
+        .span(target.1)
+        .build_errs()
+}
+
 fn destructure_tuple<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     target: &'ast SExpr<'src>,
@@ -341,7 +611,7 @@ fn destructure_tuple<'src, 'ast>(
     for item in items.iter() {
         match item {
             ListItem::Item(expr) => {
-                let item_bindings = destructure(ctx, expr, decl_only)?;
+                let item_bindings = destructure_item(ctx, expr, decl_only)?;
                 post_stmts.extend(item_bindings.post_stmts);
                 decls.extend(item_bindings.declarations);
 
@@ -364,6 +634,7 @@ fn destructure_tuple<'src, 'ast>(
                 );
                 i += 1;
             }
+            ListItem::Default(target, _) => return Err(misplaced_default(target)),
             ListItem::Spread(expr) => {
                 if seen_spread {
                     return Err(TfErrBuilder::default()
@@ -373,7 +644,7 @@ fn destructure_tuple<'src, 'ast>(
                 }
                 seen_spread = true;
 
-                let item_bindings = destructure(ctx, expr, true)?;
+                let item_bindings = destructure_item(ctx, expr, true)?;
                 post_stmts.extend(item_bindings.post_stmts);
                 decls.extend(item_bindings.declarations);
 
@@ -420,7 +691,7 @@ fn destructure_mapping<'src, 'ast>(
     let mut stmts = PyBlock(vec![a.assign(
         a.ident(dict_var.clone(), PyAccessCtx::Store),
         a.call(
-            a.tl_builtin("unpack_record"),
+            ctx.tl_builtin(&a, "unpack_record"),
             vec![a.call_arg(a.load_ident(cursor_var.clone()))],
         ),
     )]);
@@ -443,7 +714,7 @@ fn destructure_mapping<'src, 'ast>(
                 ),
             )),
             MappingItem::Item(key, expr) => {
-                let item_bindings = destructure(ctx, expr, decl_only)?;
+                let item_bindings = destructure_item(ctx, expr, decl_only)?;
                 let key_node = key.transform(ctx)?;
                 post_stmts.extend(key_node.pre);
                 post_stmts.extend(item_bindings.post_stmts);
@@ -457,6 +728,23 @@ fn destructure_mapping<'src, 'ast>(
                     ),
                 ));
             }
+            MappingItem::Default(key, expr, default) => {
+                let item_bindings = destructure_item(ctx, expr, decl_only)?;
+                let key_node = key.transform(ctx)?;
+                let default_node = default.transform_with_placeholder_guard(ctx)?;
+                post_stmts.extend(key_node.pre);
+                post_stmts.extend(item_bindings.post_stmts);
+                decls.extend(item_bindings.declarations);
+
+                stmts.extend(default_node.pre);
+                stmts.push(a.assign(
+                    item_bindings.assign_to,
+                    a.call(
+                        a.attribute(a.load_ident(dict_var.clone()), "pop", PyAccessCtx::Load),
+                        vec![a.call_arg(key_node.value), a.call_arg(default_node.value)],
+                    ),
+                ));
+            }
             MappingItem::Spread(expr) => {
                 if spread_var.is_some() {
                     return Err(TfErrBuilder::default()
@@ -471,7 +759,7 @@ fn destructure_mapping<'src, 'ast>(
     }
 
     if let Some(spread_var) = spread_var {
-        let item_bindings = destructure(ctx, spread_var, true)?;
+        let item_bindings = destructure_item(ctx, spread_var, true)?;
 
         post_stmts.extend(item_bindings.post_stmts);
         decls.extend(item_bindings.declarations);
@@ -488,12 +776,81 @@ fn destructure_mapping<'src, 'ast>(
     })
 }
 
+fn transform_del_targets<'src>(
+    ctx: &mut TfCtx<'src>,
+    target: &SExpr<'src>,
+    stmts: &mut PyBlock<'src>,
+    targets: &mut Vec<SPyExpr<'src>>,
+) -> TfResult<()> {
+    match &target.0 {
+        Expr::Ident(..) | Expr::Attribute(..) | Expr::Subscript(..) => {
+            let t = target.transform_with_access(ctx, PyAccessCtx::Del)?;
+            stmts.extend(t.pre);
+            targets.push(t.value);
+        }
+        Expr::Tuple(items) | Expr::List(items) => {
+            for item in items {
+                match item {
+                    ListItem::Item(expr) => transform_del_targets(ctx, expr, stmts, targets)?,
+                    ListItem::Spread(expr) => {
+                        return Err(TfErrBuilder::default()
+                            .message("Cannot delete a spread")
+                            .span(expr.1)
+                            .build_errs());
+                    }
+                    ListItem::Default(target, _) => return Err(misplaced_default(target)),
+                }
+            }
+        }
+        _ => {
+            let message = match &target.0 {
+                Expr::Literal(..) | Expr::Fstr(..) => "Cannot delete a literal",
+                Expr::Call(..) | Expr::MappedCall(..) => "Cannot delete a call result",
+                _ => "Deletion target is not allowed",
+            };
+
+            return Err(TfErrBuilder::default()
+                .message(message)
+                .span(target.1)
+                .build_errs());
+        }
+    }
+
+    Ok(())
+}
+
 struct DestructureBindings<'a> {
     assign_to: SPyExpr<'a>,
     post_stmts: PyBlock<'a>,
     declarations: Vec<PyIdent<'a>>,
 }
 
+/**
+ * Destructures one item of a list, tuple or mapping target. An `_` item ignores its value:
+ * it's stored to a throwaway temporary instead of binding `_`.
+ */
+fn destructure_item<'src>(
+    ctx: &mut TfCtx<'src>,
+    target: &SExpr<'src>,
+    decl_only: bool,
+) -> TfResult<DestructureBindings<'src>> {
+    if let Expr::Ident((id, _)) = &target.0
+        && id == "_"
+    {
+        let a = PyAstBuilder::new(target.1);
+        return Ok(DestructureBindings {
+            post_stmts: PyBlock::new(),
+            assign_to: a.ident(
+                ctx.temp_var_name("des_ignore", target.1.start),
+                PyAccessCtx::Store,
+            ),
+            declarations: vec![],
+        });
+    }
+
+    destructure(ctx, target, decl_only)
+}
+
 fn destructure<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     target: &'ast SExpr<'src>,
@@ -502,56 +859,53 @@ fn destructure<'src, 'ast>(
     let mut post_stmts = PyBlock::new();
     let mut decls = Vec::<PyIdent<'src>>::new();
 
-    let assign_to: SPyExpr<'src>;
-
-    match &target.0 {
+    let assign_to: SPyExpr<'src> = match &target.0 {
         Expr::Ident(..) | Expr::Attribute(..) | Expr::Subscript(..) => {
-            match &target.0 {
-                Expr::Ident(id) => {
-                    decls.push(ctx.escape_ident(&id.0));
-                }
-                Expr::Attribute(..) | Expr::Subscript(..) => {
-                    if decl_only {
-                        return Err(TfErrBuilder::default()
-                            .message("Only identifiers allowed in this destructuring")
-                            .span(target.1)
-                            .build_errs());
-                    }
-                }
-                _ => {
-                    panic!();
-                }
+            if let Expr::Ident(id) = &target.0 {
+                decls.push(ctx.escape_ident(&id.0));
+            } else if decl_only {
+                return Err(TfErrBuilder::default()
+                    .message("Only identifiers allowed in this destructuring")
+                    .span(target.1)
+                    .build_errs());
             }
 
             let target_node = target.transform_with_access(ctx, PyAccessCtx::Store)?;
             post_stmts.extend(target_node.pre);
 
-            assign_to = target_node.value;
+            target_node.value
         }
         Expr::List(items) => {
             let bindings = destructure_list(ctx, target, items, decl_only)?;
 
             post_stmts.extend(bindings.post_stmts);
             decls.extend(bindings.declarations);
-            assign_to = bindings.assign_to;
+            bindings.assign_to
         }
         Expr::Tuple(items) => {
             let bindings = destructure_tuple(ctx, target, items, decl_only)?;
 
             post_stmts.extend(bindings.post_stmts);
             decls.extend(bindings.declarations);
-            assign_to = bindings.assign_to;
+            bindings.assign_to
         }
         Expr::Mapping(items) => {
             let bindings = destructure_mapping(ctx, target, items, decl_only)?;
 
             post_stmts.extend(bindings.post_stmts);
             decls.extend(bindings.declarations);
-            assign_to = bindings.assign_to;
+            bindings.assign_to
         }
         _ => {
+            let message = match &target.0 {
+                Expr::Literal(..) | Expr::Fstr(..) => "Cannot assign to a literal",
+                Expr::Call(..) | Expr::MappedCall(..) => "Cannot assign to a call result",
+                Expr::Slice(..) => "Cannot assign to a slice; use it as a subscript instead",
+                _ => "Assignment target is not allowed",
+            };
+
             return Err(TfErrBuilder::default()
-                .message("Assignment target is not allowed")
+                .message(message)
                 .span(target.1)
                 .build_errs());
         }
@@ -565,7 +919,7 @@ fn destructure<'src, 'ast>(
 }
 
 fn get_scope_modifier<'a>(
-    mods: &'a Vec<AssignModifier>,
+    mods: &'a [AssignModifier],
     is_top_level: bool,
     span: &Span,
 ) -> TfResult<Option<&'a AssignModifier>> {
@@ -586,7 +940,7 @@ fn get_scope_modifier<'a>(
             .build_errs());
     }
 
-    let scope_modifier = scope_modifier.first().map(|x| *x);
+    let scope_modifier = scope_modifier.first().copied();
 
     if !is_top_level && scope_modifier.is_some_and(|x| *x == AssignModifier::Export) {
         return Err(TfErrBuilder::default()
@@ -624,6 +978,115 @@ fn get_scope_modifying_statements<'a>(
     Ok(stmts)
 }
 
+/**
+ * Removes the global/nonlocal declarations from a function body, including those in nested
+ * suites but not in nested scopes, and collects their names in first-seen order.
+ */
+fn take_scope_declarations<'src>(
+    block: &mut PyBlock<'src>,
+    globals: &mut Vec<PyIdent<'src>>,
+    nonlocals: &mut Vec<PyIdent<'src>>,
+) {
+    let add = |names: &mut Vec<PyIdent<'src>>, decls: &[PyIdent<'src>]| {
+        for decl in decls {
+            if !names.contains(decl) {
+                names.push(decl.clone());
+            }
+        }
+    };
+
+    block.0.retain_mut(|stmt| {
+        match &mut stmt.value {
+            PyStmt::Global(decls) => {
+                add(globals, decls);
+                return false;
+            }
+            PyStmt::Nonlocal(decls) => {
+                add(nonlocals, decls);
+                return false;
+            }
+            PyStmt::If(_, body, orelse) => {
+                take_scope_declarations(body, globals, nonlocals);
+                if let Some(orelse) = orelse {
+                    take_scope_declarations(orelse, globals, nonlocals);
+                }
+            }
+            PyStmt::Match(_, cases) => {
+                for case in cases {
+                    take_scope_declarations(&mut case.body, globals, nonlocals);
+                }
+            }
+            PyStmt::While(_, body) | PyStmt::For(_, _, body) => {
+                take_scope_declarations(body, globals, nonlocals);
+            }
+            PyStmt::Try(body, handlers, finally) => {
+                take_scope_declarations(body, globals, nonlocals);
+                for handler in handlers {
+                    take_scope_declarations(&mut handler.body, globals, nonlocals);
+                }
+                if let Some(finally) = finally {
+                    take_scope_declarations(finally, globals, nonlocals);
+                }
+            }
+            _ => {}
+        }
+
+        true
+    });
+}
+
+fn finally_escape_message(kw: &str) -> String {
+    format!("'{kw}' in a finally block swallows any exception raised in the try block")
+}
+
+/**
+ * Finds a return in a finally block, which discards the exception being propagated. Nested
+ * functions are skipped.
+ */
+fn find_finally_return(block: &PyBlock) -> Option<Span> {
+    block.0.iter().find_map(|stmt| match &stmt.value {
+        PyStmt::Return(..) => Some(stmt.tl_span),
+        PyStmt::If(_, body, orelse) => {
+            find_finally_return(body).or_else(|| orelse.as_ref().and_then(find_finally_return))
+        }
+        PyStmt::Match(_, cases) => cases
+            .iter()
+            .find_map(|case| find_finally_return(&case.body)),
+        PyStmt::While(_, body) | PyStmt::For(_, _, body) => find_finally_return(body),
+        PyStmt::Try(body, handlers, finally) => find_finally_return(body)
+            .or_else(|| {
+                handlers
+                    .iter()
+                    .find_map(|handler| find_finally_return(&handler.body))
+            })
+            .or_else(|| finally.as_ref().and_then(find_finally_return)),
+        _ => None,
+    })
+}
+
+/**
+ * Python requires a global/nonlocal declaration to precede any use of the name in the
+ * function, so emit a single deduplicated declaration of each kind at the top of the body.
+ */
+fn hoist_scope_declarations<'src>(mut body: PyBlock<'src>, span: &Span) -> PyBlock<'src> {
+    let mut globals = vec![];
+    let mut nonlocals = vec![];
+    take_scope_declarations(&mut body, &mut globals, &mut nonlocals);
+
+    let a = PyAstBuilder::new(*span);
+    let mut stmts = PyBlock::new();
+
+    if !globals.is_empty() {
+        stmts.push(a.global(globals));
+    }
+    if !nonlocals.is_empty() {
+        stmts.push(a.nonlocal(nonlocals));
+    }
+
+    stmts.extend(body);
+    stmts
+}
+
 fn transform_assignment<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     lhs: &'ast SExpr<'src>,
@@ -632,6 +1095,8 @@ fn transform_assignment<'src, 'ast>(
     span: &Span,
 ) -> TfResult<(PyBlock<'src>, Vec<PyIdent<'src>>)> {
     let mut stmts = PyBlock::new();
+    // `name = deco(fn)`, `name = fn | deco` etc. become decorated defs; only a plain
+    // name can be the target of a def, so attribute and subscript targets are assigned.
     if let Expr::Ident(ident) = &lhs.0 {
         let py_ident = ctx.escape_ident(&ident.0);
 
@@ -679,14 +1144,19 @@ fn transform_assignment<'src, 'ast>(
                     .into_iter()
                     .map(|x| {
                         let t = x.transform_with_placeholder_guard(ctx)?;
-                        stmts.extend(t.pre);
+                        if !t.pre.is_empty() {
+                            return Err(TfErrBuilder::default()
+                                .message("Decorator expressions cannot contain statements; bind the decorator to a name first")
+                                .span(x.1)
+                                .build_errs());
+                        }
                         Ok(t.value)
                     })
                     .collect::<TfResult<_>>()?,
             ))
         };
 
-        if let Expr::Fn(arglist, body) = &cur_node {
+        if let Expr::Fn(arglist, body, type_params) = &cur_node {
             let decorators = py_decorators()?;
             return Ok((
                 make_fn_def(
@@ -694,15 +1164,24 @@ fn transform_assignment<'src, 'ast>(
                     py_ident.clone(),
                     FnDefArgs::ArgList(arglist),
                     FnDefBody::Expr(body),
+                    type_params,
                     decorators,
                     span,
                 )?,
                 vec![py_ident.clone()],
             ));
-        } else if let Expr::Class(bases, body) = &cur_node {
+        } else if let Expr::Class(bases, body, type_params) = &cur_node {
             let decorators = py_decorators()?;
             return Ok((
-                make_class_def(ctx, py_ident.clone(), &bases, &body, decorators, span)?,
+                make_class_def(
+                    ctx,
+                    py_ident.clone(),
+                    bases,
+                    body,
+                    type_params,
+                    decorators,
+                    span,
+                )?,
                 vec![py_ident.clone()],
             ));
         };
@@ -744,14 +1223,38 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
         let a = PyAstBuilder::new(*span);
 
         match &stmt {
+            Stmt::Expr((Expr::Block(block), _)) => {
+                // a block in statement position is inlined as a suite; its value is unused
+                let mut stmts = block.transform(ctx)?.drop_expr(ctx)?;
+                if stmts.0.is_empty() {
+                    stmts.push(a.pass());
+                }
+
+                Ok(stmts)
+            }
             Stmt::Expr(expr) => {
-                let expr = expr.transform_with_placeholder_guard(ctx)?;
+                let expr = ctx.transform_stmt_expr(expr)?;
                 let mut stmts = expr.pre;
-                stmts.push((PyStmt::Expr(expr.value), *span).into());
+                let value = if top_level && ctx.echo_expressions {
+                    echo_expr(expr.value)
+                } else {
+                    expr.value
+                };
+                stmts.push((PyStmt::Expr(value), *span).into());
 
                 Ok(stmts)
             }
             Stmt::Assert(expr, msg) => {
+                if let Expr::Tuple(items) = &expr.0
+                    && !items.is_empty()
+                {
+                    ctx.warn(
+                        "Assertion is always true because its condition is a non-empty tuple; \
+                         remove the parentheses to give it a message",
+                        &expr.1,
+                    );
+                }
+
                 let expr_node = expr.transform_with_placeholder_guard(ctx)?;
                 let msg = msg
                     .as_ref()
@@ -759,18 +1262,128 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
                     .transpose()?;
 
                 let mut stmts = expr_node.pre;
-                let mut msg_node = None;
-                if let Some(msg) = msg {
-                    stmts.extend(msg.pre);
-                    msg_node = Some(msg.value);
+                match msg {
+                    // the message needs statements of its own; only run them on failure,
+                    // like Python only evaluates the message of a failing assert
+                    Some(msg) if !msg.pre.is_empty() => {
+                        let a = PyAstBuilder::new(*span);
+                        let mut body = msg.pre;
+                        body.push(a.assert(a.bool(false), Some(msg.value)));
+                        stmts.push(a.if_(a.unary(PyUnaryOp::Not, expr_node.value), body, None));
+                    }
+                    msg => {
+                        let msg_node = msg.map(|msg| msg.value);
+                        stmts.push((PyStmt::Assert(expr_node.value, msg_node), *span).into());
+                    }
+                }
+
+                // anything beyond a bare assert would still run under `python -O`
+                if let [stmt] = &stmts.0[..]
+                    && matches!(stmt.value, PyStmt::Assert(..))
+                {
+                    return Ok(stmts);
+                }
+
+                let a = PyAstBuilder::new(*span);
+                Ok(PyBlock(vec![a.if_(a.load_ident("__debug__"), stmts, None)]))
+            }
+            Stmt::TypeAlias(name, value) => {
+                let value = value.transform_with_placeholder_guard(ctx)?;
+                let name = (
+                    PyExpr::Ident(ctx.escape_ident(&name.0), PyAccessCtx::Store),
+                    name.1,
+                )
+                    .into();
+
+                if ctx.python_version < (3, 12) {
+                    let mut stmts = value.pre;
+                    stmts.push((PyStmt::Assign(name, value.value), *span).into());
+                    return Ok(stmts);
+                }
+
+                // the value of a `type` statement is evaluated lazily, so it can't have statements
+                if !value.pre.is_empty() {
+                    return Err(TfErrBuilder::default()
+                        .message("Type alias values cannot contain statements")
+                        .span(*span)
+                        .build_errs());
                 }
 
-                stmts.push((PyStmt::Assert(expr_node.value, msg_node), *span).into());
+                Ok(PyBlock(vec![
+                    (PyStmt::TypeAlias(name, value.value), *span).into(),
+                ]))
+            }
+            Stmt::Annotate(target, annotation) => {
+                let annotation = annotation.transform_with_placeholder_guard(ctx)?;
+                let mut stmts = annotation.pre;
+
+                let target = (
+                    PyExpr::Ident(ctx.escape_ident(&target.0), PyAccessCtx::Store),
+                    target.1,
+                );
+                stmts.push((PyStmt::AnnAssign(target.into(), annotation.value), *span).into());
+
+                Ok(stmts)
+            }
+            Stmt::Del(target) => {
+                let mut stmts = PyBlock::new();
+                let mut targets = Vec::new();
+                transform_del_targets(ctx, target, &mut stmts, &mut targets)?;
+
+                stmts.push((PyStmt::Del(targets), *span).into());
+
+                Ok(stmts)
+            }
+            Stmt::Defer(expr) => {
+                let Some(scope) = ctx.defer_stack.last() else {
+                    return Err(TfErrBuilder::default()
+                        .message("Defer is only allowed inside a function")
+                        .span(*span)
+                        .build_errs());
+                };
+
+                let var = match scope {
+                    Some(var) => var.clone(),
+                    None => {
+                        let var = ctx.temp_var_name("defer", span.start);
+                        *ctx.defer_stack.last_mut().unwrap() = Some(var.clone());
+                        var
+                    }
+                };
+
+                let a = PyAstBuilder::new(*span);
+                let deferred = match &expr.0 {
+                    Expr::Call(..) => {
+                        let call = expr.transform_with_placeholder_guard(ctx)?;
+                        SPyExprWithPre {
+                            value: bind_deferred_call(ctx, call.value, span),
+                            pre: call.pre,
+                        }
+                    }
+                    _ => make_fn_exp(
+                        ctx,
+                        FnDefArgs::PyArgList(vec![]),
+                        FnDefBody::Expr(expr),
+                        &[],
+                        span,
+                    )?,
+                };
+
+                let mut stmts = deferred.pre;
+                stmts.push(a.expr(a.call(
+                    a.attribute(a.load_ident(var), "append", PyAccessCtx::Load),
+                    vec![a.call_arg(deferred.value)],
+                )));
 
                 Ok(stmts)
             }
             Stmt::Return(expr) => {
-                let value = expr.transform_with_placeholder_guard(ctx)?;
+                let value = match record_return_fields(ctx, expr) {
+                    Some(fields) => {
+                        (Expr::Mapping(fields), expr.1).transform_with_placeholder_guard(ctx)?
+                    }
+                    None => expr.transform_with_placeholder_guard(ctx)?,
+                };
                 let mut stmts = value.pre;
 
                 stmts.push((PyStmt::Return(value.value), *span).into());
@@ -810,79 +1423,68 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
 
                 Ok(stmts)
             }
-            Stmt::For(target, iter, body) => {
+            Stmt::For(label, target, iter, body) => {
                 let mut pre = PyBlock::new();
-                let iter_node = bind_pre(&mut pre, iter.transform_with_placeholder_guard(ctx)?);
-
-                let mut body_block = PyBlock::new();
-
-                let (matcher, cursor) = create_throwing_matcher(ctx, target)?;
-                body_block.extend(matcher);
-                body_block.extend(body.transform(ctx)?.drop_expr(ctx)?);
-
-                pre.push(a.for_(
-                    a.ident(cursor.clone(), PyAccessCtx::Store),
-                    a.call(
-                        a.tl_builtin("vget"),
+                let iter_node = match &iter.0 {
+                    // `for i in a..b` iterates a range directly rather than a slice object
+                    Expr::Slice(Some(start), Some(stop), step) => {
+                        let mut args = vec![];
+                        for bound in [Some(start), Some(stop), step.as_ref()]
+                            .into_iter()
+                            .flatten()
+                        {
+                            let bound = bound.as_ref().transform_with_placeholder_guard(ctx)?;
+                            args.push(a.call_arg(bind_pre(&mut pre, bound)));
+                        }
+                        a.call(a.load_ident("range"), args)
+                    }
+                    _ => a.call(
+                        ctx.tl_builtin(&a, "vget"),
                         vec![
-                            a.call_arg(iter_node),
+                            a.call_arg(bind_pre(
+                                &mut pre,
+                                iter.transform_with_placeholder_guard(ctx)?,
+                            )),
                             a.call_arg(a.literal(PyLiteral::Str("iter".into()))),
                         ],
                     ),
-                    body_block,
-                ));
-
-                Ok(pre)
-            }
-            Stmt::While(cond, body) => {
-                let cond_node = cond.transform_with_placeholder_guard(ctx)?;
-
-                ctx.fn_ctx_stack.push(FnCtx::new());
-                let body_block = body.transform(ctx)?.drop_expr(ctx)?;
-                let fn_ctx = ctx.fn_ctx_stack.pop().unwrap();
-
-                let mut stmts = PyBlock::new();
-
-                let cond: SPyExpr<'src> = if cond_node.pre.is_empty() {
-                    cond_node.value
-                } else {
-                    if fn_ctx.is_async {
-                        // TODO revisit this!
-                        return Err(TfErrBuilder::default()
-                            .message("Await is not allowed in this complex loop condition")
-                            .span(*span)
-                            .build_errs());
-                    }
-
-                    if fn_ctx.is_do {
-                        return Err(TfErrBuilder::default()
-                            .message("Binding is not allowed in this complex loop condition")
-                            .span(*span)
-                            .build_errs());
-                    }
+                };
 
-                    let aux_fn = make_fn_exp(
-                        ctx,
-                        FnDefArgs::PyArgList(vec![]),
-                        FnDefBody::PyStmts(cond_node.pre, false, false),
-                        span,
-                    )?;
+                let mut body_block = PyBlock::new();
 
-                    stmts.extend(aux_fn.pre);
+                let (matcher, cursor) = create_throwing_matcher(ctx, target)?;
+                body_block.extend(matcher);
 
-                    (PyExpr::Call(Box::new(aux_fn.value), vec![]), *span).into()
-                };
+                ctx.push_loop(label, span);
+                let body = body.transform(ctx).and_then(|x| x.drop_expr(ctx));
+                let (init, checks) = ctx.pop_loop(span);
+                body_block.extend(body?);
 
-                stmts.push((PyStmt::While(cond, body_block), *span).into());
+                pre.extend(init);
+                pre.push(a.for_(
+                    PyAstBuilder::new(target.1).ident(cursor.clone(), PyAccessCtx::Store),
+                    iter_node,
+                    body_block,
+                ));
+                pre.extend(checks);
 
-                Ok(stmts)
+                Ok(pre)
             }
+            Stmt::While(label, cond, body) => transform_while(ctx, label, cond, body, None, span),
             Stmt::Try(body, excepts, finally) => {
                 let body_block = body.transform(ctx)?.drop_expr(ctx)?;
+                let outer_finally = ctx.finally_loop_depth.replace(ctx.loop_stack.len());
                 let finally_block = finally
                     .as_ref()
                     .map(|f| f.transform(ctx)?.drop_expr(ctx))
-                    .transpose()?;
+                    .transpose();
+                ctx.finally_loop_depth = outer_finally;
+                let finally_block = finally_block?;
+
+                // break and continue are checked against their target loop in `loop_jump`
+                if let Some(return_span) = finally_block.as_ref().and_then(find_finally_return) {
+                    ctx.warn(finally_escape_message("return"), &return_span);
+                }
 
                 let mut stmts = PyBlock::new();
                 let var_name = ctx.temp_var_name("e", span.start);
@@ -895,8 +1497,9 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
 
                 Ok(stmts)
             }
-            Stmt::Break => Ok(PyBlock(vec![(PyStmt::Break, *span).into()])),
-            Stmt::Continue => Ok(PyBlock(vec![(PyStmt::Continue, *span).into()])),
+            Stmt::Break(label) => ctx.loop_jump(label.as_ref(), true, span),
+            Stmt::Continue(label) => ctx.loop_jump(label.as_ref(), false, span),
+            Stmt::Pass => Ok(PyBlock(vec![a.pass()])),
             Stmt::Import(import_stmt) => {
                 let mut aliases = vec![];
 
@@ -909,13 +1512,11 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
 
                 let full_module = ".".repeat(import_stmt.level) + &base_module;
 
-                if import_stmt.reexport {
-                    if !top_level {
-                        return Err(TfErrBuilder::default()
-                            .message("Re-exporting imports is only allowed at the top level")
-                            .span(*span)
-                            .build_errs());
-                    }
+                if import_stmt.reexport && !top_level {
+                    return Err(TfErrBuilder::default()
+                        .message("Re-exporting imports is only allowed at the top level")
+                        .span(*span)
+                        .build_errs());
                 }
 
                 match &import_stmt.imports {
@@ -941,7 +1542,7 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
                             // alias else orig_name
                             let export_aliases: Vec<_> = aliases
                                 .iter()
-                                .map(|x| ctx.escape_ident(&x.as_name.as_ref().unwrap_or(&x.name)))
+                                .map(|x| ctx.escape_ident(x.as_name.as_ref().unwrap_or(&x.name)))
                                 .collect();
 
                             ctx.exports.extend(export_aliases);
@@ -980,6 +1581,120 @@ impl<'src> SStmtExt<'src> for SStmt<'src> {
     }
 }
 
+/**
+ * Lowers a while loop. With `ret_varname`, the value of the body is assigned to it on every
+ * iteration, so that a while-expression evaluates to the value of its last iteration.
+ */
+fn transform_while<'src, 'ast>(
+    ctx: &mut TfCtx<'src>,
+    label: &'ast Option<SIdent<'src>>,
+    cond: &'ast SExpr<'src>,
+    body: &'ast SExpr<'src>,
+    ret_varname: Option<&str>,
+    span: &Span,
+) -> TfResult<PyBlock<'src>> {
+    let cond_node = cond.transform_with_placeholder_guard(ctx)?;
+
+    ctx.fn_ctx_stack.push(FnCtx::new());
+    ctx.push_loop(label, span);
+    let body_block = body.transform(ctx).and_then(|x| match ret_varname {
+        Some(ret_varname) => {
+            let a = PyAstBuilder::new(*span);
+            let mut block = x.pre;
+            block.push(a.assign(
+                a.ident(ret_varname.to_string(), PyAccessCtx::Store),
+                x.value,
+            ));
+            Ok(block)
+        }
+        None => x.drop_expr(ctx),
+    });
+    let (init, checks) = ctx.pop_loop(span);
+    let body_block = body_block?;
+    let fn_ctx = ctx.fn_ctx_stack.pop().unwrap();
+
+    let mut stmts = init;
+
+    let cond: SPyExpr<'src> = if cond_node.pre.is_empty() {
+        cond_node.value
+    } else {
+        if fn_ctx.is_async {
+            // TODO revisit this!
+            return Err(TfErrBuilder::default()
+                .message("Await is not allowed in this complex loop condition")
+                .span(*span)
+                .build_errs());
+        }
+
+        if fn_ctx.is_do {
+            return Err(TfErrBuilder::default()
+                .message("Binding is not allowed in this complex loop condition")
+                .span(*span)
+                .build_errs());
+        }
+
+        let aux_fn = make_fn_exp(
+            ctx,
+            FnDefArgs::PyArgList(vec![]),
+            FnDefBody::PyStmts(cond_node.pre, false, false),
+            &[],
+            span,
+        )?;
+
+        stmts.extend(aux_fn.pre);
+
+        (PyExpr::Call(Box::new(aux_fn.value), vec![]), *span).into()
+    };
+
+    stmts.push((PyStmt::While(cond, body_block), *span).into());
+    stmts.extend(checks);
+
+    Ok(stmts)
+}
+
+/**
+ * An if-expression whose value is used must end each branch in an expression; point at the
+ * final statement of a branch that doesn't. An explicit `pass` opts out.
+ */
+fn check_if_branch_values<'src>(value: &SExpr<'src>) -> TfResult<()> {
+    let Expr::If(_, then_block, else_block) = &value.0 else {
+        return Ok(());
+    };
+
+    for branch in [Some(then_block), else_block.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        match &branch.0 {
+            Expr::If(..) => check_if_branch_values(branch)?,
+            Expr::Block(stmts) => match stmts.last() {
+                Some((Stmt::Expr(inner), _)) => check_if_branch_values(inner)?,
+                Some((
+                    Stmt::Raise(..)
+                    | Stmt::Return(..)
+                    | Stmt::Break(..)
+                    | Stmt::Continue(..)
+                    | Stmt::Pass,
+                    _,
+                ))
+                | None => {}
+                Some((_, span)) => {
+                    return Err(TfErrBuilder::default()
+                        .message(
+                            "This branch of the if-expression ends in a statement, so it has no \
+                             value; add a final expression to give it one",
+                        )
+                        .span(*span)
+                        .build_errs());
+                }
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 fn transform_if_expr<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     cond: &'ast SExpr<'src>,
@@ -987,18 +1702,39 @@ fn transform_if_expr<'src, 'ast>(
     else_block: Option<&'ast SExpr<'src>>,
     span: &Span,
 ) -> TfResult<SPyExprWithPre<'src>> {
+    let ret_varname = ctx.temp_var_name("ifexp", span.start);
+    let load_ret_var: SPyExpr = (
+        PyExpr::Ident(ret_varname.clone().into(), PyAccessCtx::Load),
+        *span,
+    )
+        .into();
+
+    let aux_stmts = transform_if_chain(ctx, cond, then_block, else_block, &ret_varname, span)?;
+
+    Ok(SPyExprWithPre {
+        value: load_ret_var,
+        pre: aux_stmts,
+    })
+}
+
+/**
+ * Assigns the value of an if-expression to `ret_varname`. An if-expression in the else branch
+ * assigns to the same variable, so that `else if` chains become a flat if/elif/else.
+ */
+fn transform_if_chain<'src, 'ast>(
+    ctx: &mut TfCtx<'src>,
+    cond: &'ast SExpr<'src>,
+    then_block: &'ast SExpr<'src>,
+    else_block: Option<&'ast SExpr<'src>>,
+    ret_varname: &str,
+    span: &Span,
+) -> TfResult<PyBlock<'src>> {
     let cond = cond.transform(ctx)?;
     let mut aux_stmts = cond.pre;
     let a = PyAstBuilder::new(*span);
 
-    let ret_varname = ctx.temp_var_name("ifexp", span.start);
     let store_ret_var: SPyExpr = (
-        PyExpr::Ident(ret_varname.clone().into(), PyAccessCtx::Store),
-        *span,
-    )
-        .into();
-    let load_ret_var: SPyExpr = (
-        PyExpr::Ident(ret_varname.clone().into(), PyAccessCtx::Load),
+        PyExpr::Ident(ret_varname.to_string().into(), PyAccessCtx::Store),
         *span,
     )
         .into();
@@ -1007,35 +1743,65 @@ fn transform_if_expr<'src, 'ast>(
     let mut then_block_ast = t.pre;
     then_block_ast.push(a.assign(store_ret_var.clone(), t.value));
 
-    let mut else_block = else_block
-        .as_ref()
-        .map(|else_block| {
+    let else_block = match else_block {
+        Some((Expr::If(cond, then_block, else_block), else_span)) => transform_if_chain(
+            ctx,
+            cond,
+            then_block,
+            else_block.as_deref(),
+            ret_varname,
+            else_span,
+        )?,
+        Some(else_block) => {
             let t = else_block.transform(ctx)?;
             let mut else_block_ast = t.pre;
-            else_block_ast.push(a.assign(store_ret_var.clone(), t.value));
-            Ok(else_block_ast)
-        })
-        .transpose()?;
-
-    if else_block.is_none() {
-        else_block = Some(PyBlock(vec![
-            a.assign(store_ret_var.clone(), a.literal(PyLiteral::None)),
-        ]));
-    }
+            else_block_ast.push(a.assign(store_ret_var, t.value));
+            else_block_ast
+        }
+        None => PyBlock(vec![a.assign(store_ret_var, a.literal(PyLiteral::None))]),
+    };
 
-    aux_stmts.push((PyStmt::If(cond.value, then_block_ast, else_block), *span).into());
+    aux_stmts.push(
+        (
+            PyStmt::If(cond.value, then_block_ast, Some(else_block)),
+            *span,
+        )
+            .into(),
+    );
 
-    Ok(SPyExprWithPre {
-        value: load_ret_var,
-        pre: aux_stmts,
-    })
+    Ok(aux_stmts)
 }
 
-fn bind_pre<'src, 'a, T>(pre: &'a mut PyBlock<'src>, v: WithPre<'src, T>) -> T {
+fn bind_pre<'src, T>(pre: &mut PyBlock<'src>, v: WithPre<'src, T>) -> T {
     pre.extend(v.pre);
     v.value
 }
 
+fn pattern_binds(pattern: &PyPattern, name: &str) -> bool {
+    match pattern {
+        PyPattern::Value(..) | PyPattern::Singleton(..) => false,
+        PyPattern::As(inner, ident) => {
+            ident.as_deref() == Some(name)
+                || inner
+                    .as_ref()
+                    .is_some_and(|x| pattern_binds(&x.value, name))
+        }
+        PyPattern::Or(items) => items.iter().any(|x| pattern_binds(&x.value, name)),
+        PyPattern::Sequence(items) => items.iter().any(|x| match x {
+            PyPatternSequenceItem::Item(x) => pattern_binds(&x.value, name),
+            PyPatternSequenceItem::Spread(ident) => ident.as_deref() == Some(name),
+        }),
+        PyPattern::Mapping(kvps, spread) => {
+            spread.as_deref() == Some(name)
+                || kvps.iter().any(|(_, x)| pattern_binds(&x.value, name))
+        }
+        PyPattern::Class(_, items, kvps) => {
+            items.iter().any(|x| pattern_binds(&x.value, name))
+                || kvps.iter().any(|(_, x)| pattern_binds(&x.value, name))
+        }
+    }
+}
+
 trait SPatternExt<'src> {
     fn transform<'ast>(
         &'ast self,
@@ -1061,12 +1827,24 @@ impl<'src> SPatternExt<'src> for SPattern<'src> {
         };
 
         let transformed = match pattern {
-            Pattern::As(pattern, ident) => PyPattern::As(
-                Some(Box::new(attach(pattern.transform(ctx)?))),
-                Some(ctx.escape_ident(&ident.0).clone()),
-            ),
+            Pattern::As(pattern, ident) => {
+                let inner = attach(pattern.transform(ctx)?);
+                let name = ctx.escape_ident(&ident.0);
+
+                if pattern_binds(&inner.value, &name) {
+                    return Err(TfErrBuilder::default()
+                        .message(format!(
+                            "Name '{}' is bound more than once in pattern",
+                            ident.0
+                        ))
+                        .span(ident.1)
+                        .build_errs());
+                }
+
+                PyPattern::As(Some(Box::new(inner)), Some(name))
+            }
             Pattern::Literal(literal) => match literal.0 {
-                Literal::Num(..) | Literal::Str(..) => {
+                Literal::Num(..) | Literal::Str(..) | Literal::Bytes(..) => {
                     PyPattern::Value((PyExpr::Literal(literal.0.transform(ctx)?), *span).into())
                 }
                 Literal::Bool(..) | Literal::None => {
@@ -1084,7 +1862,10 @@ impl<'src> SPatternExt<'src> for SPattern<'src> {
                         let a = PyAstBuilder::new(*span);
                         pre.push(a.assign(
                             a.ident(var.clone(), PyAccessCtx::Store),
-                            a.call(a.load_ident("Record"), vec![a.call_kwarg("value", v_node)]),
+                            a.call(
+                                ctx.tl_builtin(&a, "Record"),
+                                vec![a.call_kwarg("value", v_node)],
+                            ),
                         ));
 
                         PyPattern::Value(a.attribute(a.load_ident(var), "value", PyAccessCtx::Load))
@@ -1196,9 +1977,9 @@ impl<'src> SPatternExt<'src> for SPattern<'src> {
     }
 }
 
-fn create_throwing_matcher<'src, 'ast>(
+fn create_throwing_matcher<'src>(
     ctx: &mut TfCtx<'src>,
-    pattern: &'ast SPattern<'src>,
+    pattern: &SPattern<'src>,
 ) -> TfResult<(PyBlock<'src>, PyIdent<'src>)> {
     if let Pattern::Capture(Some(id)) = &pattern.0 {
         return Ok((PyBlock::new(), ctx.escape_ident(&id.0)));
@@ -1209,7 +1990,7 @@ fn create_throwing_matcher<'src, 'ast>(
     let a = PyAstBuilder::new(pattern.1);
     let success = PyBlock(vec![a.pass()]);
     let fail = PyBlock(vec![a.raise(Some(a.call(
-        a.load_ident("MatchError"),
+        ctx.tl_builtin(&a, "MatchError"),
         vec![a.call_arg(a.fstr(vec![
             a.fstr_str("failed to match value "),
             a.fstr_expr(a.load_ident(cursor.clone()), None),
@@ -1226,10 +2007,10 @@ fn create_throwing_matcher<'src, 'ast>(
     ))
 }
 
-fn create_matcher<'src, 'ast>(
+fn create_matcher<'src>(
     ctx: &mut TfCtx<'src>,
     subject: SPyExpr<'src>,
-    pattern: &'ast SPattern<'src>,
+    pattern: &SPattern<'src>,
     on_success: PyBlock<'src>,
     on_fail: PyBlock<'src>,
 ) -> TfResult<PyBlock<'src>> {
@@ -1262,6 +2043,34 @@ fn create_matcher<'src, 'ast>(
     Ok(post)
 }
 
+/**
+ * If every alternative of an or-pattern is a number or string literal, returns the literal values.
+ */
+fn literal_or_pattern_values<'src>(
+    ctx: &mut TfCtx<'src>,
+    pattern: &SPattern<'src>,
+) -> TfResult<Option<Vec<PyListItem<'src>>>> {
+    let Pattern::Or(items) = &pattern.0 else {
+        return Ok(None);
+    };
+
+    let mut values = vec![];
+    for item in items {
+        match &item.0 {
+            Pattern::Literal(
+                literal @ (Literal::Num(..) | Literal::Str(..) | Literal::Bytes(..), _),
+            ) => {
+                values.push(PyListItem::Item(
+                    (PyExpr::Literal(literal.0.transform(ctx)?), item.1).into(),
+                ));
+            }
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(Some(values))
+}
+
 fn transform_match_expr<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     subject: &'ast SExpr<'src>,
@@ -1301,19 +2110,44 @@ fn transform_match_expr<'src, 'ast>(
 
         // TODO verify binding same names
 
-        let pattern = if let Some(pattern) = &case.pattern {
-            bind_pre(&mut pre, pattern.transform(ctx)?)
-        } else {
-            (PyPattern::As(None, None), *span).into()
+        // `case 1 | 2 | ...` becomes `case x if x in (1, 2, ...)`;
+        // a tuple rather than a set keeps == semantics for unhashable subjects
+        let folded = match (&case.pattern, &case.guard) {
+            (Some(pattern), None) if ctx.fold_literal_or_patterns => {
+                literal_or_pattern_values(ctx, pattern)?.map(|values| (pattern.1, values))
+            }
+            _ => None,
         };
 
-        let guard = if let Some(guard) = &case.guard {
-            Some(bind_pre(
-                &mut pre,
-                guard.transform_with_placeholder_guard(ctx)?,
-            ))
+        let (pattern, guard) = if let Some((pattern_span, values)) = folded {
+            let a = PyAstBuilder::new(pattern_span);
+            let var_name = ctx.temp_var_name("mlit", pattern_span.start);
+
+            (
+                (
+                    PyPattern::As(None, Some(var_name.clone().into())),
+                    pattern_span,
+                )
+                    .into(),
+                Some(a.in_(a.load_ident(var_name), a.tuple(values, PyAccessCtx::Load))),
+            )
         } else {
-            None
+            let pattern = if let Some(pattern) = &case.pattern {
+                bind_pre(&mut pre, pattern.transform(ctx)?)
+            } else {
+                (PyPattern::As(None, None), *span).into()
+            };
+
+            let guard = if let Some(guard) = &case.guard {
+                Some(bind_pre(
+                    &mut pre,
+                    guard.transform_with_placeholder_guard(ctx)?,
+                ))
+            } else {
+                None
+            };
+
+            (pattern, guard)
         };
 
         let py_block = case.body.transform(ctx)?;
@@ -1351,17 +2185,41 @@ fn transform_match_expr<'src, 'ast>(
     ))
 }
 
+fn transform_type_params<'src>(
+    ctx: &TfCtx<'src>,
+    type_params: &[SIdent<'src>],
+    kind: &str,
+    span: &Span,
+) -> TfResult<Vec<PyIdent<'src>>> {
+    if !type_params.is_empty() && ctx.python_version < (3, 12) {
+        return Err(TfErrBuilder::default()
+            .message(format!(
+                "{kind} type parameters require Python 3.12 or later"
+            ))
+            .span(*span)
+            .build_errs());
+    }
+
+    Ok(type_params
+        .iter()
+        .map(|param| ctx.escape_ident(&param.0))
+        .collect())
+}
+
 fn make_class_def<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     name: Cow<'src, str>,
     bases: &'ast Vec<SCallItem<'src>>,
     body: &'ast SExpr<'src>,
+    type_params: &'ast [SIdent<'src>],
     decorators: PyDecorators<'src>,
     span: &Span,
 ) -> TfResult<PyBlock<'src>> {
     let mut stmts = PyBlock::new();
     let mut bases_nodes: Vec<PyCallItem<'src>> = vec![];
 
+    let type_params = transform_type_params(ctx, type_params, "Class", span)?;
+
     let mut block = body.transform(ctx)?.drop_expr(ctx)?;
 
     for base in bases {
@@ -1376,24 +2234,44 @@ fn make_class_def<'src, 'ast>(
                 stmts.extend(expr_node.pre);
                 PyCallItem::Kwarg(ctx.escape_ident(&name.0), expr_node.value)
             }
-            _ => {
-                return Err(TfErrBuilder::default()
-                    .message("spread args are not allowed in class bases")
-                    .span(*span)
-                    .build_errs());
+            CallItem::ArgSpread(expr) => {
+                let expr_node = expr.transform_with_placeholder_guard(ctx)?;
+                stmts.extend(expr_node.pre);
+                PyCallItem::ArgSpread(expr_node.value)
+            }
+            CallItem::KwargSpread(expr) => {
+                let expr_node = expr.transform_with_placeholder_guard(ctx)?;
+                stmts.extend(expr_node.pre);
+                PyCallItem::KwargSpread(expr_node.value)
             }
         };
 
         bases_nodes.push(call_item);
     }
 
+    if ctx.posonly_self {
+        for stmt in &mut block.0 {
+            if let PyStmt::FnDef(_, args, _, decorators, _) = &mut stmt.value
+                && let Some(self_arg) = args.first()
+                && matches!(self_arg.value, PyArgDefItem::Arg(..))
+                && !decorators
+                    .0
+                    .iter()
+                    .any(|d| matches!(&d.value, PyExpr::Ident(name, _) if name == "staticmethod"))
+            {
+                let span = self_arg.tl_span;
+                args.insert(1, (PyArgDefItem::PosOnlyMarker, span).into());
+            }
+        }
+    }
+
     if block.is_empty() {
         block.push((PyStmt::Pass, *span).into());
     }
 
     stmts.push(
         (
-            PyStmt::ClassDef(name, bases_nodes, block, decorators),
+            PyStmt::ClassDef(name, bases_nodes, block, decorators, type_params),
             *span,
         )
             .into(),
@@ -1410,24 +2288,209 @@ enum FnDefBody<'src, 'ast> {
 
 enum FnDefArgs<'src, 'ast> {
     ArgList(&'ast [ArgDefItem<'src>]),
-    PyArgList(Vec<PyArgDefItem<'src>>),
+    PyArgList(Vec<SPyArgDefItem<'src>>),
+}
+
+/**
+ * Finds the first load of any of `names` in `expr`, skipping lambdas that shadow the name.
+ */
+fn find_ident_load<'src>(
+    expr: &SPyExpr<'src>,
+    names: &[Cow<'src, str>],
+) -> Option<(Cow<'src, str>, Span)> {
+    let find = |e: &SPyExpr<'src>| find_ident_load(e, names);
+
+    match &expr.value {
+        PyExpr::Ident(ident, PyAccessCtx::Load) => {
+            if names.contains(ident) {
+                Some((ident.clone(), expr.tl_span))
+            } else {
+                None
+            }
+        }
+        PyExpr::Literal(..) | PyExpr::Ident(..) => None,
+        PyExpr::Fstr(parts) => parts.iter().find_map(|part| match part {
+            PyFstrPart::Str(..) => None,
+            PyFstrPart::Expr(e, _) => find(e),
+        }),
+        PyExpr::Binary(_, lhs, rhs) => find(lhs).or_else(|| find(rhs)),
+        PyExpr::Unary(_, e)
+        | PyExpr::Attribute(e, _, _)
+        | PyExpr::Await(e)
+        | PyExpr::Yield(e)
+        | PyExpr::YieldFrom(e) => find(e),
+        PyExpr::Subscript(e, index, _) => find(e).or_else(|| find(index)),
+        PyExpr::Call(e, items) => find(e).or_else(|| {
+            items.iter().find_map(|item| match item {
+                PyCallItem::Arg(e)
+                | PyCallItem::Kwarg(_, e)
+                | PyCallItem::ArgSpread(e)
+                | PyCallItem::KwargSpread(e) => find(e),
+            })
+        }),
+        PyExpr::IfExpr(cond, then, else_) => {
+            find(cond).or_else(|| find(then)).or_else(|| find(else_))
+        }
+        PyExpr::Lambda(args, body) => {
+            let mut shadowed = names.to_vec();
+            for arg in args {
+                let name = match &arg.value {
+                    PyArgDefItem::Arg(name, default) => {
+                        if let Some(found) = default.as_ref().and_then(find) {
+                            return Some(found);
+                        }
+                        name
+                    }
+                    PyArgDefItem::ArgSpread(name) | PyArgDefItem::KwargSpread(name) => name,
+                    PyArgDefItem::PosOnlyMarker => continue,
+                };
+                shadowed.retain(|x| x != name);
+            }
+
+            find_ident_load(body, &shadowed)
+        }
+        PyExpr::List(items, _) | PyExpr::Tuple(items, _) => {
+            items.iter().find_map(|item| match item {
+                PyListItem::Item(e) | PyListItem::Spread(e) => find(e),
+            })
+        }
+        PyExpr::Dict(items) => items.iter().find_map(|item| match item {
+            PyDictItem::Item(k, v) => find(k).or_else(|| find(v)),
+            PyDictItem::Spread(e) => find(e),
+        }),
+        PyExpr::Slice(start, stop, step) => [start, stop, step]
+            .into_iter()
+            .flatten()
+            .find_map(|e| find(e)),
+    }
+}
+
+/// Finds the first load of any of `names` in a block, like `find_ident_load` does for an
+/// expression. Functions defined in the block shadow their parameters.
+struct IdentLoadFinder<'src> {
+    names: Vec<Cow<'src, str>>,
+    found: Option<(Cow<'src, str>, Span)>,
+}
+
+impl<'src> IdentLoadFinder<'src> {
+    fn find(
+        block: &mut PyBlock<'src>,
+        names: Vec<Cow<'src, str>>,
+    ) -> Option<(Cow<'src, str>, Span)> {
+        let mut finder = IdentLoadFinder { names, found: None };
+        finder.visit_block(block);
+        finder.found
+    }
+}
+
+impl<'src> PyVisitorMut<'src> for IdentLoadFinder<'src> {
+    fn visit_stmt(&mut self, stmt: &mut SPyStmt<'src>) {
+        if self.found.is_some() {
+            return;
+        }
+
+        let PyStmt::FnDef(_, args, body, decorators, _) = &mut stmt.value else {
+            return walk_stmt(self, stmt);
+        };
+
+        for decorator in &mut decorators.0 {
+            self.visit_expr(decorator);
+        }
+
+        let mut shadowed = self.names.clone();
+        for arg in args.iter_mut() {
+            match &mut arg.value {
+                PyArgDefItem::Arg(name, default) => {
+                    if let Some(default) = default {
+                        self.visit_expr(default);
+                    }
+                    shadowed.retain(|x| x != name);
+                }
+                PyArgDefItem::ArgSpread(name) | PyArgDefItem::KwargSpread(name) => {
+                    shadowed.retain(|x| x != name);
+                }
+                PyArgDefItem::PosOnlyMarker => {}
+            }
+        }
+
+        if self.found.is_none() {
+            self.found = IdentLoadFinder::find(body, shadowed);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut SPyExpr<'src>) {
+        if self.found.is_none() {
+            self.found = find_ident_load(expr, &self.names);
+        }
+    }
 }
 
 fn make_arglist<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     arglist: FnDefArgs<'src, 'ast>,
-) -> TfResult<(PyBlock<'src>, PyBlock<'src>, Vec<PyArgDefItem<'src>>)> {
+) -> TfResult<(PyBlock<'src>, PyBlock<'src>, Vec<SPyArgDefItem<'src>>)> {
     let mut pre = PyBlock::new();
     let mut post = PyBlock::new();
 
     let args = match arglist {
         FnDefArgs::ArgList(args) => {
+            // defaults are evaluated in the enclosing scope when the function is defined,
+            // so a reference to a sibling parameter would silently capture an outer variable
+            let param_names = args
+                .iter()
+                .filter_map(|arg| match arg {
+                    ArgDefItem::Arg((Pattern::Capture(Some(name)), _), _)
+                    | ArgDefItem::ArgSpread(name)
+                    | ArgDefItem::KwargSpread(name) => Some(ctx.escape_ident(&name.0)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            // python stores positional defaults for the last N parameters only, so once a
+            // positional parameter has a default, every following one needs one too
+            let mut seen_default = false;
+            let mut keyword_only = false;
+
             let mut args_vec = vec![];
             for arg in args {
                 let arg = match arg {
                     ArgDefItem::Arg(arg, default) => {
+                        let arg_span = arg.1;
+                        if default.is_some() {
+                            seen_default = true;
+                        } else if seen_default && !keyword_only {
+                            return Err(TfErrBuilder::default()
+                                .message(
+                                    "Parameter without a default cannot follow a parameter with a default",
+                                )
+                                .span(arg.1)
+                                .build_errs());
+                        }
+
                         let default = if let Some(default) = default {
-                            let t = default.transform_with_placeholder_guard(ctx)?;
+                            let mut t = default.transform_with_placeholder_guard(ctx)?;
+
+                            // `x = x` deliberately captures the outer `x`, so only siblings count
+                            let siblings = match &arg.0 {
+                                Pattern::Capture(Some(own)) => {
+                                    let own = ctx.escape_ident(&own.0);
+                                    param_names.iter().filter(|x| **x != own).cloned().collect()
+                                }
+                                _ => param_names.clone(),
+                            };
+
+                            let found = IdentLoadFinder::find(&mut t.pre, siblings.clone())
+                                .or_else(|| find_ident_load(&t.value, &siblings));
+                            if let Some((name, span)) = found {
+                                return Err(TfErrBuilder::default()
+                                    .message(format!(
+                                        "Default value cannot reference parameter '{name}'; \
+                                         defaults are evaluated in the enclosing scope"
+                                    ))
+                                    .span(span)
+                                    .build_errs());
+                            }
+
                             pre.extend(t.pre);
                             Some(t.value)
                         } else {
@@ -1436,16 +2499,17 @@ fn make_arglist<'src, 'ast>(
 
                         let (matcher, cursor) = create_throwing_matcher(ctx, arg)?;
                         post.extend(matcher);
-                        PyArgDefItem::Arg(cursor, default)
+                        (PyArgDefItem::Arg(cursor, default), arg_span)
                     }
                     ArgDefItem::ArgSpread(name) => {
-                        PyArgDefItem::ArgSpread(ctx.escape_ident(&name.0))
+                        keyword_only = true;
+                        (PyArgDefItem::ArgSpread(ctx.escape_ident(&name.0)), name.1)
                     }
                     ArgDefItem::KwargSpread(name) => {
-                        PyArgDefItem::KwargSpread(ctx.escape_ident(&name.0))
+                        (PyArgDefItem::KwargSpread(ctx.escape_ident(&name.0)), name.1)
                     }
                 };
-                args_vec.push(arg);
+                args_vec.push(arg.into());
             }
             args_vec
         }
@@ -1467,7 +2531,7 @@ fn prepare_py_fn<'src, 'ast>(
 ) -> TfResult<(
     PyBlock<'src>,
     PyBlock<'src>,
-    Vec<PyArgDefItem<'src>>,
+    Vec<SPyArgDefItem<'src>>,
     PyDecorators<'src>,
 )> {
     let mut aux_stmts = PyBlock::new();
@@ -1488,14 +2552,31 @@ fn prepare_py_fn<'src, 'ast>(
             }
 
             if is_do {
-                decorators.push(a.tl_builtin("do"));
+                decorators.push(ctx.tl_builtin(&a, "do"));
             }
 
             stmts
         }
         FnDefBody::Expr(block) => {
+            let record_fields = record_return_fields(ctx, final_value_expr(block));
+
             ctx.fn_ctx_stack.push(FnCtx::new());
-            let block = block.transform(ctx)?;
+            ctx.defer_stack.push(None);
+            let loop_stack = std::mem::take(&mut ctx.loop_stack);
+            let finally_loop_depth = ctx.finally_loop_depth.take();
+            let block = block.transform(ctx).and_then(|mut block| {
+                if let Some(fields) = record_fields {
+                    // the fields are plain identifiers, so there are no statements to keep
+                    block.value = (Expr::Mapping(fields), block.value.tl_span)
+                        .transform_with_placeholder_guard(ctx)?
+                        .value;
+                }
+                Ok(block)
+            });
+            ctx.loop_stack = loop_stack;
+            ctx.finally_loop_depth = finally_loop_depth;
+            let defer_var = ctx.defer_stack.pop().unwrap();
+            let block = block?;
             let fn_ctx = ctx.fn_ctx_stack.pop().unwrap();
 
             if fn_ctx.is_async {
@@ -1504,15 +2585,34 @@ fn prepare_py_fn<'src, 'ast>(
             }
 
             if fn_ctx.is_do {
-                decorators.push(a.tl_builtin("do"));
+                decorators.push(ctx.tl_builtin(&a, "do"));
             }
 
             let mut stmts = block.pre;
             stmts.push(a.return_(block.value));
+
+            if let Some(var) = defer_var {
+                // run the deferred functions in reverse order, however the body exits
+                let run_deferred = a.expr(a.call(
+                    ctx.tl_builtin(&a, "run_deferred"),
+                    vec![a.call_arg(a.load_ident(var.clone()))],
+                ));
+
+                stmts = PyBlock(vec![
+                    a.assign(
+                        a.ident(var, PyAccessCtx::Store),
+                        a.list(vec![], PyAccessCtx::Load),
+                    ),
+                    a.try_(stmts, vec![], Some(PyBlock(vec![run_deferred]))),
+                ]);
+            }
+
             stmts
         }
     });
 
+    let body_stmts = hoist_scope_declarations(body_stmts, span);
+
     Ok((aux_stmts, body_stmts, args, decorators))
 }
 
@@ -1520,12 +2620,17 @@ fn make_fn_exp<'src, 'ast>(
     ctx: &mut TfCtx<'src>,
     arglist: FnDefArgs<'src, 'ast>,
     body: FnDefBody<'src, 'ast>,
+    type_params: &'ast [SIdent<'src>],
     span: &Span,
 ) -> TfResult<SPyExprWithPre<'src>> {
+    let type_params = transform_type_params(ctx, type_params, "Function", span)?;
     let (mut aux_stmts, body_stmts, args, decorators) = prepare_py_fn(ctx, arglist, body, span)?;
     let a = PyAstBuilder::new(*span);
 
-    if body_stmts.0.len() == 1 {
+    // Only lower to a lambda when the entire body is a single return. Any other statement,
+    // e.g. temporaries from block expressions or argument matchers, requires a named def,
+    // and so do type parameters.
+    if body_stmts.0.len() == 1 && type_params.is_empty() {
         // TODO maybe refactor prepare_py_fn to return body_stmts as PyExprWithPre instead of pattern matching Return
 
         if let PyStmt::Return(_) = &body_stmts.0[0].value {
@@ -1552,7 +2657,13 @@ fn make_fn_exp<'src, 'ast>(
     let name = ctx.temp_var_name("fnexp", span.start);
     aux_stmts.push(
         (
-            PyStmt::FnDef(name.clone().into(), args, body_stmts, decorators),
+            PyStmt::FnDef(
+                name.clone().into(),
+                args,
+                body_stmts,
+                decorators,
+                type_params,
+            ),
             *span,
         )
             .into(),
@@ -1568,30 +2679,109 @@ fn make_fn_def<'src, 'ast>(
     name: Cow<'src, str>,
     arglist: FnDefArgs<'src, 'ast>,
     body: FnDefBody<'src, 'ast>,
+    type_params: &'ast [SIdent<'src>],
     mut decorators: PyDecorators<'src>,
     span: &Span,
 ) -> TfResult<PyBlock<'src>> {
+    let type_params = transform_type_params(ctx, type_params, "Function", span)?;
     let (mut aux_stmts, body_stmts, args, inner_decorators) =
         prepare_py_fn(ctx, arglist, body, span)?;
 
-    decorators.0.extend(inner_decorators.0);
+    decorators.0.extend(inner_decorators.0);
+
+    aux_stmts.push(
+        (
+            PyStmt::FnDef(name, args, body_stmts, decorators, type_params),
+            *span,
+        )
+            .into(),
+    );
+    Ok(aux_stmts)
+}
+
+/// Like Go, a deferred call evaluates its callee and arguments at the `defer` statement, so
+/// `defer f.close()` in a loop closes every file. They're bound as the defaults of a lambda
+/// that makes the call.
+fn bind_deferred_call<'src>(ctx: &TfCtx<'src>, call: SPyExpr<'src>, span: &Span) -> SPyExpr<'src> {
+    let a = PyAstBuilder::new(*span);
+    let PyExpr::Call(func, items) = call.value else {
+        return a.lambda(vec![], call);
+    };
+
+    let mut params = vec![];
+    let mut bind = |value: SPyExpr<'src>| {
+        let name = format!(
+            "{}_{}",
+            ctx.temp_var_name("deferarg", span.start),
+            params.len()
+        );
+        params.push((PyArgDefItem::Arg(name.clone().into(), Some(value)), *span).into());
+        a.load_ident(name)
+    };
+
+    let func = bind(*func);
+    let items = items
+        .into_iter()
+        .map(|item| match item {
+            PyCallItem::Arg(value) => PyCallItem::Arg(bind(value)),
+            PyCallItem::Kwarg(name, value) => PyCallItem::Kwarg(name, bind(value)),
+            PyCallItem::ArgSpread(value) => PyCallItem::ArgSpread(bind(value)),
+            PyCallItem::KwargSpread(value) => PyCallItem::KwargSpread(bind(value)),
+        })
+        .collect();
+
+    a.lambda(params, a.call(func, items))
+}
+
+/// The expression whose value a function body implicitly returns.
+fn final_value_expr<'src, 'ast>(body: &'ast SExpr<'src>) -> &'ast SExpr<'src> {
+    match &body.0 {
+        Expr::Block(stmts) => match stmts.last() {
+            Some((Stmt::Expr(expr), _)) => expr,
+            _ => body,
+        },
+        _ => body,
+    }
+}
 
-    aux_stmts.push(
-        (
-            PyStmt::FnDef(name.into(), args, body_stmts, decorators),
-            *span,
-        )
-            .into(),
-    );
-    Ok(aux_stmts)
+/// With `record_returns`, `return a, b` returns `{a, b}` instead, so that callers can destructure
+/// the result by name. The same goes for a function body ending in `a, b`. Only tuples of two or
+/// more plain identifiers are affected.
+fn record_return_fields<'src>(
+    ctx: &TfCtx<'src>,
+    expr: &SExpr<'src>,
+) -> Option<Vec<MappingItem<'src>>> {
+    if !ctx.record_returns {
+        return None;
+    }
+
+    let Expr::Tuple(items) = &expr.0 else {
+        return None;
+    };
+
+    if items.len() < 2 {
+        return None;
+    }
+
+    items
+        .iter()
+        .map(|item| match item {
+            ListItem::Item((Expr::Ident(id), _)) => Some(MappingItem::Ident(id.clone())),
+            _ => None,
+        })
+        .collect()
 }
 
-fn transform_call_items<'src, 'ast>(
+fn transform_call_items<'src>(
     ctx: &mut TfCtx<'src>,
-    args: &'ast [SCallItem<'src>],
+    args: &[SCallItem<'src>],
     span: &Span,
 ) -> TfResult<(PyBlock<'src>, Vec<PyCallItem<'src>>)> {
+    // Python's ordering rules: positional args may not follow any keyword
+    // item, and `*` spreads may not follow `**` spreads, but `*` spreads may
+    // follow plain keyword args.
     let mut started_kwargs = false;
+    let mut started_kwarg_spread = false;
     let mut call_items = vec![];
     let mut aux_stmts = PyBlock::new();
 
@@ -1616,9 +2806,9 @@ fn transform_call_items<'src, 'ast>(
                 call_items.push(PyCallItem::Kwarg(name.clone(), e.value));
             }
             CallItem::ArgSpread(expr) => {
-                if started_kwargs {
+                if started_kwarg_spread {
                     return Err(TfErrBuilder::default()
-                        .message("Cannot have arg spread after kwargs")
+                        .message("Cannot have arg spread after kwarg spread")
                         .span(*span)
                         .build_errs());
                 }
@@ -1629,6 +2819,7 @@ fn transform_call_items<'src, 'ast>(
             }
             CallItem::KwargSpread(expr) => {
                 started_kwargs = true;
+                started_kwarg_spread = true;
                 let e = expr.transform_with_deep_placeholder_guard(ctx)?;
                 aux_stmts.extend(e.pre);
                 call_items.push(PyCallItem::KwargSpread(e.value));
@@ -1639,9 +2830,9 @@ fn transform_call_items<'src, 'ast>(
     Ok((aux_stmts, call_items))
 }
 
-fn transform_subscript_items<'src, 'ast>(
+fn transform_subscript_items<'src>(
     ctx: &mut TfCtx<'src>,
-    indices: &'ast [ListItem<'src>],
+    indices: &[ListItem<'src>],
     span: &Span,
 ) -> TfResult<(PyBlock<'src>, SPyExpr<'src>)> {
     let mut aux_stmts = PyBlock::new();
@@ -1649,24 +2840,24 @@ fn transform_subscript_items<'src, 'ast>(
     let single_item = if indices.len() == 1 {
         match &indices[0] {
             ListItem::Item(item) => Some(item),
-            ListItem::Spread(_) => None,
+            ListItem::Spread(_) | ListItem::Default(..) => None,
         }
     } else {
         None
     };
 
     let subscript_expr = if let Some(single_item) = single_item {
-        let e = single_item.transform_with_deep_placeholder_guard(ctx)?;
+        let e = transform_subscript_item(ctx, single_item)?;
         aux_stmts.extend(e.pre);
         e.value
     } else {
         (
             PyExpr::Tuple(
                 indices
-                    .into_iter()
+                    .iter()
                     .map(|i| match i {
                         ListItem::Item(expr) => {
-                            let e = expr.transform_with_deep_placeholder_guard(ctx)?;
+                            let e = transform_subscript_item(ctx, expr)?;
                             aux_stmts.extend(e.pre);
                             Ok(PyListItem::Item(e.value))
                         }
@@ -1675,6 +2866,7 @@ fn transform_subscript_items<'src, 'ast>(
                             aux_stmts.extend(e.pre);
                             Ok(PyListItem::Spread(e.value))
                         }
+                        ListItem::Default(target, _) => Err(misplaced_default(target)),
                     })
                     .collect::<TfResult<Vec<_>>>()?,
                 PyAccessCtx::Load,
@@ -1687,6 +2879,161 @@ fn transform_subscript_items<'src, 'ast>(
     Ok((aux_stmts, subscript_expr))
 }
 
+/**
+ * A slice directly inside a subscript becomes a native Python slice, so that `a[1..3] = xs`
+ * is a slice assignment.
+ */
+fn transform_subscript_item<'src>(
+    ctx: &mut TfCtx<'src>,
+    item: &SExpr<'src>,
+) -> TfResult<SPyExprWithPre<'src>> {
+    let Expr::Slice(start, end, step) = &item.0 else {
+        return item.transform_with_deep_placeholder_guard(ctx);
+    };
+
+    let mut pre = PyBlock::new();
+    let mut bound = |ctx: &mut TfCtx<'src>, e: &Option<Box<SExpr<'src>>>| {
+        e.as_ref()
+            .map(|e| {
+                let t = e.transform_with_deep_placeholder_guard(ctx)?;
+                pre.extend(t.pre);
+                Ok(Box::new(t.value))
+            })
+            .transpose()
+    };
+
+    let start = bound(ctx, start)?;
+    let end = bound(ctx, end)?;
+    let step = bound(ctx, step)?;
+
+    Ok(SPyExprWithPre {
+        value: (PyExpr::Slice(start, end, step), item.1).into(),
+        pre,
+    })
+}
+
+struct LoopCtx {
+    label: Option<String>,
+    ctl_var: String,
+    targeted: bool,
+    /// Enclosing loops that a labeled `break` or `continue` inside this loop jumps to.
+    exits_to: Vec<usize>,
+}
+
+impl<'src> TfCtx<'src> {
+    fn push_loop(&mut self, label: &Option<SIdent<'src>>, span: &Span) {
+        self.loop_stack.push(LoopCtx {
+            label: label.as_ref().map(|x| x.0.to_string()),
+            ctl_var: self.temp_var_name("loopctl", span.start),
+            targeted: false,
+            exits_to: vec![],
+        });
+    }
+
+    /**
+     * Python has no labeled loops, so a labeled jump past the innermost loop sets the target's
+     * control variable and breaks; every loop in between checks the variable after it exits
+     * and keeps breaking until the target loop is reached, which then breaks or continues.
+     *
+     * Returns the statements to emit before and after the loop.
+     */
+    fn pop_loop(&mut self, span: &Span) -> (PyBlock<'src>, PyBlock<'src>) {
+        let a = PyAstBuilder::new(*span);
+        let loop_ctx = self.loop_stack.pop().unwrap();
+        let depth = self.loop_stack.len();
+
+        let mut init = PyBlock::new();
+        if loop_ctx.targeted {
+            init.push(a.assign(
+                a.ident(loop_ctx.ctl_var.clone(), PyAccessCtx::Store),
+                a.none(),
+            ));
+        }
+
+        let mut checks = PyBlock::new();
+        for target in loop_ctx.exits_to {
+            let ctl_var = self.loop_stack[target].ctl_var.clone();
+            let is_set = a.binary(PyBinaryOp::Nis, a.load_ident(ctl_var.clone()), a.none());
+
+            let body = if target + 1 == depth {
+                PyBlock(vec![
+                    a.if_(
+                        a.binary(
+                            PyBinaryOp::Eq,
+                            a.load_ident(ctl_var.clone()),
+                            a.str("break"),
+                        ),
+                        PyBlock(vec![a.break_()]),
+                        None,
+                    ),
+                    a.assign(a.ident(ctl_var, PyAccessCtx::Store), a.none()),
+                    a.continue_(),
+                ])
+            } else {
+                PyBlock(vec![a.break_()])
+            };
+
+            checks.push(a.if_(is_set, body, None));
+        }
+
+        (init, checks)
+    }
+
+    fn loop_jump(
+        &mut self,
+        label: Option<&SIdent<'src>>,
+        is_break: bool,
+        span: &Span,
+    ) -> TfResult<PyBlock<'src>> {
+        let a = PyAstBuilder::new(*span);
+        let plain = || PyBlock(vec![if is_break { a.break_() } else { a.continue_() }]);
+        let kw = if is_break { "break" } else { "continue" };
+
+        let Some(label) = label else {
+            if let Some(depth) = self.finally_loop_depth
+                && self.loop_stack.len() <= depth
+            {
+                self.warn(finally_escape_message(kw), span);
+            }
+            return Ok(plain());
+        };
+
+        let Some(target) = self
+            .loop_stack
+            .iter()
+            .rposition(|x| x.label.as_deref() == Some(label.0.as_ref()))
+        else {
+            return Err(TfErrBuilder::default()
+                .message(format!("No enclosing loop is labeled '{}'", label.0))
+                .span(label.1)
+                .build_errs());
+        };
+
+        if self.finally_loop_depth.is_some_and(|depth| target < depth) {
+            self.warn(finally_escape_message(kw), span);
+        }
+
+        if target + 1 == self.loop_stack.len() {
+            return Ok(plain());
+        }
+
+        self.loop_stack[target].targeted = true;
+        for loop_ctx in &mut self.loop_stack[target + 1..] {
+            if !loop_ctx.exits_to.contains(&target) {
+                loop_ctx.exits_to.push(target);
+            }
+        }
+
+        Ok(PyBlock(vec![
+            a.assign(
+                a.ident(self.loop_stack[target].ctl_var.clone(), PyAccessCtx::Store),
+                a.str(kw),
+            ),
+            a.break_(),
+        ]))
+    }
+}
+
 struct FnCtx {
     is_async: bool,
     is_do: bool,
@@ -1721,18 +3068,14 @@ impl PlaceholderCtx {
     }
 }
 
-fn await_error<'src>(span: &Span) -> TfErrs {
+fn await_error(span: &Span) -> TfErrs {
     TfErrBuilder::default()
         .message("Await is not allowed except at the top level in interactive contexts; use the Async monad instead")
         .span(*span)
         .build_errs()
 }
 
-fn set_async_ctx<'src>(
-    stack: &mut Vec<FnCtx>,
-    allow_top_level_await: bool,
-    span: &Span,
-) -> TfResult<()> {
+fn set_async_ctx(stack: &mut [FnCtx], allow_top_level_await: bool, span: &Span) -> TfResult<()> {
     if let Some(fn_ctx) = stack.last_mut() {
         fn_ctx.is_async = true;
     } else if !allow_top_level_await {
@@ -1742,7 +3085,7 @@ fn set_async_ctx<'src>(
     Ok(())
 }
 
-fn set_do_ctx<'src>(stack: &mut Vec<FnCtx>, span: &Span) -> TfResult<()> {
+fn set_do_ctx(stack: &mut [FnCtx], span: &Span) -> TfResult<()> {
     if let Some(fn_ctx) = stack.last_mut() {
         fn_ctx.is_do = true;
     } else {
@@ -1790,8 +3133,9 @@ where
 
         let fn_exp = make_fn_exp(
             ctx,
-            FnDefArgs::PyArgList(vec![PyArgDefItem::Arg(var_name, None)]),
+            FnDefArgs::PyArgList(vec![(PyArgDefItem::Arg(var_name, None), *span).into()]),
             FnDefBody::PyStmts(body, fn_ctx.is_do, false),
+            &[],
             span,
         )?;
 
@@ -1821,6 +3165,21 @@ fn transform_placeholder<'src>(
     span: &Span,
     access_ctx: PyAccessCtx,
 ) -> TfResult<SPyExprWithPre<'src>> {
+    if ctx.strict && ctx.placeholder_ctx_stack.is_empty() {
+        // keep going so that every orphaned placeholder is reported at once
+        ctx.orphan_placeholder_errs.0.push(
+            TfErrBuilder::default()
+                .message("Placeholder expression outside of placeholder context")
+                .span(*span)
+                .build(),
+        );
+
+        return Ok(SPyExprWithPre {
+            value: (PyExpr::Ident("_".into(), access_ctx), *span).into(),
+            pre: PyBlock::new(),
+        });
+    }
+
     unsafe {
         let raw_ctx = ctx as *mut TfCtx<'src>;
         let ph_ctx = ctx.placeholder_ctx_stack.last_mut().ok_or_else(|| {
@@ -1840,19 +3199,21 @@ fn transform_placeholder<'src>(
     }
 }
 
-fn transform_postfix_expr<'src, 'ast>(
+fn transform_postfix_expr<'src>(
     ctx: &mut TfCtx<'src>,
-    expr: &'ast SExpr<'src>,
+    expr: &SExpr<'src>,
     access_ctx: PyAccessCtx,
 ) -> TfResult<SPyExprWithPre<'src>> {
     let mut aux = PyBlock::new();
     let (lift_lhs, lhs_node) = match &expr.0 {
         Expr::Attribute(obj, _) => (false, obj),
+        Expr::DynAttribute(obj, _) => (false, obj),
         Expr::Subscript(obj, _) => (false, obj),
         Expr::Call(obj, _) => (false, obj),
         Expr::Then(obj, _) => (false, obj),
         Expr::Extension(obj, _) => (false, obj),
         Expr::MappedAttribute(obj, _) => (true, obj),
+        Expr::MappedDynAttribute(obj, _) => (true, obj),
         Expr::MappedSubscript(obj, _) => (true, obj),
         Expr::MappedCall(obj, _) => (true, obj),
         Expr::MappedThen(obj, _) => (true, obj),
@@ -1865,6 +3226,15 @@ fn transform_postfix_expr<'src, 'ast>(
         }
     };
 
+    if matches!(expr.0, Expr::DynAttribute(..)) && access_ctx != PyAccessCtx::Load {
+        return Err(TfErrBuilder::default()
+            .message(
+                "Dynamic attribute access can only be read from; use setattr or delattr instead",
+            )
+            .span(expr.1)
+            .build_errs());
+    }
+
     if lift_lhs && access_ctx != PyAccessCtx::Load {
         return Err(TfErrBuilder::default()
             .message("Internal error: Cannot use null-coalescing in a non-Load context")
@@ -1885,38 +3255,59 @@ fn transform_postfix_expr<'src, 'ast>(
     placeholder_guard(ctx, &expr.1, |ctx| {
         let a = PyAstBuilder::new(expr.1);
 
-        let guard_if_expr = |expr| {
-            a.if_expr(
-                a.call(a.tl_builtin("ok"), vec![a.call_arg(lhs.clone())]),
-                expr,
-                lhs.clone(),
-            )
-        };
+        let guard_if_expr =
+            |ok, expr| a.if_expr(a.call(ok, vec![a.call_arg(lhs.clone())]), expr, lhs.clone());
 
         let node = match &expr.0 {
             Expr::Call(_, list) => {
-                let t = transform_call_items(ctx, &list, &expr.1)?;
+                let t = transform_call_items(ctx, list, &expr.1)?;
                 aux.extend(t.0);
                 a.call(lhs, t.1)
             }
             Expr::MappedCall(_, list) => {
-                let t = transform_call_items(ctx, &list, &expr.1)?;
+                let t = transform_call_items(ctx, list, &expr.1)?;
                 aux.extend(t.0);
-                guard_if_expr(a.call(lhs.clone(), t.1))
+                guard_if_expr(ctx.tl_builtin(&a, "ok"), a.call(lhs.clone(), t.1))
             }
             Expr::Subscript(_, list) => {
-                let t = transform_subscript_items(ctx, &list, &expr.1)?;
+                let t = transform_subscript_items(ctx, list, &expr.1)?;
                 aux.extend(t.0);
                 a.subscript(lhs, t.1, access_ctx)
             }
             Expr::MappedSubscript(_, list) => {
-                let t = transform_subscript_items(ctx, &list, &expr.1)?;
+                let t = transform_subscript_items(ctx, list, &expr.1)?;
                 aux.extend(t.0);
-                guard_if_expr(a.subscript(lhs.clone(), t.1, access_ctx))
+                guard_if_expr(
+                    ctx.tl_builtin(&a, "ok"),
+                    a.subscript(lhs.clone(), t.1, access_ctx),
+                )
             }
             Expr::Attribute(_, attr) => a.attribute(lhs, ctx.escape_ident(&attr.0), access_ctx),
             Expr::MappedAttribute(_, attr) => {
-                guard_if_expr(a.attribute(lhs.clone(), ctx.escape_ident(&attr.0), access_ctx))
+                let ok = ctx.tl_builtin(&a, "ok");
+                guard_if_expr(
+                    ok,
+                    a.attribute(lhs.clone(), ctx.escape_ident(&attr.0), access_ctx),
+                )
+            }
+            Expr::DynAttribute(_, rhs) => {
+                let rhs_node = rhs.transform(ctx)?;
+                aux.extend(rhs_node.pre);
+                a.call(
+                    a.load_ident("getattr"),
+                    vec![a.call_arg(lhs), a.call_arg(rhs_node.value)],
+                )
+            }
+            Expr::MappedDynAttribute(_, rhs) => {
+                let rhs_node = rhs.transform(ctx)?;
+                aux.extend(rhs_node.pre);
+                guard_if_expr(
+                    ctx.tl_builtin(&a, "ok"),
+                    a.call(
+                        a.load_ident("getattr"),
+                        vec![a.call_arg(lhs.clone()), a.call_arg(rhs_node.value)],
+                    ),
+                )
             }
             Expr::Then(_, rhs) => {
                 let rhs_node = rhs.transform_with_placeholder_guard(ctx)?;
@@ -1926,22 +3317,28 @@ fn transform_postfix_expr<'src, 'ast>(
             Expr::MappedThen(_, rhs) => {
                 let rhs_node = rhs.transform_with_placeholder_guard(ctx)?;
                 aux.extend(rhs_node.pre);
-                guard_if_expr(a.call(rhs_node.value, vec![PyCallItem::Arg(lhs.clone())]))
+                guard_if_expr(
+                    ctx.tl_builtin(&a, "ok"),
+                    a.call(rhs_node.value, vec![PyCallItem::Arg(lhs.clone())]),
+                )
             }
             Expr::Extension(_, rhs) => a.call(
-                a.tl_builtin("vget"),
+                ctx.tl_builtin(&a, "vget"),
                 vec![
                     a.call_arg(lhs),
                     a.call_arg(a.literal(PyLiteral::Str(ctx.escape_ident(&rhs.0)))),
                 ],
             ),
-            Expr::MappedExtension(_, rhs) => guard_if_expr(a.call(
-                a.tl_builtin("vget"),
-                vec![
-                    a.call_arg(lhs.clone()),
-                    a.call_arg(a.literal(PyLiteral::Str(ctx.escape_ident(&rhs.0)))),
-                ],
-            )),
+            Expr::MappedExtension(_, rhs) => guard_if_expr(
+                ctx.tl_builtin(&a, "ok"),
+                a.call(
+                    ctx.tl_builtin(&a, "vget"),
+                    vec![
+                        a.call_arg(lhs.clone()),
+                        a.call_arg(a.literal(PyLiteral::Str(ctx.escape_ident(&rhs.0)))),
+                    ],
+                ),
+            ),
             _ => {
                 return Err(TfErrBuilder::default()
                 .message("Internal error: Postfix expressions can only be attributes, subscripts, calls, or extensions")
@@ -1965,7 +3362,7 @@ fn is_default_pattern<'src>(pattern: &SPattern<'src>) -> TfResult<bool> {
 
             if cap
                 .as_ref()
-                .is_some_and(|x| char::is_uppercase(x.0.chars().nth(0).unwrap_or('_')))
+                .is_some_and(|x| char::is_uppercase(x.0.chars().next().unwrap_or('_')))
             {
                 return Err(TfErrBuilder::default()
                     .message(
@@ -2007,7 +3404,7 @@ fn matching_except_handler<'src>(
     let (mut match_stmt, has_default) =
         transform_match_expr(ctx, &b.ident(var_name.clone()), handlers, false, span)?;
 
-    if let PyStmt::Match(_, cases) = &mut match_stmt.pre.0.last_mut().unwrap().value {
+    if let Some(PyStmt::Match(_, cases)) = match_stmt.pre.0.last_mut().map(|x| &mut x.value) {
         if !has_default {
             cases.push(PyMatchCase {
                 pattern: (PyPattern::As(None, None), *span).into(),
@@ -2050,11 +3447,12 @@ impl<'src> ListItemsExt<'src> for Vec<ListItem<'src>> {
             let e = match expr {
                 ListItem::Spread(expr) => expr.transform_with_deep_placeholder_guard(ctx)?,
                 ListItem::Item(expr) => expr.transform_with_deep_placeholder_guard(ctx)?,
+                ListItem::Default(target, _) => return Err(misplaced_default(target)),
             };
             aux_stmts.extend(e.pre);
             items.push(match expr {
                 ListItem::Spread(_) => PyListItem::Spread(e.value),
-                ListItem::Item(_) => PyListItem::Item(e.value),
+                _ => PyListItem::Item(e.value),
             });
         }
 
@@ -2069,8 +3467,9 @@ trait LiteralExt<'src> {
 impl<'src> LiteralExt<'src> for Literal<'src> {
     fn transform<'ast>(&'ast self, _ctx: &mut TfCtx<'src>) -> TfResult<PyLiteral<'src>> {
         let value = match self {
-            Literal::Num(num) => PyLiteral::Num(num.to_owned()),
-            Literal::Str(s) => PyLiteral::Str(s.to_owned()),
+            Literal::Num(num) => PyLiteral::Num(num.clone()),
+            Literal::Str(s) => PyLiteral::Str(s.clone()),
+            Literal::Bytes(b) => PyLiteral::Bytes(b.clone()),
             Literal::Bool(b) => PyLiteral::Bool(*b),
             Literal::None => PyLiteral::None,
         };
@@ -2110,6 +3509,13 @@ trait SExprExt<'src> {
         ctx: &mut TfCtx<'src>,
         py_ctx: PyAccessCtx,
     ) -> TfResult<SPyExprWithPre<'src>>;
+
+    /// `transform_with_access` without growing the stack first.
+    fn transform_with_access_inner<'ast>(
+        &'ast self,
+        ctx: &mut TfCtx<'src>,
+        py_ctx: PyAccessCtx,
+    ) -> TfResult<SPyExprWithPre<'src>>;
 }
 
 impl<'src> SExprExt<'src> for SExpr<'src> {
@@ -2182,6 +3588,14 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
         &'ast self,
         ctx: &mut TfCtx<'src>,
         access_ctx: PyAccessCtx,
+    ) -> TfResult<SPyExprWithPre<'src>> {
+        grow_stack(|| self.transform_with_access_inner(ctx, access_ctx))
+    }
+
+    fn transform_with_access_inner<'ast>(
+        &'ast self,
+        ctx: &mut TfCtx<'src>,
+        access_ctx: PyAccessCtx,
     ) -> TfResult<SPyExprWithPre<'src>> {
         let (expr, span) = self;
         let a = PyAstBuilder::new(*span);
@@ -2199,7 +3613,7 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
         }
 
         match &expr {
-            Expr::Checked(expr, pattern) => {
+            Expr::Checked(expr, pattern, handler) => {
                 let a = PyAstBuilder::new(*span);
                 let b = AstBuilder::new(*span);
 
@@ -2218,15 +3632,19 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                     a.load_ident(var_name.clone()),
                 ));
 
+                // the handler, if any, is applied to the exception to produce the value
+                let caught = match handler {
+                    Some(handler) => b.then(b.ident(err_name.clone()), *handler.clone()),
+                    None => b.ident(err_name.clone()),
+                };
+
                 let mut handlers = vec![];
                 if let Some(pattern) = pattern {
                     handlers.push(MatchCase {
                         // avoid this clone?
                         pattern: Some(*pattern.clone()),
                         guard: None,
-                        body: b.block_expr(vec![
-                            b.assign(b.ident(var_name.clone()), b.ident(err_name.clone())),
-                        ]),
+                        body: b.block_expr(vec![b.assign(b.ident(var_name.clone()), caught)]),
                     });
                 } else {
                     handlers.push(MatchCase {
@@ -2252,16 +3670,24 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                 })
             }
             Expr::Placeholder => transform_placeholder(ctx, span, access_ctx),
-            Expr::Fn(arglist, body) => make_fn_exp(
+            Expr::Fn(arglist, body, type_params) => make_fn_exp(
                 ctx,
                 FnDefArgs::ArgList(arglist),
                 FnDefBody::Expr(body),
+                type_params,
                 span,
             ),
-            Expr::Class(bases, body) => {
+            Expr::Class(bases, body, type_params) => {
                 let name: Cow<_> = ctx.temp_var_name("clsexp", span.start).into();
-                let aux_stmts =
-                    make_class_def(ctx, name.clone(), bases, body, PyDecorators::new(), span)?;
+                let aux_stmts = make_class_def(
+                    ctx,
+                    name.clone(),
+                    bases,
+                    body,
+                    type_params,
+                    PyDecorators::new(),
+                    span,
+                )?;
 
                 Ok(SPyExprWithPre {
                     value: (PyExpr::Ident(name, PyAccessCtx::Load), *span).into(),
@@ -2295,6 +3721,8 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
             }),
             Expr::Attribute(..)
             | Expr::MappedAttribute(..)
+            | Expr::DynAttribute(..)
+            | Expr::MappedDynAttribute(..)
             | Expr::Call(..)
             | Expr::MappedCall(..)
             | Expr::Subscript(..)
@@ -2303,13 +3731,42 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
             | Expr::MappedThen(..)
             | Expr::Extension(..)
             | Expr::MappedExtension(..) => transform_postfix_expr(ctx, self, access_ctx),
-            Expr::If(cond, then_block, else_block) => transform_if_expr(
-                ctx,
-                cond,
-                then_block,
-                else_block.as_ref().map(|x| x.as_ref()),
-                span,
-            ),
+            Expr::While(cond, body) => {
+                let ret_varname = ctx.temp_var_name("whileexp", span.start);
+                let a = PyAstBuilder::new(*span);
+
+                let mut pre = PyBlock::new();
+                pre.push(a.assign(
+                    a.ident(ret_varname.clone(), PyAccessCtx::Store),
+                    a.literal(PyLiteral::None),
+                ));
+                pre.extend(transform_while(
+                    ctx,
+                    &None,
+                    cond,
+                    body,
+                    Some(&ret_varname),
+                    span,
+                )?);
+
+                Ok(SPyExprWithPre {
+                    value: a.load_ident(ret_varname),
+                    pre,
+                })
+            }
+            Expr::If(cond, then_block, else_block) => {
+                if ctx.statement_if != Some(*span) {
+                    check_if_branch_values(self)?;
+                }
+
+                transform_if_expr(
+                    ctx,
+                    cond,
+                    then_block,
+                    else_block.as_ref().map(|x| x.as_ref()),
+                    span,
+                )
+            }
             Expr::Block(block) => {
                 let t = block.transform(ctx)?;
 
@@ -2323,9 +3780,11 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                 Ok(SPyExprWithPre { value, pre: t.pre })
             }
             Expr::Match(subject, cases) => {
+                ctx.check_feature(ctx.allow_match, "'match'", span)?;
                 Ok(transform_match_expr(ctx, subject, &cases.iter().collect(), true, span)?.0)
             }
             Expr::Matches(subject, pattern) => {
+                ctx.check_feature(ctx.allow_match, "'matches'", span)?;
                 let mut block = PyBlock::new();
                 let subject_t = subject.transform(ctx)?;
                 block.extend(subject_t.pre);
@@ -2410,7 +3869,10 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                         let a = PyAstBuilder::new(*span);
 
                         let expr = a.if_expr(
-                            a.call(a.tl_builtin("ok"), vec![a.call_arg(lhs.value.clone())]),
+                            a.call(
+                                ctx.tl_builtin(&a, "ok"),
+                                vec![a.call_arg(lhs.value.clone())],
+                            ),
                             lhs.value,
                             rhs.value,
                         );
@@ -2422,40 +3884,49 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                     }
                 };
 
-                return Ok(SPyExprWithPre {
+                Ok(SPyExprWithPre {
                     value: (
                         PyExpr::Binary(py_op, Box::new(lhs.value), Box::new(rhs.value)),
                         *span,
                     )
                         .into(),
                     pre: aux_stmts,
-                });
+                })
             }
             Expr::Await(expr) => {
+                ctx.check_feature(ctx.allow_async, "'await'", span)?;
                 set_async_ctx(&mut ctx.fn_ctx_stack, ctx.allow_top_level_await, span)?;
 
                 let expr = expr.transform(ctx)?;
 
-                return Ok(SPyExprWithPre {
+                Ok(SPyExprWithPre {
                     value: (PyExpr::Await(Box::new(expr.value)), *span).into(),
                     pre: expr.pre,
-                });
+                })
             }
             Expr::Yield(expr) => {
                 let expr = expr.transform(ctx)?;
 
-                return Ok(SPyExprWithPre {
+                Ok(SPyExprWithPre {
                     value: (PyExpr::Yield(Box::new(expr.value)), *span).into(),
                     pre: expr.pre,
-                });
+                })
+            }
+            Expr::Raise(expr) => {
+                let expr = expr.transform(ctx)?;
+
+                Ok(SPyExprWithPre {
+                    value: a.call(ctx.tl_builtin(&a, "raise_"), vec![a.call_arg(expr.value)]),
+                    pre: expr.pre,
+                })
             }
             Expr::YieldFrom(expr) => {
                 let expr = expr.transform(ctx)?;
 
-                return Ok(SPyExprWithPre {
+                Ok(SPyExprWithPre {
                     value: (
                         PyExpr::YieldFrom(Box::new(a.call(
-                            a.tl_builtin("vget"),
+                            ctx.tl_builtin(&a, "vget"),
                             vec![
                                 a.call_arg(expr.value),
                                 a.call_arg(a.literal(PyLiteral::Str("iter".into()))),
@@ -2465,7 +3936,7 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                     )
                         .into(),
                     pre: expr.pre,
-                });
+                })
             }
             Expr::Unary(op, expr) => {
                 let expr = expr.transform(ctx)?;
@@ -2485,114 +3956,120 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                     }
                 };
 
-                return Ok(SPyExprWithPre {
+                Ok(SPyExprWithPre {
                     value: (PyExpr::Unary(py_op, Box::new(expr.value)), *span).into(),
                     pre: expr.pre,
-                });
+                })
             }
-            Expr::List(exprs) => {
-                return placeholder_guard(ctx, span, |ctx| {
-                    let (items, aux_stmts) = exprs.transform(ctx)?;
+            Expr::List(exprs) => placeholder_guard(ctx, span, |ctx| {
+                let (items, aux_stmts) = exprs.transform(ctx)?;
 
-                    return Ok(SPyExprWithPre {
-                        value: (PyExpr::List(items, PyAccessCtx::Load), *span).into(),
-                        pre: aux_stmts,
-                    });
-                });
-            }
-            Expr::Tuple(exprs) => {
-                return placeholder_guard(ctx, span, |ctx| {
-                    let (items, aux_stmts) = exprs.transform(ctx)?;
+                Ok(SPyExprWithPre {
+                    value: (PyExpr::List(items, PyAccessCtx::Load), *span).into(),
+                    pre: aux_stmts,
+                })
+            }),
+            Expr::Tuple(exprs) => placeholder_guard(ctx, span, |ctx| {
+                let (items, aux_stmts) = exprs.transform(ctx)?;
 
-                    return Ok(SPyExprWithPre {
-                        value: (PyExpr::Tuple(items, PyAccessCtx::Load), *span).into(),
-                        pre: aux_stmts,
-                    });
-                });
-            }
-            Expr::Mapping(items) => {
-                return placeholder_guard(ctx, span, |ctx| {
-                    let mut aux_stmts = PyBlock::new();
-                    let mut dict_items = vec![];
-
-                    for item in items {
-                        match item {
-                            MappingItem::Ident(id) => {
-                                dict_items.push(PyDictItem::Item(
-                                    a.literal(PyLiteral::Str(ctx.escape_ident(&id.0))),
-                                    a.load_ident(ctx.escape_ident(&id.0)),
-                                ));
-                            }
-                            MappingItem::Item(key, value) => {
-                                let key = key.transform_with_deep_placeholder_guard(ctx)?;
-                                let value = value.transform_with_deep_placeholder_guard(ctx)?;
+                Ok(SPyExprWithPre {
+                    value: (PyExpr::Tuple(items, PyAccessCtx::Load), *span).into(),
+                    pre: aux_stmts,
+                })
+            }),
+            Expr::Mapping(items) => placeholder_guard(ctx, span, |ctx| {
+                let mut aux_stmts = PyBlock::new();
+                let mut dict_items = vec![];
 
-                                aux_stmts.extend(key.pre);
-                                aux_stmts.extend(value.pre);
+                for item in items {
+                    match item {
+                        MappingItem::Ident(id) => {
+                            dict_items.push(PyDictItem::Item(
+                                a.literal(PyLiteral::Str(ctx.escape_ident(&id.0))),
+                                a.load_ident(ctx.escape_ident(&id.0)),
+                            ));
+                        }
+                        MappingItem::Item(key, value) => {
+                            let key = key.transform_with_deep_placeholder_guard(ctx)?;
+                            let value = value.transform_with_deep_placeholder_guard(ctx)?;
 
-                                dict_items.push(PyDictItem::Item(key.value, value.value));
-                            }
-                            MappingItem::Spread(expr) => {
-                                let e = expr.transform_with_deep_placeholder_guard(ctx)?;
-                                aux_stmts.extend(e.pre);
+                            aux_stmts.extend(key.pre);
+                            aux_stmts.extend(value.pre);
 
-                                dict_items.push(PyDictItem::Spread(e.value));
-                            }
+                            dict_items.push(PyDictItem::Item(key.value, value.value));
                         }
-                    }
+                        MappingItem::Spread(expr) => {
+                            let e = expr.transform_with_deep_placeholder_guard(ctx)?;
+                            aux_stmts.extend(e.pre);
 
-                    return Ok(SPyExprWithPre {
-                        value: a.call(a.load_ident("Record"), vec![a.call_arg(a.dict(dict_items))]),
-                        pre: aux_stmts,
-                    });
-                });
-            }
-            Expr::Slice(start, end, step) => {
-                return placeholder_guard(ctx, span, |ctx| {
-                    let start_node = start
-                        .as_ref()
-                        .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
-                        .transpose()?;
-                    let end_node = end
-                        .as_ref()
-                        .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
-                        .transpose()?;
-                    let step_node = step
-                        .as_ref()
-                        .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
-                        .transpose()?;
+                            dict_items.push(PyDictItem::Spread(e.value));
+                        }
+                        MappingItem::Default(_, target, _) => {
+                            return Err(misplaced_default(target));
+                        }
+                    }
+                }
 
-                    let mut aux_stmts = PyBlock::new();
+                Ok(SPyExprWithPre {
+                    value: a.call(
+                        ctx.tl_builtin(&a, "Record"),
+                        vec![a.call_arg(a.dict(dict_items))],
+                    ),
+                    pre: aux_stmts,
+                })
+            }),
+            Expr::Slice(start, end, step) => placeholder_guard(ctx, span, |ctx| {
+                let start_node = start
+                    .as_ref()
+                    .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
+                    .transpose()?;
+                let end_node = end
+                    .as_ref()
+                    .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
+                    .transpose()?;
+                let step_node = step
+                    .as_ref()
+                    .map(|e| e.as_ref().transform_with_deep_placeholder_guard(ctx))
+                    .transpose()?;
 
-                    let mut get = |x: Option<SPyExprWithPre<'src>>| {
-                        let expr = if let Some(x) = x {
-                            aux_stmts.extend(x.pre);
-                            x.value
-                        } else {
-                            (PyExpr::Literal(PyLiteral::None), *span).into()
-                        };
+                let mut aux_stmts = PyBlock::new();
 
-                        PyCallItem::Arg(expr)
+                let mut get = |x: Option<SPyExprWithPre<'src>>| {
+                    let expr = if let Some(x) = x {
+                        aux_stmts.extend(x.pre);
+                        x.value
+                    } else {
+                        (PyExpr::Literal(PyLiteral::None), *span).into()
                     };
 
-                    return Ok(SPyExprWithPre {
-                        value: (
-                            PyExpr::Call(
-                                Box::new(
-                                    (PyExpr::Ident("slice".into(), PyAccessCtx::Load), *span)
-                                        .into(),
-                                ),
-                                vec![get(start_node), get(end_node), get(step_node)],
+                    PyCallItem::Arg(expr)
+                };
+
+                Ok(SPyExprWithPre {
+                    value: (
+                        PyExpr::Call(
+                            Box::new(
+                                (PyExpr::Ident("slice".into(), PyAccessCtx::Load), *span).into(),
                             ),
-                            *span,
-                        )
-                            .into(),
-                        pre: aux_stmts,
-                    });
-                });
-            }
+                            vec![get(start_node), get(end_node), get(step_node)],
+                        ),
+                        *span,
+                    )
+                        .into(),
+                    pre: aux_stmts,
+                })
+            }),
+            // without interpolations, an f-string is just a string
+            Expr::Fstr(begin, parts) if parts.is_empty() => Ok(SPyExprWithPre {
+                value: (
+                    PyExpr::Literal(PyLiteral::Str(begin.0.clone().into())),
+                    *span,
+                )
+                    .into(),
+                pre: PyBlock::new(),
+            }),
             Expr::Fstr(begin, parts) => {
-                return placeholder_guard(ctx, span, |ctx| {
+                placeholder_guard(ctx, span, |ctx| {
                     let mut aux_stmts = PyBlock::new();
                     let mut nodes = Vec::new();
 
@@ -2608,11 +4085,11 @@ impl<'src> SExprExt<'src> for SExpr<'src> {
                     }
 
                     let expr = (PyExpr::Fstr(nodes), *span).into();
-                    return Ok(SPyExprWithPre {
+                    Ok(SPyExprWithPre {
                         value: expr,
                         pre: aux_stmts,
-                    });
-                });
+                    })
+                })
             }
         }
     }
@@ -2622,20 +4099,36 @@ pub struct TransformOutput<'src> {
     pub py_block: PyBlock<'src>,
     pub exports: Vec<PyIdent<'src>>,
     pub module_star_exports: Vec<PyIdent<'src>>,
+    /// Names loaded from the runtime's `__tl__` namespace, in order of first use.
+    pub runtime_helpers: Vec<&'static str>,
+    /// Suspicious but valid code that the transpiled output faithfully reproduces.
+    pub warnings: TfErrs,
 }
 
-pub fn transform_ast<'src, 'ast>(
+pub fn transform_ast<'src>(
     source: &'src str,
-    block: &'ast Vec<SStmt<'src>>,
-    allow_await: bool,
+    block: &Vec<SStmt<'src>>,
+    options: &TranspileOptions,
 ) -> TfResult<TransformOutput<'src>> {
-    let mut ctx = TfCtx::new(source)?;
-    ctx.allow_top_level_await = allow_await;
+    let mut ctx = TfCtx::with_options(source, options)?;
+
+    let stmts = block.transform_with_depth(&mut ctx, true);
 
-    let mut stmts = block.transform_with_depth(&mut ctx, true)?;
+    if !ctx.orphan_placeholder_errs.0.is_empty() {
+        let mut errs = ctx.orphan_placeholder_errs;
+        if let Err(e) = stmts {
+            errs.extend(e);
+        }
+        return Err(errs);
+    }
 
-    if let PyBlockExpr::Expr(value) = stmts.value {
+    let mut stmts = stmts?;
+
+    if let PyBlockExpr::Expr(mut value) = stmts.value {
         let span = value.tl_span;
+        if ctx.echo_expressions {
+            value = echo_expr(value);
+        }
         stmts.pre.push((PyStmt::Expr(value), span).into());
     }
 
@@ -2643,5 +4136,43 @@ pub fn transform_ast<'src, 'ast>(
         py_block: stmts.pre,
         exports: ctx.exports,
         module_star_exports: ctx.module_star_exports,
+        runtime_helpers: ctx.runtime_helpers,
+        warnings: ctx.warnings,
     })
 }
+
+/// Wraps a top-level expression statement in `print(...)` for `echo_expressions`.
+fn echo_expr<'src>(value: SPyExpr<'src>) -> SPyExpr<'src> {
+    let a = PyAstBuilder::new(value.tl_span);
+    a.call(a.load_ident("print"), vec![a.call_arg(value)])
+}
+
+/// Transform a single expression that must lower to a Python expression without any statements.
+pub fn transform_expr_ast<'src>(
+    source: &'src str,
+    expr: &SExpr<'src>,
+    options: &TranspileOptions,
+) -> TfResult<SPyExpr<'src>> {
+    let mut ctx = TfCtx::with_options(source, options)?;
+
+    let t = expr.transform_with_placeholder_guard(&mut ctx);
+
+    if !ctx.orphan_placeholder_errs.0.is_empty() {
+        let mut errs = ctx.orphan_placeholder_errs;
+        if let Err(e) = t {
+            errs.extend(e);
+        }
+        return Err(errs);
+    }
+
+    let t = t?;
+
+    if !t.pre.is_empty() {
+        return Err(TfErrBuilder::default()
+            .message("This expression needs statements to evaluate and cannot be used on its own")
+            .span(expr.1)
+            .build_errs());
+    }
+
+    Ok(t.value)
+}
@@ -0,0 +1,1490 @@
+#[cfg(test)]
+mod tests {
+    use koatl_core::{
+        TlErr, TranspileOptions, diagnostics, emit_py_source,
+        linecol::LineColCache,
+        lint, parse_tl,
+        py::{
+            ast::{PyExpr, SPyExpr},
+            visit::{PyVisitorMut, walk_expr},
+        },
+        transform::transform_ast,
+        transpile_expr_to_py_ast, transpile_to_debug_string, transpile_to_py_ast,
+        transpile_to_py_ast_with_warnings, transpile_to_source,
+    };
+
+    fn transpile_with(src: &str, options: TranspileOptions) -> Result<String, Vec<String>> {
+        transpile_to_source(src, options)
+            .map(|ctx| ctx.source)
+            .map_err(|errs| errs.into_iter().map(|e| e.message).collect())
+    }
+
+    fn transpile(src: &str) -> Result<String, Vec<String>> {
+        transpile_with(src, TranspileOptions::interactive())
+    }
+
+    fn warnings(src: &str) -> Vec<TlErr> {
+        let Ok(output) = transpile_to_py_ast_with_warnings(src, TranspileOptions::interactive())
+        else {
+            panic!("failed to transpile: {src}");
+        };
+        output.warnings
+    }
+
+    fn runtime_helpers(src: &str) -> Vec<&'static str> {
+        let Ok(tl_ast) = parse_tl(src) else {
+            panic!("failed to parse: {src}");
+        };
+        let Ok(output) = transform_ast(src, &tl_ast, &TranspileOptions::interactive()) else {
+            panic!("failed to transform: {src}");
+        };
+        output.runtime_helpers
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(transpile("").unwrap(), "");
+        assert_eq!(transpile("\n# comment\n").unwrap(), "");
+    }
+
+    #[test]
+    fn test_default_arg_referencing_param() {
+        let errs = transpile("f = (a, b = a) => a + b\n").unwrap_err();
+
+        assert_eq!(errs.len(), 1);
+        assert!(
+            errs[0].contains("'a'"),
+            "Expected error to name the parameter, got: {}",
+            errs[0]
+        );
+
+        // a default naming its own parameter captures the outer variable on purpose
+        assert!(transpile("x = 1\nf = (x = x) => x\n").is_ok());
+        assert!(transpile("x = 1\nf = (x = x, y = x) => y\n").is_err());
+    }
+
+    #[test]
+    fn test_default_arg_referencing_param_nested() {
+        assert!(transpile("f = (a, b = [1, a + 1]) => b\n").is_err());
+        assert!(transpile("f = (*args, b = args) => b\n").is_err());
+    }
+
+    #[test]
+    fn test_default_arg_referencing_param_in_pre() {
+        // `try` lowers to statements before the default's value
+        let errs = transpile("f = (a, b = (try a.x)) => b\n").unwrap_err();
+        assert!(errs[0].contains("parameter 'a'"), "got: {errs:?}");
+
+        let errs = transpile("f = (a, b = if a: 1 else: 2) => b\n").unwrap_err();
+        assert!(errs[0].contains("parameter 'a'"), "got: {errs:?}");
+
+        // a function in the default that takes its own `a` doesn't reference the parameter
+        assert!(transpile("f = (a, b = (a => try a.x)) => b\n").is_ok());
+        assert!(transpile("x = 1\nf = (a, b = (try x.y)) => b\n").is_ok());
+    }
+
+    #[test]
+    fn test_default_arg_shadowed_by_lambda() {
+        let source = transpile("f = (a, b = a => a) => b(a)\n").unwrap();
+        assert!(source.contains("def f"), "Expected a function, got: {source}");
+    }
+
+    #[test]
+    fn test_default_arg_referencing_outer() {
+        assert!(transpile("x = 1\nf = (a, b = x) => a + b\n").is_ok());
+    }
+
+    #[test]
+    fn test_fn_exp_lambda() {
+        let source = transpile("print((x => x + 1)(1))\n").unwrap();
+        assert!(source.contains("lambda x:"), "Expected a lambda, got: {source}");
+    }
+
+    #[test]
+    fn test_fn_exp_with_temp_falls_back_to_def() {
+        let source = transpile("print((x => if x: 1 else: 2)(1))\n").unwrap();
+        assert!(!source.contains("lambda"), "Expected no lambda, got: {source}");
+        assert!(source.contains("def _fnexp_"), "Expected a def, got: {source}");
+    }
+
+    #[test]
+    fn test_fn_exp_with_matcher_falls_back_to_def() {
+        let source = transpile("print(([a, b] => a)([1, 2]))\n").unwrap();
+        assert!(!source.contains("lambda"), "Expected no lambda, got: {source}");
+    }
+
+    #[test]
+    fn test_fn_exp_with_default_pre_stays_lambda() {
+        let source = transpile("print(((x = if True: 1 else: 2) => x)())\n").unwrap();
+        assert!(source.contains("lambda x="), "Expected a lambda, got: {source}");
+    }
+
+    #[test]
+    fn test_indent_option() {
+        let src = "f = x =>\n    y = x + 1\n    y\n";
+
+        let source = transpile(src).unwrap();
+        assert!(source.contains("def f(x):\n  y = x + 1\n  return y\n"));
+
+        let mut options = TranspileOptions::interactive();
+        options.indent = "    ".to_string();
+        let source = transpile_with(src, options).unwrap();
+        assert!(source.contains("def f(x):\n    y = x + 1\n    return y\n"));
+
+        let mut options = TranspileOptions::interactive();
+        options.indent = "\t".to_string();
+        let source = transpile_with(src, options).unwrap();
+        assert!(source.contains("def f(x):\n\ty = x + 1\n\treturn y\n"));
+    }
+
+    #[test]
+    fn test_strict_reports_all_orphan_placeholders() {
+        let src = "f = x => $ + $\n";
+
+        let errs = transpile(src).unwrap_err();
+        assert_eq!(errs.len(), 1);
+
+        let mut options = TranspileOptions::interactive();
+        options.strict = true;
+        let errs = transpile_with(src, options).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(errs.iter().all(|e| e.contains("Placeholder")));
+    }
+
+    #[test]
+    fn test_strict_allows_guarded_placeholders() {
+        let mut options = TranspileOptions::interactive();
+        options.strict = true;
+        assert!(transpile_with("f = $ + 1\ng = [$, 1]\n", options).is_ok());
+    }
+
+    #[test]
+    fn test_fold_literal_or_pattern() {
+        let alternatives = (0..50).map(|i| i.to_string()).collect::<Vec<_>>();
+        let src = format!(
+            "x = 7 match:\n    {} => True\n    default False\n",
+            alternatives.join(" | ")
+        );
+
+        let source = transpile(&src).unwrap();
+        assert!(source.contains("(0) | (1) | (2)"), "got: {source}");
+
+        let mut options = TranspileOptions::interactive();
+        options.fold_literal_or_patterns = true;
+        let source = transpile_with(&src, options).unwrap();
+        assert!(!source.contains(") | ("), "got: {source}");
+        assert!(
+            source.contains(&format!(" in ({})", alternatives.join(", "))),
+            "got: {source}"
+        );
+    }
+
+    #[test]
+    fn test_fold_literal_or_pattern_skips_captures() {
+        let mut options = TranspileOptions::interactive();
+        options.fold_literal_or_patterns = true;
+        let source = transpile_with("x = 7 match:\n    1 | y => y\n", options).unwrap();
+        assert!(source.contains("(1) | (_ as y)"), "got: {source}");
+    }
+
+    #[test]
+    fn test_statement_position_block() {
+        let source = transpile("(\n    a = 1\n    print(a)\n)\nb = 2\n").unwrap();
+        assert_eq!(source, "a = 1\nprint(a)\nb = 2\n");
+    }
+
+    #[test]
+    fn test_single_element_tuple() {
+        let source = transpile("x = 1\ny = (x,)\nz = x,\n").unwrap();
+        assert_eq!(source, "x = 1\ny = (x,)\nz = (x,)\n");
+    }
+
+    #[test]
+    fn test_invalid_assignment_targets() {
+        let errs = transpile("1 = x\n").unwrap_err();
+        assert_eq!(errs, vec!["Cannot assign to a literal"]);
+
+        let errs = transpile("f() = x\n").unwrap_err();
+        assert_eq!(errs, vec!["Cannot assign to a call result"]);
+
+        let errs = transpile("[a, f()] = x\n").unwrap_err();
+        assert_eq!(errs, vec!["Cannot assign to a call result"]);
+    }
+
+    #[test]
+    fn test_invalid_assignment_target_span() {
+        let src = "[a, f()] = x\n";
+        let errs = transpile_to_source(src, TranspileOptions::interactive())
+            .err()
+            .unwrap();
+        let span = errs[0].span.unwrap();
+        assert_eq!(&src[span.start..span.end], "f()");
+    }
+
+    #[test]
+    fn test_global_declarations_hoisted() {
+        let source =
+            transpile("f = () =>\n    print(x)\n    global x = 1\n    global x = 2\n").unwrap();
+        assert_eq!(
+            source,
+            "def f():\n  global x\n  print(x)\n  x = 1\n  x = 2\n  return None\n"
+        );
+    }
+
+    #[test]
+    fn test_nonlocal_declarations_hoisted() {
+        let source = transpile(
+            "f = () =>\n    if True:\n        nonlocal x = 1\n    nonlocal x = 2\n    None\n",
+        )
+        .unwrap();
+        assert_eq!(source.matches("nonlocal x").count(), 1, "got: {source}");
+        assert!(
+            source.starts_with("def f():\n  nonlocal x\n"),
+            "got: {source}"
+        );
+    }
+
+    #[test]
+    fn test_import_used_helpers_only() {
+        let mut options = TranspileOptions::module();
+        options.import_used_helpers_only = true;
+        let source = transpile_with("x = None\ny = x ?? 1\n", options).unwrap();
+        assert!(
+            source.starts_with("from koatl.runtime import __tl__\n"),
+            "got: {source}"
+        );
+        assert!(source.contains("__tl__.ok(x)"), "got: {source}");
+
+        let mut options = TranspileOptions::script();
+        options.import_used_helpers_only = true;
+        let source = transpile_with("x = 1\n", options).unwrap();
+        assert!(!source.contains("koatl.runtime"), "got: {source}");
+    }
+
+    #[test]
+    fn test_custom_runtime_module() {
+        let mut options = TranspileOptions::script();
+        options.runtime_module = "myrt".to_string();
+        options.prelude_module = "mypre".to_string();
+        let source = transpile_with("x = 1\n", options).unwrap();
+        assert!(
+            source.starts_with("from myrt import *\nfrom mypre import *\n"),
+            "got: {source}"
+        );
+    }
+
+    #[test]
+    fn test_runtime_helpers_reported() {
+        assert_eq!(
+            runtime_helpers("x = None\ny = x ?? 1\nz = x ?? 2\n"),
+            vec!["ok"]
+        );
+        assert_eq!(runtime_helpers("x = {a: 1}\n"), vec!["Record"]);
+        assert_eq!(
+            runtime_helpers("f = ([a]) => a\ny = x?.foo\n"),
+            vec!["MatchError", "ok"]
+        );
+        assert!(runtime_helpers("x = 1 + 2\n").is_empty());
+    }
+
+    #[test]
+    fn test_tuple_match_subject() {
+        let source = transpile("x, y match:\n    [1, 2] => 3\n").unwrap();
+        assert!(source.starts_with("match (x, y):\n"), "got: {source}");
+
+        let source = transpile("z = x, (y match 2 => 3)\n").unwrap();
+        assert!(source.starts_with("match y:\n"), "got: {source}");
+    }
+
+    #[test]
+    fn test_postfix_decorators() {
+        let source = transpile("f = &[m.decos[0], make(1).wrap] x => x\n").unwrap();
+        assert!(
+            source.starts_with("@(m.decos[0])\n@(make(1).wrap)\ndef f(x):\n"),
+            "got: {source}"
+        );
+    }
+
+    #[test]
+    fn test_decorator_with_statements() {
+        let errs = transpile("f = &[(if c: a else: b)] x => x\n").unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("Decorator"), "got: {}", errs[0]);
+    }
+
+    #[test]
+    fn test_pass_statement() {
+        let source = transpile("f = () =>\n    pass\n").unwrap();
+        assert_eq!(source, "def f():\n  pass\n  return None\n");
+    }
+
+    #[test]
+    fn test_temp_var_salt() {
+        let src = "x = if a: 1 else: 2\ny = x match 1 => 2\n";
+        let temp_names = |salt: &str| {
+            let mut options = TranspileOptions::interactive();
+            options.temp_var_salt = Some(salt.to_string());
+            let source = transpile_with(src, options).unwrap();
+            source
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .filter(|word| word.starts_with('_') && word.len() > 1)
+                .map(|word| word.to_string())
+                .collect::<std::collections::HashSet<_>>()
+        };
+
+        let a = temp_names("a");
+        let b = temp_names("b");
+        assert_eq!(a.len(), 2, "got: {a:?}");
+        assert!(a.iter().all(|name| name.contains("_a_")), "got: {a:?}");
+        assert!(a.is_disjoint(&b), "got: {a:?} and {b:?}");
+
+        assert!(!temp_names("v2_x").is_empty());
+        for salt in ["a-b", "1 x", "é"] {
+            let mut options = TranspileOptions::interactive();
+            options.temp_var_salt = Some(salt.to_string());
+            let errs = transpile_with(src, options).unwrap_err();
+            assert!(errs[0].contains("Invalid temp_var_salt"), "got: {errs:?}");
+        }
+    }
+
+    #[test]
+    fn test_non_default_after_default() {
+        let src = "f = (a, b = 1, c) => a\n";
+        let errs = transpile_to_source(src, TranspileOptions::interactive())
+            .err()
+            .unwrap();
+        assert_eq!(errs.len(), 1);
+        assert!(
+            errs[0]
+                .message
+                .contains("cannot follow a parameter with a default")
+        );
+        let span = errs[0].span.unwrap();
+        assert_eq!(&src[span.start..span.end], "c");
+
+        let source = transpile("f = (a, b = 1) => a\n").unwrap();
+        assert!(source.starts_with("def f(a, b=1):\n"), "got: {source}");
+
+        assert!(transpile("f = (a, b = 1, *args, c, d = 2) => a\n").is_ok());
+    }
+
+    #[test]
+    fn test_keyword_only_args() {
+        let source = transpile("f = (*args, key = 1) => key\n").unwrap();
+        assert!(
+            source.starts_with("def f(*args, key=1):\n"),
+            "got: {source}"
+        );
+    }
+
+    #[test]
+    fn test_expr_mode() {
+        assert!(transpile_expr_to_py_ast("a + b", TranspileOptions::interactive()).is_ok());
+
+        for src in ["x = 1", "a\nb"] {
+            let errs = transpile_expr_to_py_ast(src, TranspileOptions::interactive())
+                .err()
+                .unwrap();
+            assert_eq!(errs[0].message, "Expected a single expression");
+        }
+
+        assert!(
+            transpile_expr_to_py_ast("if a: 1 else: 2", TranspileOptions::interactive()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_export_only_at_top_level() {
+        for src in [
+            "f = () =>\n    export x = 1\n",
+            "f = (\n    export x = 1\n)\n",
+            "g = $ + (\n    export y = 1\n    y\n)\n",
+        ] {
+            let errs = transpile(src).unwrap_err();
+            assert_eq!(
+                errs,
+                vec!["Export modifier is only allowed at the top level"],
+                "for: {src}"
+            );
+        }
+
+        let source = transpile_with("export x = 1\n", TranspileOptions::module()).unwrap();
+        assert!(source.contains("(\"x\",)"), "got: {source}");
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let cases = [
+            ("x = [1, 2]\n", "x = [1, 2,]\n"),
+            ("x = (1, 2)\n", "x = (1, 2,)\n"),
+            ("x = a, b\n", "x = a, b,\n"),
+            ("x = {a: 1, b: 2}\n", "x = {a: 1, b: 2,}\n"),
+            ("f(1, *b, a=1)\n", "f(1, *b, a=1,)\n"),
+            ("f = (a, b = 1) => a\n", "f = (a, b = 1,) => a\n"),
+            ("x[1, 2]\n", "x[1, 2,]\n"),
+            ("x[1..2, 3]\n", "x[1..2, 3,]\n"),
+            (
+                "C = class(A, B):\n    pass\n",
+                "C = class(A, B,):\n    pass\n",
+            ),
+            ("import a.(b, c)\n", "import a.(b, c,)\n"),
+            ("[a, b] = x\n", "[a, b,] = x\n"),
+            (
+                "x match:\n    [a, b] => a\n",
+                "x match:\n    [a, b,] => a\n",
+            ),
+            (
+                "x match:\n    {a: b} => a\n",
+                "x match:\n    {a: b,} => a\n",
+            ),
+            (
+                "x match:\n    C(a, b=1) => a\n",
+                "x match:\n    C(a, b=1,) => a\n",
+            ),
+        ];
+
+        for (without, with) in cases {
+            assert_eq!(
+                transpile(without).unwrap(),
+                transpile(with).unwrap(),
+                "for: {with}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fstr_without_interpolations() {
+        let source = transpile("x = f\"hello\"\ny = f\"{{x}}\"\n").unwrap();
+        assert_eq!(source, "x = \"hello\"\ny = \"{x}\"\n");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let src = "x = 1\ny = )\n";
+        let errs = transpile_to_source(src, TranspileOptions::interactive())
+            .err()
+            .unwrap();
+        let diagnostics = diagnostics(&errs, &LineColCache::new(src));
+
+        assert_eq!(diagnostics.len(), 1);
+        let d = &diagnostics[0];
+        assert_eq!(
+            (d.start_line, d.start_col, d.end_line, d.end_col),
+            (1, 4, 1, 5)
+        );
+        assert_eq!(d.severity, "error");
+    }
+
+    #[test]
+    fn test_class_pattern_keywords() {
+        let out = transpile("p match:\n    Point(x, y=b, z=c) => x\n").unwrap();
+
+        assert!(
+            out.contains("case Point(_ as x, y=_ as b, z=_ as c, ):"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_negative_literal_pattern() {
+        let out = transpile("x match:\n    -1 => \"neg\"\n    -2j => \"imag\"\n").unwrap();
+
+        assert!(out.contains("case -1:"), "got: {out}");
+        assert!(out.contains("case -2j:"), "got: {out}");
+    }
+
+    #[test]
+    fn test_as_pattern_with_sequence() {
+        let out = transpile("x match:\n    [a, b] as p => p\n").unwrap();
+        assert!(out.contains("case [_ as a, _ as b] as p:"), "got: {out}");
+
+        let errs = transpile("x match:\n    [p, b] as p => p\n").unwrap_err();
+        assert!(errs[0].contains("bound more than once"), "got: {errs:?}");
+    }
+
+    #[test]
+    fn test_string_escapes_roundtrip() {
+        let out = transpile(r#"x = "a\\b\x41é\N{BULLET}\t\0""#).unwrap();
+
+        assert!(out.contains(r#"x = "a\\bAé•\t\u0000""#), "got: {out}");
+
+        let out = transpile(r#"x = f"\x7b{y}\n""#).unwrap();
+        assert!(out.contains(r#"f"{{{y}\n""#), "got: {out}");
+
+        // an unknown escape keeps its backslash, and is reported as a warning
+        let src = r#"x = "\d+""#;
+        let w = warnings(src);
+        assert_eq!(w.len(), 1);
+        assert!(w[0].message.contains("invalid escape sequence '\\d'"));
+        assert!(transpile(src).unwrap().contains(r#"x = "\\d+""#));
+    }
+
+    #[test]
+    fn test_indented_mapping_block() {
+        let entries = (0..10)
+            .map(|i| format!("    k{i}: {i}{}\n", if i % 3 == 0 { "," } else { "" }))
+            .collect::<String>();
+        let out = transpile(&format!("x = {{\n{entries}}}\n")).unwrap();
+
+        let expected = (0..10)
+            .map(|i| format!("\"k{i}\": {i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert!(
+            out.contains(&format!("Record({{{expected}}})")),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        let mut options = TranspileOptions::interactive();
+        options.allow_match = false;
+        let errs = transpile_with("x match:\n    1 => 2\n", options).unwrap_err();
+        assert!(errs[0].contains("'match' is disabled"), "got: {errs:?}");
+
+        let mut options = TranspileOptions::interactive();
+        options.allow_match = false;
+        assert!(transpile_with("x matches [a]", options).is_err());
+
+        let mut options = TranspileOptions::interactive();
+        options.allow_async = false;
+        let errs = transpile_with("await x", options).unwrap_err();
+        assert!(errs[0].contains("'await' is disabled"), "got: {errs:?}");
+
+        assert!(transpile("await x\ny = x match:\n    1 => 2\n").is_ok());
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let n = 10_000;
+
+        let errs = transpile(&format!("x = {}1{}\n", "(".repeat(n), ")".repeat(n))).unwrap_err();
+        assert!(errs[0].contains("Too many nested"), "got: {errs:?}");
+
+        let errs = transpile(&format!("x = {}1{}\n", "[".repeat(n), "]".repeat(n))).unwrap_err();
+        assert!(errs[0].contains("Too many nested"), "got: {errs:?}");
+    }
+
+    #[test]
+    fn test_long_chains_transpile() {
+        let out = transpile(&format!("x = {}\n", vec!["1"; 1000].join(" + "))).unwrap();
+        assert!(out.starts_with("x = 1 + 1 + "), "got: {out}");
+
+        assert!(transpile(&format!("x = {}\n", vec!["1"; 10_000].join(" + "))).is_ok());
+        assert!(transpile(&format!("x = a{}\n", ".b".repeat(10_000))).is_ok());
+        assert!(transpile(&format!("x = {}1\n", "-".repeat(10_000))).is_ok());
+    }
+
+    #[test]
+    fn test_labeled_loops() {
+        let out =
+            transpile("outer: for x in xs:\n    for y in ys:\n        break outer\n").unwrap();
+        assert!(out.contains("= \"break\"\n"), "got: {out}");
+        assert!(out.contains("is not None:"), "got: {out}");
+
+        // jumping to the innermost loop needs no control variable
+        let out = transpile("outer: for x in xs:\n    continue outer\n").unwrap();
+        assert!(!out.contains("loopctl"), "got: {out}");
+
+        let errs = transpile("for x in xs:\n    break outer\n").unwrap_err();
+        assert!(
+            errs[0].contains("No enclosing loop is labeled 'outer'"),
+            "got: {errs:?}"
+        );
+
+        let errs = transpile(
+            "outer: for x in xs:\n    f = () =>\n        while 1:\n            break outer\n",
+        )
+        .unwrap_err();
+        assert!(errs[0].contains("No enclosing loop"), "got: {errs:?}");
+    }
+
+    #[test]
+    fn test_type_ignores() {
+        let src = "x = 1\n#- # type: ignore -#\nz = x # type: ignore\n#type:ignore[a, b]\n# type: ignorex\n";
+
+        let Ok(output) = transpile_to_py_ast_with_warnings(src, TranspileOptions::script()) else {
+            panic!("failed to transpile: {src}");
+        };
+        assert_eq!(output.type_ignores, vec![(3, ""), (4, "[a, b]")]);
+    }
+
+    #[test]
+    fn test_if_expr_chain_shares_temp() {
+        let out = transpile("y = if a: 1 else if b: 2 else if c: 3 else 4\n").unwrap();
+
+        let temps: std::collections::HashSet<_> = out
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|w| w.starts_with("_ifexp"))
+            .collect();
+        assert_eq!(temps.len(), 1, "got: {out}");
+        assert!(
+            out.contains("elif b:") && out.contains("elif c:"),
+            "got: {out}"
+        );
+        assert!(!out.contains("else:\n  if"), "got: {out}");
+    }
+
+    #[test]
+    fn test_dynamic_attribute() {
+        let out = transpile("x = obj.[name]\ny = obj?.[name]\n").unwrap();
+        assert!(out.contains("x = getattr(obj, name)"), "got: {out}");
+        assert!(
+            out.contains("getattr(obj, name) if __tl__.ok(obj)"),
+            "got: {out}"
+        );
+
+        // `.()` remains a then-call
+        let out = transpile("x = obj.(f)\n").unwrap();
+        assert!(out.contains("x = f(obj)"), "got: {out}");
+    }
+
+    #[test]
+    fn test_del_multiple_targets() {
+        let out = transpile("del a, b.c, d[i]\ndel (x, [y, z])\n").unwrap();
+        assert!(out.contains("del a, b.c, d[i]\n"), "got: {out}");
+        assert!(out.contains("del x, y, z\n"), "got: {out}");
+
+        let errs = transpile("del a, f()\n").unwrap_err();
+        assert!(
+            errs[0].contains("Cannot delete a call result"),
+            "got: {errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_slice_assignment() {
+        let out = transpile("arr[1..3] = xs\nx = arr[....2, 0]\n").unwrap();
+        assert!(out.contains("arr[1:3] = xs"), "got: {out}");
+        assert!(out.contains("x = arr[::2, 0]"), "got: {out}");
+    }
+
+    #[test]
+    fn test_adjacent_string_literals() {
+        let out = transpile("x = \"a\" \"b\"\ny = (\"c\"\n    r\"\\d\")\n").unwrap();
+        assert!(out.contains("x = \"ab\"\n"), "got: {out}");
+        assert!(out.contains("y = \"c\\\\d\"\n"), "got: {out}");
+    }
+
+    #[test]
+    fn test_assert_tuple_warning() {
+        let w = warnings("assert (x == 1, \"oops\")\n");
+        assert_eq!(w.len(), 1);
+        assert!(
+            w[0].message.contains("always true"),
+            "got: {}",
+            w[0].message
+        );
+
+        assert!(warnings("assert x == 1, \"oops\"\n").is_empty());
+        assert!(warnings("assert (x == 1)\n").is_empty());
+        assert_eq!(
+            transpile("assert (x == 1)\n").unwrap().trim(),
+            "assert x == 1"
+        );
+    }
+
+    #[test]
+    fn test_visitor_renames_calls() {
+        struct RenameCalls;
+
+        impl<'a> PyVisitorMut<'a> for RenameCalls {
+            fn visit_expr(&mut self, expr: &mut SPyExpr<'a>) {
+                if let PyExpr::Call(func, _) = &mut expr.value
+                    && let PyExpr::Ident(name, _) = &mut func.value
+                    && name == "log"
+                {
+                    *name = "traced_log".into();
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        let src = "log(1)\nf = x => log(x, log)\ng = () =>\n    return [log(2)]\n";
+        let Ok(mut py_ast) = transpile_to_py_ast(src, TranspileOptions::interactive()) else {
+            panic!("failed to transpile: {src}");
+        };
+
+        RenameCalls.visit_block(&mut py_ast);
+
+        let Ok(ctx) = emit_py_source(&mut py_ast, "  ") else {
+            panic!("failed to emit: {src}");
+        };
+        let out = ctx.source;
+        assert!(out.contains("traced_log(1)"), "got: {out}");
+        assert!(out.contains("traced_log(x, log)"), "got: {out}");
+        assert!(out.contains("[traced_log(2)]"), "got: {out}");
+        assert!(!out.contains(" log("), "got: {out}");
+    }
+
+    #[test]
+    fn test_postfix_conditional() {
+        let out = transpile("a = x > 0 then \"pos\" else \"neg\"\n").unwrap();
+        assert!(out.contains("if x > 0:"), "got: {out}");
+        assert!(out.contains("= \"pos\"\nelse:"), "got: {out}");
+
+        // the else branch extends to the right and takes the pipe
+        let out = transpile("a = x then 1 else 2 | f\n").unwrap();
+        assert!(out.contains("= f(2)"), "got: {out}");
+        assert!(out.contains("a = _ifexp"), "got: {out}");
+
+        let out = transpile("a = (x then 1 else 2) | f\n").unwrap();
+        assert!(out.contains("a = f(_ifexp"), "got: {out}");
+
+        // the condition binds tighter than a pipe
+        let out = transpile("a = x | g then 1 else 2\n").unwrap();
+        assert!(out.contains("if g:"), "got: {out}");
+        assert!(out.contains("a = _ifexp_l1c8(x)"), "got: {out}");
+    }
+
+    #[test]
+    fn test_defer() {
+        let out = transpile("f = () =>\n    defer a()\n    defer b()\n    c()\n").unwrap();
+        assert!(out.contains("  _defer_l2c4 = []\n  try:\n"), "got: {out}");
+        // the callee and arguments are evaluated at the defer statement
+        assert!(
+            out.contains("_defer_l2c4.append((lambda _deferarg_l3c4_0=b: (_deferarg_l3c4_0())))"),
+            "got: {out}"
+        );
+        let out = transpile("f = () =>\n    defer log.append(i, *xs, k=v)\n").unwrap();
+        assert!(
+            out.contains(
+                "(lambda _deferarg_l2c4_0=log.append, _deferarg_l2c4_1=i, _deferarg_l2c4_2=xs, \
+                 _deferarg_l2c4_3=v: (_deferarg_l2c4_0(_deferarg_l2c4_1, *_deferarg_l2c4_2, \
+                 k=_deferarg_l2c4_3)))"
+            ),
+            "got: {out}"
+        );
+        assert!(
+            out.contains("  finally:\n    __tl__.run_deferred(_defer_l2c4)"),
+            "got: {out}"
+        );
+
+        let errs = transpile("defer a()\n").unwrap_err();
+        assert!(
+            errs[0].contains("only allowed inside a function"),
+            "got: {errs:?}"
+        );
+
+        // followed by a symbol, `defer` is an ordinary name
+        let out = transpile("defer(x)\ndefer.y\ndefer = 1\n").unwrap();
+        assert_eq!(out, "defer(x)\ndefer.y\ndefer = 1\n");
+    }
+
+    #[test]
+    fn test_star_reexport_reaches_set_exports() {
+        let out = transpile_with(
+            "export import .sub.*\nimport .other.*\nexport a = 1\n",
+            TranspileOptions::module(),
+        )
+        .unwrap();
+        assert!(
+            out.contains("set_exports(__package__, globals(), (\"a\",), (\".sub\",))"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_posonly_self() {
+        let src = "A = class:\n    f = (self, x) => x\n    g = staticmethod(y => y)\n";
+
+        let mut options = TranspileOptions::interactive();
+        options.posonly_self = true;
+        let out = transpile_with(src, options).unwrap();
+        assert!(out.contains("def f(self, /, x):"), "{out}");
+        assert!(!out.contains("y, /"), "{out}");
+
+        let out = transpile(src).unwrap();
+        assert!(!out.contains("/"), "{out}");
+    }
+
+    #[test]
+    fn test_coalesce_chain() {
+        let out = transpile("x = a() ?? b() ?? c()\n").unwrap();
+
+        // each call appears exactly once; the left operand is lifted into a temp
+        for call in ["a()", "b()", "c()"] {
+            assert_eq!(out.matches(call).count(), 1, "got: {out}");
+        }
+        assert!(out.find("a()") < out.find("b()"), "got: {out}");
+        assert!(out.find("b()") < out.find("c()"), "got: {out}");
+    }
+
+    #[test]
+    fn test_call_item_ordering() {
+        let out = transpile("f(k=1, *a)\nf(**d, k=1)\nf(*a, **d)\n").unwrap();
+        assert!(out.contains("f(k=1, *a)"), "got: {out}");
+        assert!(out.contains("f(**d, k=1)"), "got: {out}");
+        assert!(out.contains("f(*a, **d)"), "got: {out}");
+
+        let errs = transpile("f(k=1, a)\n").unwrap_err();
+        assert!(
+            errs.iter().any(|e| e.contains("args after kwargs")),
+            "{errs:?}"
+        );
+
+        let errs = transpile("f(**d, a)\n").unwrap_err();
+        assert!(
+            errs.iter().any(|e| e.contains("args after kwargs")),
+            "{errs:?}"
+        );
+
+        let errs = transpile("f(**d, *a)\n").unwrap_err();
+        assert!(
+            errs.iter()
+                .any(|e| e.contains("arg spread after kwarg spread")),
+            "{errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_extension_lowering() {
+        let out = transpile("x!foo(1)\ny = x?!foo\n").unwrap();
+        assert!(out.contains("__tl__.vget(x, \"foo\")(1)"), "got: {out}");
+        assert!(
+            out.contains("__tl__.vget(x, \"foo\") if __tl__.ok(x) else x"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_mapped_extension_call() {
+        let out = transpile("y = x?!foo?(1)\n").unwrap();
+        assert!(
+            out.contains("__tl__.vget(x, \"foo\") if __tl__.ok(x) else x"),
+            "got: {out}"
+        );
+        assert_eq!(out.matches("__tl__.ok(").count(), 2, "got: {out}");
+    }
+
+    #[test]
+    fn test_record_returns() {
+        let src = "f = (a, b) =>\n    return a, b\ng = (a, b) =>\n    return a, b + 1\n";
+
+        let mut options = TranspileOptions::interactive();
+        options.record_returns = true;
+        let out = transpile_with(src, options).unwrap();
+        assert!(
+            out.contains("return __tl__.Record({\"a\": a, \"b\": b})"),
+            "got: {out}"
+        );
+        // only tuples of plain names become records
+        assert!(out.contains("return (a, b + 1)"), "got: {out}");
+
+        let out = transpile(src).unwrap();
+        assert!(!out.contains("Record"), "got: {out}");
+
+        // the implicit return of a final expression is covered too
+        let src = "f = (a, b) =>\n    c = a + b\n    a, c\ng = (a, b) => (a, b)\nh = (a, b) =>\n    a, b + 1\n";
+        let mut options = TranspileOptions::interactive();
+        options.record_returns = true;
+        let out = transpile_with(src, options).unwrap();
+        assert!(
+            out.contains("return __tl__.Record({\"a\": a, \"c\": c})"),
+            "got: {out}"
+        );
+        assert!(
+            out.contains("__tl__.Record({\"a\": a, \"b\": b})"),
+            "got: {out}"
+        );
+        assert!(out.contains("return (a, b + 1)"), "got: {out}");
+    }
+
+    #[test]
+    fn test_uniformly_indented_source() {
+        let out = transpile("\n    x = 1\n    f = y =>\n        y + x\n").unwrap();
+        assert!(
+            out.contains("x = 1\ndef f(y):\n  return y + x\n"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_while_expr() {
+        let out = transpile("x = while c():\n    f()\n").unwrap();
+        assert!(
+            out.contains(
+                "_whileexp_l1c4 = None\nwhile c():\n  _whileexp_l1c4 = f()\nx = _whileexp_l1c4\n"
+            ),
+            "got: {out}"
+        );
+
+        // statement-level loops are unchanged
+        let out = transpile_with("while c():\n    f()\n", TranspileOptions::script()).unwrap();
+        assert!(!out.contains("whileexp"), "got: {out}");
+    }
+
+    #[test]
+    fn test_tuple_subscript_store() {
+        let out = transpile("matrix[i, j] = 0\n").unwrap();
+        assert!(out.contains("matrix[(i, j)] = 0"), "got: {out}");
+
+        let out = transpile("[matrix[i, j], other] = pair\n").unwrap();
+        assert!(out.contains("[matrix[(i, j)], other] = "), "got: {out}");
+    }
+
+    #[test]
+    fn test_raise_expr() {
+        let out = transpile("y = cond then a else raise Err()\n").unwrap();
+        assert!(out.contains("else:\n  raise Err()\n"), "got: {out}");
+
+        let out = transpile("x = val ?? raise Err()\n").unwrap();
+        assert!(
+            out.contains("x = (val if __tl__.ok(val) else __tl__.raise_(Err()))"),
+            "got: {out}"
+        );
+
+        // statement-level raise is unchanged
+        let out = transpile_with("raise Err()\n", TranspileOptions::script()).unwrap();
+        assert!(out.contains("\nraise Err()\n"), "got: {out}");
+    }
+
+    #[test]
+    fn test_annotation_only_class_members() {
+        let out = transpile("A = class:\n    x: int\n    y: list[str]\n").unwrap();
+        assert!(
+            out.contains("class A():\n  x: int\n  y: list[str]\n"),
+            "got: {out}"
+        );
+
+        // loop labels are not annotations
+        let out = transpile("outer: while True:\n    break outer\n").unwrap();
+        assert!(!out.contains("outer:"), "got: {out}");
+    }
+
+    #[test]
+    fn test_match_subject_evaluated_once() {
+        let src = "x = next(it) match:\n    1 | 2 => a\n    n if n > 2 => b\n";
+
+        let out = transpile(src).unwrap();
+        assert_eq!(out.matches("next(it)").count(), 1, "got: {out}");
+
+        let mut options = TranspileOptions::interactive();
+        options.fold_literal_or_patterns = true;
+        let out = transpile_with(src, options).unwrap();
+        assert_eq!(out.matches("next(it)").count(), 1, "got: {out}");
+    }
+
+    #[test]
+    fn test_echo_expressions() {
+        let src = "x = 1\nx + 1\nf = () =>\n    g()\n    h()\nx * 2\n";
+
+        let mut options = TranspileOptions::script();
+        options.echo_expressions = true;
+        let out = transpile_with(src, options).unwrap();
+        assert!(out.contains("print(x + 1)\n"), "got: {out}");
+        assert!(out.contains("print(x * 2)\n"), "got: {out}");
+        // only top-level statements are echoed
+        assert!(out.contains("  g()\n"), "got: {out}");
+        assert_eq!(out.matches("print(").count(), 2, "got: {out}");
+
+        let out = transpile_with(src, TranspileOptions::script()).unwrap();
+        assert!(!out.contains("print("), "got: {out}");
+    }
+
+    #[test]
+    fn test_destructure_defaults() {
+        let out = transpile("[a, b = 9] = xs\n").unwrap();
+        assert!(
+            out.contains("if len(_des_rest_l1c0) > 0:\n  b = _des_rest_l1c0[0]\nelse:\n  b = 9\n"),
+            "got: {out}"
+        );
+
+        let errs = transpile("[a = 1, b] = xs\n").unwrap_err();
+        assert!(
+            errs.iter()
+                .any(|e| e.contains("must come after all other items")),
+            "{errs:?}"
+        );
+
+        let errs = transpile("x = [a = 1]\n").unwrap_err();
+        assert!(
+            errs.iter()
+                .any(|e| e.contains("only allowed in a list or mapping being destructured")),
+            "{errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_destructure_mapping_defaults() {
+        let out = transpile("{\"x\": x = 0, y = 1} = m\n").unwrap();
+        assert!(
+            out.contains("x = _des_dict_l1c0.pop(\"x\", 0)"),
+            "got: {out}"
+        );
+        assert!(
+            out.contains("y = _des_dict_l1c0.pop(\"y\", 1)"),
+            "got: {out}"
+        );
+
+        let errs = transpile("m = {x = 0}\n").unwrap_err();
+        assert!(
+            errs.iter()
+                .any(|e| e.contains("only allowed in a list or mapping being destructured")),
+            "{errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_nonlocal_declaration_hoisted_above_use() {
+        let out = transpile(
+            "g = () =>\n    y = 1\n    h = () =>\n        z = y\n        nonlocal y = z + 1\n    h()\n    y\n",
+        )
+        .unwrap();
+        assert!(
+            out.contains("  def h():\n    nonlocal y\n    z = y\n    y = z + 1\n"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_for_iterates_through_vget() {
+        let out = transpile("for k in d:\n    f(k)\n").unwrap();
+        assert!(
+            out.contains("for k in __tl__.vget(d, \"iter\"):"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_try_then_coalesce() {
+        let out = transpile("x = try f() except E() => h ?? 1\n").unwrap();
+
+        // `??` applies to the whole try-expression, not to the handler
+        assert!(out.contains("h(_e_"), "got: {out}");
+        assert!(out.find("h(_e_") < out.find("__tl__.ok("), "got: {out}");
+        assert!(out.contains("else 1)"), "got: {out}");
+    }
+
+    #[test]
+    fn test_assert_message_evaluated_lazily() {
+        let out = transpile_with(
+            "assert x, (if y: f() else: g())\n",
+            TranspileOptions::script(),
+        )
+        .unwrap();
+
+        // the message's statements run inside the failure branch
+        assert!(out.contains("if not x:\n"), "got: {out}");
+        assert!(out.find("if not x:") < out.find("f()"), "got: {out}");
+        assert!(out.contains("assert False, "), "got: {out}");
+
+        let out = transpile("assert x, f()\n").unwrap();
+        assert!(out.contains("assert x, f()"), "got: {out}");
+    }
+
+    #[test]
+    fn test_for_over_range_literal() {
+        let out = transpile("for i in 0..5:\n    f(i)\n").unwrap();
+        assert!(out.contains("for i in range(0, 5):"), "got: {out}");
+
+        let out = transpile("for i in 0..10..2:\n    f(i)\n").unwrap();
+        assert!(out.contains("for i in range(0, 10, 2):"), "got: {out}");
+
+        // an open-ended slice still iterates through the runtime
+        let out = transpile("for i in 0..:\n    f(i)\n").unwrap();
+        assert!(
+            out.contains("vget(slice(0, None, None), \"iter\")"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_destructure_mixed_targets() {
+        let out = transpile("obj.x, arr[0], local = values\n").unwrap();
+
+        assert!(out.contains("obj.x = "), "got: {out}");
+        assert!(out.contains("arr[0] = "), "got: {out}");
+        assert!(out.contains("local = "), "got: {out}");
+        assert!(out.find("obj.x = ") < out.find("arr[0] = "), "got: {out}");
+        assert!(out.find("arr[0] = ") < out.find("local = "), "got: {out}");
+    }
+
+    #[test]
+    fn test_trailing_pass_suppresses_implicit_return() {
+        let out = transpile("f = () =>\n    g()\n    pass\n").unwrap();
+        assert!(out.contains("  g()\n  pass\n  return None\n"), "got: {out}");
+        assert!(!out.contains("return g()"), "got: {out}");
+    }
+
+    #[test]
+    fn test_mapping_pattern_keys() {
+        let out =
+            transpile("r = d match:\n    {\"name\": n, 0: first, -1: last, Keys.ID: i} => n\n")
+                .unwrap();
+
+        assert!(
+            out.contains(
+                "case {\"name\": _ as n, 0: _ as first, -1: _ as last, Keys.ID: _ as i, }:"
+            ),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_valueless_if_branch_error() {
+        let error_at = |src: &'static str| {
+            let Err(errs) = transpile_to_source(src, TranspileOptions::interactive()) else {
+                panic!("expected an error: {src}");
+            };
+            assert_eq!(errs.len(), 1, "src: {src}");
+            assert!(
+                errs[0].message.contains("add a final expression"),
+                "got: {}",
+                errs[0].message
+            );
+            let span = errs[0].span.expect("error should have a span");
+            &src[span.start..span.end]
+        };
+
+        assert_eq!(error_at("x = if c:\n    y = 1\nelse:\n    2\n"), "y = 1");
+
+        // an else-if branch is checked too
+        let src = "x = if c:\n    1\nelse if d:\n    z = 2\nelse:\n    3\n";
+        assert_eq!(error_at(src), "z = 2");
+
+        // so is every other position that uses the value
+        let src = "f = () =>\n    return if c:\n        y = 1\n    else:\n        2\n";
+        assert_eq!(error_at(src), "y = 1");
+        let src = "g(if c:\n    y = 1\nelse:\n    2\n)\n";
+        assert_eq!(error_at(src), "y = 1");
+        let src = "f = () =>\n    if c:\n        y = 1\n    else:\n        2\nx = f()\n";
+        assert!(transpile(src).is_ok());
+
+        // an if in statement position doesn't need a value
+        assert!(transpile("if c:\n    y = 1\nelse:\n    2\n").is_ok());
+        assert!(transpile("x = if c:\n    raise E()\nelse:\n    2\n").is_ok());
+        assert!(transpile("x = if c:\n    pass\nelse:\n    2\n").is_ok());
+    }
+
+    #[test]
+    fn test_class_base_spreads() {
+        let out = transpile("C = class(Base, *more, metaclass=Meta, **opts):\n    pass\n").unwrap();
+        assert!(
+            out.contains("class C(Base, *more, metaclass=Meta, **opts):"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn test_decorator_inference_shapes() {
+        let decorated = [
+            ("f = deco(() => 1)\n", "@deco\ndef f():"),
+            ("f = mod.deco(() => 1)\n", "@(mod.deco)\ndef f():"),
+            ("f = registry[key](() => 1)\n", "@(registry[key])\ndef f():"),
+            ("f = make(opt)(() => 1)\n", "@(make(opt))\ndef f():"),
+            ("f = (() => 1) | deco\n", "@deco\ndef f():"),
+            ("f = outer(inner(() => 1))\n", "@outer\n@inner\ndef f():"),
+        ];
+        for (src, expected) in decorated {
+            let out = transpile(src).unwrap();
+            assert!(out.contains(expected), "{src:?} got: {out}");
+        }
+
+        let assigned = [
+            ("f = deco(() => 1, 2)\n", "f = deco("),
+            ("f = deco(x=() => 1)\n", "f = deco(x="),
+            ("registry[key] = () => 1\n", "registry[key] = (lambda"),
+            ("obj.attr = deco(() => 1)\n", "obj.attr = deco((lambda"),
+        ];
+        for (src, expected) in assigned {
+            let out = transpile(src).unwrap();
+            assert!(out.contains(expected), "{src:?} got: {out}");
+            assert!(!out.contains("def "), "{src:?} got: {out}");
+        }
+    }
+
+    #[test]
+    fn test_finally_escape_warning() {
+        let src = "f = () =>\n    try:\n        g()\n    finally:\n        return 1\n";
+        let w = warnings(src);
+        assert_eq!(w.len(), 1);
+        assert!(
+            w[0].message.contains("'return' in a finally block"),
+            "got: {}",
+            w[0].message
+        );
+        let span = w[0].span.expect("warning should have a span");
+        assert_eq!(&src[span.start..span.end], "return 1");
+
+        let src =
+            "for x in xs:\n    try:\n        g()\n    finally:\n        if x:\n            break\n";
+        let w = warnings(src);
+        assert_eq!(w.len(), 1);
+        assert!(
+            w[0].message.contains("'break' in a finally block"),
+            "got: {}",
+            w[0].message
+        );
+
+        // a labeled break lowers to a plain break of an inner loop, but still escapes
+        let src = "outer: for x in xs:\n    try:\n        g()\n    finally:\n        for y in ys:\n            break outer\n";
+        let w = warnings(src);
+        assert_eq!(w.len(), 1);
+        assert!(
+            w[0].message.contains("'break' in a finally block"),
+            "got: {}",
+            w[0].message
+        );
+        let span = w[0].span.expect("warning should have a span");
+        assert_eq!(&src[span.start..span.end], "break outer");
+
+        // jumps that stay inside the finally block are fine
+        assert!(warnings("try:\n    g()\nfinally:\n    for x in xs:\n        break\n").is_empty());
+        assert!(
+            warnings("try:\n    g()\nfinally:\n    inner: for x in xs:\n        for y in ys:\n            continue inner\n")
+                .is_empty()
+        );
+        assert!(warnings("try:\n    g()\nfinally:\n    h = () =>\n        return 1\n").is_empty());
+    }
+
+    #[test]
+    fn test_debug_string_is_stable() {
+        let src = "x = [a, *b] => a\ny = {k: v} match:\n    {k} => k\n";
+        let debug_string = || {
+            let Ok(s) = transpile_to_debug_string(src, TranspileOptions::module()) else {
+                panic!("failed to transpile: {src}");
+            };
+            s
+        };
+
+        let first = debug_string();
+        for _ in 0..3 {
+            assert_eq!(first, debug_string());
+        }
+
+        assert!(first.starts_with("PyBlock("), "got: {first}");
+        assert!(first.contains("FnDef("), "got: {first}");
+        assert!(first.contains("Match("), "got: {first}");
+
+        assert!(transpile_to_debug_string("x = (\n", TranspileOptions::module()).is_err());
+    }
+
+    #[test]
+    fn test_destructure_ignores_underscore() {
+        let out = transpile("[a, _, *_] = xs\n").unwrap();
+        assert!(out.contains("[a, _des_ignore_"), "got: {out}");
+        assert!(out.contains(", *_des_ignore_"), "got: {out}");
+        assert!(!out.contains(" _,"), "got: {out}");
+
+        let out = transpile("{k: _, m} = d\n").unwrap();
+        assert!(!out.contains("\n_ = "), "got: {out}");
+
+        // a plain `_` target still binds it
+        let out = transpile("_ = x\n").unwrap();
+        assert!(out.contains("_ = x"), "got: {out}");
+    }
+
+    #[test]
+    fn test_type_alias_by_python_version() {
+        let src = "type Pair = tuple[int, int]\n";
+        let with_version = |python_version| {
+            let mut options = TranspileOptions::script();
+            options.python_version = python_version;
+            options
+        };
+
+        let out = transpile_with(src, with_version((3, 12))).unwrap();
+        assert!(
+            out.contains("type Pair = tuple[(int, int)]\n"),
+            "got: {out}"
+        );
+        let Ok(debug) = transpile_to_debug_string(src, with_version((3, 12))) else {
+            panic!("failed to transpile: {src}");
+        };
+        assert!(debug.contains("TypeAlias("), "got: {debug}");
+
+        let out = transpile_with(src, with_version((3, 11))).unwrap();
+        assert!(out.contains("Pair = tuple[(int, int)]\n"), "got: {out}");
+        assert!(!out.contains("type "), "got: {out}");
+
+        // `type` is only a keyword in this position
+        let out = transpile("t = type(x)\n").unwrap();
+        assert!(out.contains("t = type(x)"), "got: {out}");
+    }
+
+    #[test]
+    fn test_class_type_params() {
+        let src = "Box = class[T, U](Base):\n    item: T\n";
+        let with_version = |python_version| {
+            let mut options = TranspileOptions::script();
+            options.python_version = python_version;
+            options
+        };
+
+        let out = transpile_with(src, with_version((3, 12))).unwrap();
+        assert!(out.contains("class Box[T, U](Base):\n"), "got: {out}");
+
+        let errs = transpile_with(src, with_version((3, 11))).unwrap_err();
+        assert!(
+            errs.iter().any(|e| e.contains("require Python 3.12")),
+            "{errs:?}"
+        );
+    }
+
+    #[test]
+    fn test_fn_type_params() {
+        let with_version = |python_version| {
+            let mut options = TranspileOptions::script();
+            options.python_version = python_version;
+            options
+        };
+
+        let src = "first = (xs) [T] => xs[0]
+";
+        let out = transpile_with(src, with_version((3, 12))).unwrap();
+        assert!(out.contains("def first[T](xs):\n"), "got: {out}");
+
+        // a function expression with type params can't be a lambda
+        let out = transpile_with("fs = [(x) [T, U] => x]\n", with_version((3, 12))).unwrap();
+        assert!(out.contains("def _fnexp_l1c6[T, U](x):\n"), "got: {out}");
+
+        let errs = transpile_with(src, with_version((3, 11))).unwrap_err();
+        assert!(
+            errs.iter()
+                .any(|e| e.contains("Function type parameters require Python 3.12")),
+            "{errs:?}"
+        );
+
+        // a subscripted parenthesized expression is still a subscript
+        let out = transpile_with("y = (x)[0]\n", with_version((3, 12))).unwrap();
+        assert!(out.contains("y = x[0]"), "got: {out}");
+    }
+
+    #[test]
+    fn test_lint_findings() {
+        let src = "x = 1\nf = (items=[]) =>\n    y = 2\n    g = x => x\n    g(items)\n";
+        let Ok(findings) = lint(src, TranspileOptions::script()) else {
+            panic!("lint failed");
+        };
+
+        let messages = findings
+            .iter()
+            .map(|w| w.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "Mutable default argument 'items' is shared between calls",
+                "'y' is assigned but never used",
+                "'x' shadows a binding in an enclosing scope",
+            ]
+        );
+
+        // module-level names, `_`-prefixed names and class bodies are not linted
+        let src = "a = 1\nf = () =>\n    _b = 2\n    C = class:\n        a = 3\n    C\n";
+        let Ok(findings) = lint(src, TranspileOptions::script()) else {
+            panic!("lint failed");
+        };
+        assert!(findings.is_empty());
+
+        // parameters and loop targets are reported at their own identifier
+        let src = "x = 1\ni = 0\nf = (a, x) =>\n    for i in a:\n        0\n    0\n";
+        let Ok(findings) = lint(src, TranspileOptions::script()) else {
+            panic!("lint failed");
+        };
+        let spans = findings
+            .iter()
+            .map(|w| w.span.map(|s| (s.start, &src[s.start..s.end])))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [Some((20, "x")), Some((34, "i")), Some((34, "i"))]);
+    }
+
+    #[test]
+    fn test_escaped_placeholder_is_an_identifier() {
+        let out = transpile("f($)\n").unwrap();
+        assert!(out.contains("lambda"), "got: {out}");
+
+        let out = transpile("f($$)\n").unwrap();
+        assert!(!out.contains("lambda"), "got: {out}");
+        assert!(out.contains("f(_tl_dollar)"), "got: {out}");
+
+        let out = transpile("$$ = x => x\n$$(1)\n").unwrap();
+        assert!(out.contains("def _tl_dollar(x):"), "got: {out}");
+
+        // the same spelling as a keyword argument, record key and attribute
+        let out = transpile("f($$=1)\n{$$: 1}.$$\n").unwrap();
+        assert!(out.contains("f(_tl_dollar=1)"), "got: {out}");
+        assert!(out.contains("{\"_tl_dollar\": 1}"), "got: {out}");
+        assert!(out.contains("})._tl_dollar"), "got: {out}");
+
+        // the mangled name is reserved, so user identifiers can't collide with it
+        let errs = transpile("_tl_dollar = 1\n").unwrap_err();
+        assert!(errs[0].contains("are reserved"), "{errs:?}");
+    }
+
+    #[test]
+    fn test_assert_statements_gated_on_debug() {
+        let out = transpile("assert (if y: f() else: g())\n").unwrap();
+        assert!(out.starts_with("if __debug__:\n  if y:\n"), "got: {out}");
+        assert!(out.contains("  assert _ifexp_"), "got: {out}");
+
+        let out = transpile("assert x, (if y: f() else: g())\n").unwrap();
+        assert!(
+            out.starts_with("if __debug__:\n  if not x:\n"),
+            "got: {out}"
+        );
+
+        let out = transpile("assert f(), g()\n").unwrap();
+        assert!(!out.contains("__debug__"), "got: {out}");
+    }
+
+    #[test]
+    fn test_leading_dot_chain_continues_postfix_expression() {
+        use koatl_core::parser::ast::{Expr, Stmt};
+
+        let src = "result = obj\n    .method1()\n    # comment\n    .attr\n\n    .method2()\n";
+        let Ok(stmts) = parse_tl(src) else {
+            panic!("failed to parse: {src}");
+        };
+        assert_eq!(stmts.len(), 1);
+
+        let Stmt::Assign(_, value, _) = &stmts[0].0 else {
+            panic!("expected an assignment, got: {:?}", stmts[0].0);
+        };
+
+        // obj.method1().attr.method2()
+        let Expr::Call(callee, _) = &value.0 else {
+            panic!("expected a call, got: {:?}", value.0);
+        };
+        let Expr::Attribute(obj, method2) = &callee.0 else {
+            panic!("expected an attribute, got: {:?}", callee.0);
+        };
+        assert_eq!(method2.0, "method2");
+        let Expr::Attribute(obj, attr) = &obj.0 else {
+            panic!("expected an attribute, got: {:?}", obj.0);
+        };
+        assert_eq!(attr.0, "attr");
+        let Expr::Call(callee, _) = &obj.0 else {
+            panic!("expected a call, got: {:?}", obj.0);
+        };
+        let Expr::Attribute(obj, method1) = &callee.0 else {
+            panic!("expected an attribute, got: {:?}", callee.0);
+        };
+        assert_eq!(method1.0, "method1");
+        assert!(matches!(&obj.0, Expr::Ident(name) if name.0 == "obj"));
+
+        let out = transpile(src).unwrap();
+        assert_eq!(out.trim(), "result = obj.method1().attr.method2()");
+    }
+
+    #[test]
+    fn test_diagnostics_expand_tabs() {
+        let src = "f = () =>\n\tx = )\n";
+        let errs = transpile_to_source(src, TranspileOptions::interactive())
+            .err()
+            .unwrap();
+
+        // `)` is the 6th byte of its line, but a tab stop of 4 puts it at column 8
+        let d = &diagnostics(&errs, &LineColCache::new(src))[0];
+        assert_eq!((d.start_line, d.start_col), (1, 5));
+        let d = &diagnostics(&errs, &LineColCache::with_tab_width(src, 4))[0];
+        assert_eq!((d.start_line, d.start_col), (1, 8));
+
+        // a tab only advances to the next stop
+        let cache = LineColCache::with_tab_width("ab\tc\t\td", 4);
+        assert_eq!(cache.linecol(3), (1, 4));
+        assert_eq!(cache.linecol(6), (1, 12));
+    }
+}
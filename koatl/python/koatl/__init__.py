@@ -13,3 +13,14 @@ def transpile_raw(*args, **kwargs):
         return _rs.transpile_raw(*args, **kwargs)
     except SyntaxError as e:
         raise SyntaxError(e.args[0].decode("utf8")) from None
+
+
+def transpile_full(*args, **kwargs):
+    try:
+        return _rs.transpile_full(*args, **kwargs)
+    except SyntaxError as e:
+        raise SyntaxError(e.args[0].decode("utf8")) from None
+
+
+diagnostics = _rs.diagnostics
+lint = _rs.lint
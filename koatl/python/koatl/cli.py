@@ -3,15 +3,19 @@ def transpile_from_source(source, mode="script", script_path="<string>"):
     import ast
 
     transpiled_code = transpile(source, mode=mode)
+    if isinstance(transpiled_code, ast.Interactive):
+        transpiled_code = ast.Module(transpiled_code.body, type_ignores=[])
 
     return ast.unparse(transpiled_code)
 
 
 def run_from_source(source, mode="script", script_path="<string>"):
     from koatl import transpile
+    import ast
 
     transpiled_code = transpile(source, mode=mode)
-    code_obj = compile(transpiled_code, script_path, "exec")
+    compile_mode = "single" if isinstance(transpiled_code, ast.Interactive) else "exec"
+    code_obj = compile(transpiled_code, script_path, compile_mode)
 
     script_globals = {"__name__": "__main__"}
 
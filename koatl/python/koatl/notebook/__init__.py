@@ -14,7 +14,8 @@ import ast
 def source_code_transformer(lines):
     source = "".join(lines)
     py_ast = transpile(source, mode="interactive")
-    transformed_source = ast.unparse(py_ast)
+    # IPython echoes the last expression itself, so unparse the body as a module
+    transformed_source = ast.unparse(ast.Module(py_ast.body, type_ignores=[]))
 
     lines = [line + "\n" for line in transformed_source.splitlines()]
 
@@ -23,9 +23,11 @@ from .helpers import *
 __tl__ = SimpleNamespace(
     unpack_record=helpers.unpack_record,
     set_exports=helpers.set_exports,
+    run_deferred=helpers.run_deferred,
     do=helpers.do,
     vget=helpers.vget,
     ok=helpers.ok,
+    raise_=helpers.raise_,
     **{name: helpers.__dict__[name] for name in helpers.__all__},
     **{name: record.__dict__[name] for name in record.__all__},
     **{name: traits.__dict__[name] for name in traits.__all__}
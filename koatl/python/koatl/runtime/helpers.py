@@ -1,4 +1,5 @@
 from functools import wraps
+from typing import NoReturn
 from .traits import vget
 from .record import Record
 
@@ -35,16 +36,36 @@ def set_exports(package_name, globals_dict, exports, module_star_exports):
     globals_dict["__all__"] = tuple(set(globals_dict["__all__"]) | exports)
 
 
+def run_deferred(deferred):
+    """
+    Run the functions collected by `defer` statements, last first. Each one runs in its own
+    try/finally, so one that raises doesn't stop the rest from running.
+    """
+    while deferred:
+        try:
+            deferred.pop()()
+        finally:
+            if deferred:
+                run_deferred(deferred)
+
+
 def unpack_record(obj):
     """
     used in record unpacking
     """
     if hasattr(obj, "items"):
-        return Record(obj.items())
+        return Record(dict(obj.items()))
     else:
         return Record(obj.__dict__)
 
 
+def raise_(exc) -> NoReturn:
+    """
+    used by raise-expressions
+    """
+    raise exc
+
+
 def ok(obj):
     if obj is None:
         return False
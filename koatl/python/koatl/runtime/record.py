@@ -1,12 +1,28 @@
 import re
 
-__all__ = ["Record"]
+__all__ = ["Record", "LenientRecord"]
 
 
 class Record(dict):
+    def __init__(self, *args, **kwargs):
+        super().__init__()
+
+        for arg in args:
+            if not hasattr(arg, "keys"):
+                raise TypeError(
+                    f"Record() positional arguments must be mappings, not '{type(arg).__name__}'"
+                )
+            self.update(arg)
+
+        self.update(kwargs)
+
     def __repr__(self):
         return self._repr_with_visited(set())
 
+    def copy(self):
+        # dict.copy would return a plain dict; use dict(record) for that
+        return type(self)(self)
+
     def __getattr__(self, name):
         try:
             return self[name]
@@ -66,6 +82,15 @@ class Record(dict):
         )
 
 
+class LenientRecord(Record):
+    def __getattr__(self, name):
+        # dunder lookups (copy, pickle, ...) must still fail for missing keys
+        if name.startswith("__") and name.endswith("__"):
+            return super().__getattr__(name)
+
+        return self.get(name)
+
+
 koatl_keywords = {
     "if",
     "else",
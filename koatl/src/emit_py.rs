@@ -1,9 +1,9 @@
-use koatl_core::{linecol::LineColCache, parser::ast::*, py::ast::*};
+use koatl_core::{grow_stack, linecol::LineColCache, parser::ast::*, py::ast::*};
 
 use pyo3::{
     call::PyCallArgs,
     prelude::*,
-    types::{PyBool, PyDict, PyList, PyNone},
+    types::{PyBool, PyBytes, PyComplex, PyDict, PyList, PyNone},
 };
 
 #[derive(Debug)]
@@ -89,6 +89,60 @@ impl<'py, 'src> PyCtx<'py, 'src> {
 
         Ok(node.unbind())
     }
+
+    /// Like `ast.fix_missing_locations`, but iterative, since the recursive Python version
+    /// exceeds the interpreter's recursion limit on long operator chains.
+    fn fix_missing_locations(&self, root: &PyObject) -> PyTlResult<()> {
+        const LOCATION_ATTRS: [&str; 4] = ["lineno", "col_offset", "end_lineno", "end_col_offset"];
+
+        let py = self.py;
+        let mut stack = vec![(root.bind(py).clone(), [1, 0, 1, 0])];
+
+        while let Some((node, mut location)) = stack.pop() {
+            let attributes = node.getattr("_attributes")?;
+            if attributes.contains("lineno")? {
+                for (attr, value) in LOCATION_ATTRS.iter().zip(location.iter_mut()) {
+                    match node
+                        .getattr(*attr)
+                        .ok()
+                        .and_then(|v| v.extract::<usize>().ok())
+                    {
+                        Some(existing) => *value = existing,
+                        None => node.setattr(*attr, *value)?,
+                    }
+                }
+            }
+
+            for child in self
+                .ast_module
+                .call_method1("iter_child_nodes", (&node,))?
+                .try_iter()?
+            {
+                stack.push((child?, location));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the `type_params` of a function or class definition node. Only set when present,
+    /// since the field doesn't exist before Python 3.12.
+    fn set_type_params(
+        &self,
+        node: &PyObject,
+        type_params: &[PyIdent<'src>],
+        span: &Span,
+    ) -> PyTlResult<()> {
+        if !type_params.is_empty() {
+            let type_params_ast = type_params
+                .iter()
+                .map(|param| self.ast_node("TypeVar", (param.as_ref(),), span))
+                .collect::<PyTlResult<Vec<_>>>()?;
+            node.setattr(self.py, "type_params", type_params_ast)?;
+        }
+
+        Ok(())
+    }
 }
 
 trait PyBlockExt<'src> {
@@ -110,20 +164,33 @@ impl<'src> PyBlockExt<'src> for PyBlock<'src> {
 trait PyArgDefExt<'src> {
     fn emit_py<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject>;
 }
-impl<'src> PyArgDefExt<'src> for Vec<PyArgDefItem<'src>> {
+impl<'src> PyArgDefExt<'src> for Vec<SPyArgDefItem<'src>> {
     fn emit_py<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject> {
+        let mut py_posonlyargs = Vec::new();
         let mut py_args = Vec::new();
         let mut py_defaults = Vec::new();
+        let mut py_kwonlyargs = Vec::new();
+        let mut py_kw_defaults = Vec::new();
         let mut vararg = None;
         let mut kwarg = None;
 
         for arg in self {
-            match arg {
+            match &arg.value {
                 PyArgDefItem::Arg(arg_name, default) => {
                     let arg_ast = ctx.ast_cls("arg", (arg_name.as_ref(), ctx.py.None()))?;
-                    py_args.push(arg_ast);
-                    if let Some(default_expr) = default {
-                        py_defaults.push(default_expr.emit_py(ctx)?);
+
+                    // arguments after *args are keyword-only; kw_defaults has an entry for each
+                    if vararg.is_some() {
+                        py_kwonlyargs.push(arg_ast);
+                        py_kw_defaults.push(match default {
+                            Some(default_expr) => default_expr.emit_py(ctx)?,
+                            None => ctx.py.None(),
+                        });
+                    } else {
+                        py_args.push(arg_ast);
+                        if let Some(default_expr) = default {
+                            py_defaults.push(default_expr.emit_py(ctx)?);
+                        }
                     }
                 }
                 PyArgDefItem::ArgSpread(arg_name) => {
@@ -132,19 +199,23 @@ impl<'src> PyArgDefExt<'src> for Vec<PyArgDefItem<'src>> {
                 PyArgDefItem::KwargSpread(arg_name) => {
                     kwarg = Some(ctx.ast_cls("arg", (arg_name.as_ref(), ctx.py.None()))?);
                 }
+                PyArgDefItem::PosOnlyMarker => {
+                    // defaults stay shared between posonlyargs and args
+                    py_posonlyargs.append(&mut py_args);
+                }
             }
         }
 
         ctx.ast_cls(
             "arguments",
             (
-                Vec::<PyObject>::new(), // posonlyargs
-                py_args,                // args
-                vararg,                 // vararg
-                Vec::<PyObject>::new(), // kwonlyargs
-                Vec::<PyObject>::new(), // kw_defaults
-                kwarg,                  // kwarg
-                py_defaults,            // defaults
+                py_posonlyargs, // posonlyargs
+                py_args,        // args
+                vararg,         // vararg
+                py_kwonlyargs,  // kwonlyargs
+                py_kw_defaults, // kw_defaults
+                kwarg,          // kwarg
+                py_defaults,    // defaults
             ),
         )
     }
@@ -208,7 +279,7 @@ impl<'src> PyPatternExt<'src> for SPyPattern<'src> {
                 }
                 ctx.ast_node(
                     "MatchClass",
-                    (cls.emit_py(&ctx)?, items_ast, keys, values),
+                    (cls.emit_py(ctx)?, items_ast, keys, values),
                     &self.tl_span,
                 )?
             }
@@ -284,6 +355,24 @@ impl<'src> PyStmtExt<'src> for SPyStmt<'src> {
                 let value_ast = value.emit_py(ctx)?;
                 ctx.ast_node("Assign", ([target_ast], value_ast), &self.tl_span)
             }
+            PyStmt::TypeAlias(name, value) => {
+                let name_ast = name.emit_py(ctx)?;
+                let value_ast = value.emit_py(ctx)?;
+                ctx.ast_node(
+                    "TypeAlias",
+                    (name_ast, PyList::empty(ctx.py), value_ast),
+                    &self.tl_span,
+                )
+            }
+            PyStmt::AnnAssign(target, annotation) => {
+                let target_ast = target.emit_py(ctx)?;
+                let annotation_ast = annotation.emit_py(ctx)?;
+                ctx.ast_node(
+                    "AnnAssign",
+                    (target_ast, annotation_ast, Option::<PyObject>::None, 1),
+                    &self.tl_span,
+                )
+            }
             PyStmt::Return(expr) => {
                 let expr_ast = expr.emit_py(ctx)?;
                 ctx.ast_node("Return", (expr_ast,), &self.tl_span)
@@ -333,18 +422,21 @@ impl<'src> PyStmtExt<'src> for SPyStmt<'src> {
                     &self.tl_span,
                 )
             }
-            PyStmt::FnDef(name, args, body, decorators) => {
+            PyStmt::FnDef(name, args, body, decorators, type_params) => {
                 let arguments = args.emit_py(ctx)?;
                 let body_ast = body.emit_py(ctx)?;
                 let decorators = decorators.emit_py(ctx)?;
 
-                ctx.ast_node(
+                let fn_def = ctx.ast_node(
                     "FunctionDef",
                     (name.as_ref(), arguments, body_ast, decorators),
                     &self.tl_span,
-                )
+                )?;
+                ctx.set_type_params(&fn_def, type_params, &self.tl_span)?;
+
+                Ok(fn_def)
             }
-            PyStmt::ClassDef(name, bases, body, decorators) => {
+            PyStmt::ClassDef(name, bases, body, decorators, type_params) => {
                 let mut bases_ast = Vec::new();
                 let mut keywords_ast = Vec::new();
 
@@ -358,23 +450,32 @@ impl<'src> PyStmtExt<'src> for SPyStmt<'src> {
                                 ctx.ast_cls("keyword", (kw_name.as_ref(), expr.emit_py(ctx)?))?;
                             keywords_ast.push(keyword);
                         }
-                        _ => {
-                            return Err(PyTlErr {
-                                message: "Unsupported call item in class definition".to_string(),
-                                py_err: None,
-                                span: Some(self.tl_span),
-                            });
+                        PyCallItem::ArgSpread(expr) => {
+                            let starred = ctx.ast_node(
+                                "Starred",
+                                (expr.emit_py(ctx)?, ctx.ast_cls("Load", ())?),
+                                &self.tl_span,
+                            )?;
+                            bases_ast.push(starred);
+                        }
+                        PyCallItem::KwargSpread(expr) => {
+                            let keyword =
+                                ctx.ast_cls("keyword", (ctx.py.None(), expr.emit_py(ctx)?))?;
+                            keywords_ast.push(keyword);
                         }
                     }
                 }
 
                 let body_ast = body.emit_py(ctx)?;
                 let decorators = decorators.emit_py(ctx)?;
-                ctx.ast_node(
+                let class_def = ctx.ast_node(
                     "ClassDef",
                     (name.as_ref(), bases_ast, keywords_ast, body_ast, decorators),
                     &self.tl_span,
-                )
+                )?;
+                ctx.set_type_params(&class_def, type_params, &self.tl_span)?;
+
+                Ok(class_def)
             }
             PyStmt::While(cond, body) => {
                 let cond_ast = cond.emit_py(ctx)?;
@@ -439,14 +540,14 @@ trait PyNameCtxExt {
 
 impl PyNameCtxExt for PyAccessCtx {
     fn emit_py(&self, ctx: &PyCtx<'_, '_>) -> PyTlResult<PyObject> {
-        Ok(ctx.ast_cls(
+        ctx.ast_cls(
             match self {
                 PyAccessCtx::Load => "Load",
                 PyAccessCtx::Store => "Store",
                 PyAccessCtx::Del => "Del",
             },
             (),
-        )?)
+        )
     }
 }
 
@@ -461,6 +562,22 @@ impl<'src> PyLiteralExt<'src> for PyLiteral<'src> {
                 Ok(i) => ctx.ast_node("Constant", (i,), span)?,
                 Err(_) => match num.parse::<f64>() {
                     Ok(f) => ctx.ast_node("Constant", (f,), span)?,
+                    Err(_) if num.ends_with(['j', 'J']) => {
+                        match num[..num.len() - 1].parse::<f64>() {
+                            Ok(f) => ctx.ast_node(
+                                "Constant",
+                                (PyComplex::from_doubles(ctx.py, 0., f),),
+                                span,
+                            )?,
+                            Err(_) => {
+                                return Err(PyTlErr {
+                                    message: format!("Invalid number literal: {}", num),
+                                    py_err: None,
+                                    span: Some(*span),
+                                })
+                            }
+                        }
+                    }
                     Err(_) => {
                         return Err(PyTlErr {
                             message: format!("Invalid number literal: {}", num),
@@ -472,6 +589,7 @@ impl<'src> PyLiteralExt<'src> for PyLiteral<'src> {
             },
             PyLiteral::Bool(b) => ctx.ast_node("Constant", (b,), span)?,
             PyLiteral::Str(s) => ctx.ast_node("Constant", (s,), span)?,
+            PyLiteral::Bytes(b) => ctx.ast_node("Constant", (PyBytes::new(ctx.py, b),), span)?,
             PyLiteral::None => ctx.ast_node("Constant", (ctx.py.None(),), span)?,
         })
     }
@@ -479,10 +597,17 @@ impl<'src> PyLiteralExt<'src> for PyLiteral<'src> {
 
 trait PyExprExt<'src> {
     fn emit_py<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject>;
+
+    /// `emit_py` without growing the stack first.
+    fn emit_py_inner<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject>;
 }
 
 impl<'src> PyExprExt<'src> for SPyExpr<'src> {
     fn emit_py<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject> {
+        grow_stack(|| self.emit_py_inner(ctx))
+    }
+
+    fn emit_py_inner<'py>(&self, ctx: &PyCtx<'py, 'src>) -> PyTlResult<PyObject> {
         Ok(match &self.value {
             PyExpr::Literal(lit) => lit.emit_py(ctx, &self.tl_span)?,
             PyExpr::Ident(ident, c) => {
@@ -520,11 +645,13 @@ impl<'src> PyExprExt<'src> for SPyExpr<'src> {
                     PyBinaryOp::Neq => Some("NotEq"),
                     PyBinaryOp::Is => Some("Is"),
                     PyBinaryOp::Nis => Some("IsNot"),
+                    PyBinaryOp::In => Some("In"),
+                    PyBinaryOp::Nin => Some("NotIn"),
                     _ => None,
                 };
 
                 if let Some(py_cmp_op) = py_cmp_op {
-                    return Ok(ctx.ast_node(
+                    return ctx.ast_node(
                         "Compare",
                         (
                             left.emit_py(ctx)?,
@@ -532,7 +659,7 @@ impl<'src> PyExprExt<'src> for SPyExpr<'src> {
                             [right.emit_py(ctx)?],
                         ),
                         &self.tl_span,
-                    )?);
+                    );
                 }
 
                 return Err(PyTlErr {
@@ -672,7 +799,7 @@ impl<'src> PyExprExt<'src> for SPyExpr<'src> {
                             values.push(value.emit_py(ctx)?);
                         }
                         PyDictItem::Spread(expr) => {
-                            keys.push(ctx.py.None().into());
+                            keys.push(ctx.py.None());
                             values.push(expr.emit_py(ctx)?);
                         }
                     }
@@ -712,15 +839,46 @@ impl<'src> PyExprExt<'src> for SPyExpr<'src> {
     }
 }
 
-pub fn emit_py(block: &PyBlock, source: &str) -> PyTlResult<PyObject> {
+/// `type_ignores` holds the line and tag of each `# type: ignore` comment, as returned by
+/// the transpiler.
+pub fn emit_py(
+    block: &PyBlock,
+    source: &str,
+    type_ignores: &[(usize, &str)],
+) -> PyTlResult<PyObject> {
+    emit_py_root(block, source, "Module", type_ignores)
+}
+
+/// Like `emit_py`, but wrap the statements in an `ast.Interactive` node,
+/// to be compiled with mode "single" so that expression statements are echoed.
+pub fn emit_py_interactive(block: &PyBlock, source: &str) -> PyTlResult<PyObject> {
+    emit_py_root(block, source, "Interactive", &[])
+}
+
+/// Emit the statements wrapped in a root node of type `root_type`, either
+/// "Module" or "Interactive". Only modules carry type ignores.
+fn emit_py_root(
+    block: &PyBlock,
+    source: &str,
+    root_type: &str,
+    type_ignores: &[(usize, &str)],
+) -> PyTlResult<PyObject> {
     Python::with_gil(move |py| {
         let ctx = PyCtx::new(py, source)?;
         let stmts = block.emit_py(&ctx)?;
 
-        let root_node = ctx.ast_node_unspanned("Module", (stmts, PyList::empty(py)))?;
+        let root_node = if root_type == "Module" {
+            let type_ignore_nodes = PyList::empty(py);
+            for (line, tag) in type_ignores {
+                type_ignore_nodes.append(ctx.ast_node_unspanned("TypeIgnore", (line, tag))?)?;
+            }
+
+            ctx.ast_node_unspanned(root_type, (stmts, type_ignore_nodes))?
+        } else {
+            ctx.ast_node_unspanned(root_type, (stmts,))?
+        };
 
-        ctx.ast_module
-            .call_method1("fix_missing_locations", (&root_node,))?;
+        ctx.fix_missing_locations(&root_node)?;
 
         let dump_args = PyDict::new(ctx.py);
         dump_args.set_item("indent", 4)?;
@@ -733,3 +891,16 @@ pub fn emit_py(block: &PyBlock, source: &str) -> PyTlResult<PyObject> {
         Ok(root_node)
     })
 }
+
+pub fn emit_py_expr(expr: &SPyExpr, source: &str) -> PyTlResult<PyObject> {
+    Python::with_gil(move |py| {
+        let ctx = PyCtx::new(py, source)?;
+        let body = expr.emit_py(&ctx)?;
+
+        let root_node = ctx.ast_node_unspanned("Expression", (body,))?;
+
+        ctx.fix_missing_locations(&root_node)?;
+
+        Ok(root_node)
+    })
+}
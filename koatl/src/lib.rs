@@ -1,15 +1,20 @@
 pub mod emit_py;
 
 use koatl_core::{
-    format_errs, linecol::LineColCache, transpile_to_py_ast, transpile_to_source, TranspileOptions,
+    diagnostics as locate_errs, emit_py_source, format_errs, linecol::LineColCache,
+    lint as lint_src, py::emit::EmitCtx, transpile_expr_to_py_ast, transpile_to_py_ast_timed,
+    transpile_to_py_ast_with_warnings, transpile_to_source, PhaseTimings, TlErr, TranspileOptions,
+    TranspileOutput,
 };
 use pyo3::{
     prelude::*,
     types::{PyDict, PyList},
 };
+use std::ffi::CString;
+use std::time::Instant;
 
 fn get_option(mode: &str) -> PyResult<TranspileOptions> {
-    Ok(match mode {
+    let mut options = match mode {
         "module" => TranspileOptions::module(),
         "prelude" => TranspileOptions::prelude(),
         "interactive" => TranspileOptions::interactive(),
@@ -19,52 +24,270 @@ fn get_option(mode: &str) -> PyResult<TranspileOptions> {
                 "Invalid transpilation mode.",
             ))
         }
-    })
+    };
+
+    // target the running interpreter, since the emitted AST is compiled right away
+    options.python_version = Python::with_gil(|py| {
+        let version = py.version_info();
+        (version.major, version.minor)
+    });
+
+    Ok(options)
 }
 
-#[pyfunction(signature=(src, mode="script", filename="<string>"))]
-fn transpile(src: &str, mode: &str, filename: &str) -> PyResult<PyObject> {
-    let options = get_option(mode)?;
+/// With `posonly_self=True`, the first parameter of each method is made positional-only, see
+/// `TranspileOptions::posonly_self`. With `profile=True`, returns `(ast, metrics)` where
+/// `metrics` maps each phase ("lex", "parse", "transform", "emit") to its duration in seconds.
+/// With `record_returns=True`, returning a tuple of names returns a `Record` of them instead.
+/// With `echo_expressions=True`, the value of each top-level expression statement is printed.
+/// `mode="expr"` transpiles a single expression, and doesn't support `profile` or
+/// `echo_expressions`.
+#[pyfunction(signature=(src, mode="script", filename="<string>", posonly_self=false, profile=false, record_returns=false, echo_expressions=false))]
+fn transpile(
+    src: &str,
+    mode: &str,
+    filename: &str,
+    posonly_self: bool,
+    profile: bool,
+    record_returns: bool,
+    echo_expressions: bool,
+) -> PyResult<PyObject> {
+    // expressions are transpiled like interactive input
+    let mut options = get_option(if mode == "expr" { "interactive" } else { mode })?;
+    options.posonly_self = posonly_self;
+    options.record_returns = record_returns;
+    options.echo_expressions = echo_expressions;
 
-    let py_ast = transpile_to_py_ast(src, options).map_err(|e| {
+    if mode == "expr" {
+        if profile {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Profiling is not supported in expr mode.",
+            ));
+        }
+
+        if echo_expressions {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "echo_expressions is not supported in expr mode.",
+            ));
+        }
+
+        let py_expr = transpile_expr_to_py_ast(src, options).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PySyntaxError, _>(format_errs(&e, filename, src))
+        })?;
+
+        return emit_py::emit_py_expr(&py_expr, src).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyException, _>(format!("Emission error: {}", e.message))
+        });
+    }
+
+    let mut timings = PhaseTimings::default();
+    let output = transpile_to_py_ast_timed(src, options, &mut timings).map_err(|e| {
         PyErr::new::<pyo3::exceptions::PySyntaxError, _>(format_errs(&e, filename, src))
     })?;
 
-    let py_ast_obj = emit_py::emit_py(&py_ast, src).map_err(|e| {
+    warn_all(&output.warnings, filename, src)?;
+
+    let start = Instant::now();
+    let emitted = if mode == "interactive" {
+        emit_py::emit_py_interactive(&output.py_ast, src)
+    } else {
+        emit_py::emit_py(&output.py_ast, src, &output.type_ignores)
+    };
+    let py_ast_obj = emitted.map_err(|e| {
         PyErr::new::<pyo3::exceptions::PyException, _>(format!("Emission error: {}", e.message))
     })?;
-    Ok(py_ast_obj)
+    let emit = start.elapsed();
+
+    if !profile {
+        return Ok(py_ast_obj);
+    }
+
+    Python::with_gil(|py| -> PyResult<PyObject> {
+        let metrics = PyDict::new(py);
+        metrics.set_item("lex", timings.lex.as_secs_f64())?;
+        metrics.set_item("parse", timings.parse.as_secs_f64())?;
+        metrics.set_item("transform", timings.transform.as_secs_f64())?;
+        metrics.set_item("emit", emit.as_secs_f64())?;
+
+        Ok((py_ast_obj, metrics).into_pyobject(py)?.unbind().into_any())
+    })
 }
 
-#[pyfunction(signature=(src, mode="script", filename="<string>"))]
-fn transpile_raw(src: &str, mode: &str, filename: &str) -> PyResult<PyObject> {
-    let options = get_option(mode)?;
+#[pyfunction(signature=(src, mode="script", filename="<string>", posonly_self=false, record_returns=false, echo_expressions=false))]
+fn transpile_raw(
+    src: &str,
+    mode: &str,
+    filename: &str,
+    posonly_self: bool,
+    record_returns: bool,
+    echo_expressions: bool,
+) -> PyResult<PyObject> {
+    let mut options = get_option(mode)?;
+    options.posonly_self = posonly_self;
+    options.record_returns = record_returns;
+    options.echo_expressions = echo_expressions;
 
     let ctx = transpile_to_source(src, options).map_err(|e| {
         PyErr::new::<pyo3::exceptions::PySyntaxError, _>(format_errs(&e, filename, src))
     })?;
 
+    let retval = Python::with_gil(|py| -> PyResult<PyObject> {
+        let ret_list = PyList::empty(py);
+        ret_list.append(&ctx.source)?;
+        ret_list.append(source_map(py, &ctx, src)?)?;
+
+        Ok(ret_list.unbind().into_any())
+    })?;
+
+    Ok(retval)
+}
+
+/// Issue each transpile warning as a Python `SyntaxWarning` at its source line.
+fn warn_all(warnings: &[TlErr], filename: &str, src: &str) -> PyResult<()> {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
     let line_cache = LineColCache::new(src);
+    let to_cstr = |s: &str| CString::new(s.replace('\0', "")).unwrap();
 
-    let retval = Python::with_gil(|py| -> PyResult<PyObject> {
-        let pydict = PyDict::new(py);
+    Python::with_gil(|py| {
+        let category = py.get_type::<pyo3::exceptions::PySyntaxWarning>();
 
-        for (line, span) in ctx.source_line_map {
-            pydict.set_item(line, line_cache.linecol(span.start).0)?;
+        for w in warnings {
+            let line = w.span.map(|s| line_cache.linecol(s.start).0).unwrap_or(0);
+            PyErr::warn_explicit(
+                py,
+                &category,
+                &to_cstr(&w.message),
+                &to_cstr(filename),
+                line as i32,
+                None,
+                None,
+            )?;
         }
 
-        let ret_list = PyList::empty(py);
-        ret_list.append(ctx.source)?;
-        ret_list.append(pydict)?;
+        Ok(())
+    })
+}
 
-        Ok(ret_list.unbind().into_any())
+/// Map each emitted Python line to its line in the Coatl source.
+fn source_map<'py>(py: Python<'py>, ctx: &EmitCtx, src: &str) -> PyResult<Bound<'py, PyDict>> {
+    let line_cache = LineColCache::new(src);
+    let pydict = PyDict::new(py);
+
+    for (line, span) in &ctx.source_line_map {
+        pydict.set_item(line, line_cache.linecol(span.start).0)?;
+    }
+
+    Ok(pydict)
+}
+
+/// Transpile once and return a dict with the emitted `ast`, its `source` and the `sourcemap`.
+#[pyfunction(signature=(src, mode="script", filename="<string>", posonly_self=false, record_returns=false, echo_expressions=false))]
+fn transpile_full(
+    src: &str,
+    mode: &str,
+    filename: &str,
+    posonly_self: bool,
+    record_returns: bool,
+    echo_expressions: bool,
+) -> PyResult<PyObject> {
+    let mut options = get_option(mode)?;
+    options.posonly_self = posonly_self;
+    options.record_returns = record_returns;
+    options.echo_expressions = echo_expressions;
+    let indent = options.indent.clone();
+
+    let to_syntax_err = |e: Vec<TlErr>| {
+        PyErr::new::<pyo3::exceptions::PySyntaxError, _>(format_errs(&e, filename, src))
+    };
+
+    let TranspileOutput {
+        mut py_ast,
+        warnings,
+        type_ignores,
+    } = transpile_to_py_ast_with_warnings(src, options).map_err(to_syntax_err)?;
+    warn_all(&warnings, filename, src)?;
+    let ctx = emit_py_source(&mut py_ast, &indent).map_err(to_syntax_err)?;
+
+    let py_ast_obj = emit_py::emit_py(&py_ast, src, &type_ignores).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyException, _>(format!("Emission error: {}", e.message))
     })?;
 
-    Ok(retval)
+    Python::with_gil(|py| -> PyResult<PyObject> {
+        let pydict = PyDict::new(py);
+        pydict.set_item("ast", py_ast_obj)?;
+        pydict.set_item("source", &ctx.source)?;
+        pydict.set_item("sourcemap", source_map(py, &ctx, src)?)?;
+
+        Ok(pydict.unbind().into_any())
+    })
 }
+
+/// Convert errors and warnings to a list of dicts with `message`, `severity` and 0-based
+/// `start_line`, `start_col`, `end_line` and `end_col`. With `tab_width`, columns count tabs
+/// the way an editor displays them.
+fn diagnostic_list(errs: &[TlErr], src: &str, tab_width: Option<usize>) -> PyResult<PyObject> {
+    let line_cache = match tab_width {
+        Some(tab_width) => LineColCache::with_tab_width(src, tab_width),
+        None => LineColCache::new(src),
+    };
+
+    Python::with_gil(|py| -> PyResult<PyObject> {
+        let pylist = PyList::empty(py);
+
+        for d in locate_errs(errs, &line_cache) {
+            let pydict = PyDict::new(py);
+            pydict.set_item("message", d.message)?;
+            pydict.set_item("start_line", d.start_line)?;
+            pydict.set_item("start_col", d.start_col)?;
+            pydict.set_item("end_line", d.end_line)?;
+            pydict.set_item("end_col", d.end_col)?;
+            pydict.set_item("severity", d.severity)?;
+            pylist.append(pydict)?;
+        }
+
+        Ok(pylist.unbind().into_any())
+    })
+}
+
+/// Transpile `src` and return its errors, or its warnings on success, as a list of diagnostic
+/// dicts (see `diagnostic_list`).
+#[pyfunction(signature=(src, mode="script", tab_width=None))]
+fn diagnostics(src: &str, mode: &str, tab_width: Option<usize>) -> PyResult<PyObject> {
+    let options = get_option(mode)?;
+    let indent = options.indent.clone();
+
+    let result = transpile_to_py_ast_with_warnings(src, options).and_then(|mut output| {
+        emit_py_source(&mut output.py_ast, &indent)?;
+        Ok(output.warnings)
+    });
+
+    let diagnostics = match &result {
+        Ok(warnings) => warnings,
+        Err(errs) => errs,
+    };
+
+    diagnostic_list(diagnostics, src, tab_width)
+}
+
+/// Transpile `src` without emitting code and return its warnings and lint findings, or its
+/// errors if it does not transpile, as a list of diagnostic dicts (see `diagnostic_list`).
+#[pyfunction(signature=(src, mode="script", tab_width=None))]
+fn lint(src: &str, mode: &str, tab_width: Option<usize>) -> PyResult<PyObject> {
+    let options = get_option(mode)?;
+    let findings = lint_src(src, options).unwrap_or_else(|errs| errs);
+
+    diagnostic_list(&findings, src, tab_width)
+}
+
 #[pymodule(name = "_rs")]
 fn py_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(transpile, m)?)?;
     m.add_function(wrap_pyfunction!(transpile_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(transpile_full, m)?)?;
+    m.add_function(wrap_pyfunction!(diagnostics, m)?)?;
+    m.add_function(wrap_pyfunction!(lint, m)?)?;
     Ok(())
 }
@@ -0,0 +1,2 @@
+p = 1
+_q = 2
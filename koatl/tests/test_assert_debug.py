@@ -0,0 +1,21 @@
+import koatl
+
+SOURCE = """
+calls = []
+record = () =>
+    calls.append(1)
+    True
+assert (if True: record() else: False)
+"""
+
+
+def run(optimize):
+    tree = koatl.transpile(SOURCE, mode="script")
+    ns = {}
+    exec(compile(tree, "<string>", "exec", optimize=optimize), ns)
+    return ns["calls"]
+
+
+def test_assert_condition_statements_skipped_under_optimize():
+    assert run(optimize=0) == [1]
+    assert run(optimize=1) == []
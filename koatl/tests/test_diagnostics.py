@@ -0,0 +1,22 @@
+import koatl
+
+
+def test_diagnostics_locate_syntax_errors():
+    [diagnostic] = koatl.diagnostics("x = 1\ny = )\n")
+
+    assert diagnostic["severity"] == "error"
+    assert (
+        diagnostic["start_line"],
+        diagnostic["start_col"],
+        diagnostic["end_line"],
+        diagnostic["end_col"],
+    ) == (1, 4, 1, 5)
+
+
+def test_diagnostics_expand_tabs():
+    [diagnostic] = koatl.diagnostics("f = () =>\n\tx = )\n", tab_width=4)
+    assert (diagnostic["start_line"], diagnostic["start_col"]) == (1, 8)
+
+
+def test_diagnostics_empty_on_success():
+    assert koatl.diagnostics("x = 1\n") == []
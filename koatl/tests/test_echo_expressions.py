@@ -0,0 +1,26 @@
+import contextlib
+import io
+
+import koatl
+
+
+def run(src, **kwargs):
+    # script mode, since interactive mode compiles in "single" mode, which echoes by itself
+    tree = koatl.transpile(src, mode="script", **kwargs)
+    out = io.StringIO()
+    with contextlib.redirect_stdout(out):
+        exec(compile(tree, "<string>", "exec"), {})
+    return out.getvalue()
+
+
+def test_echo_expressions():
+    src = "x = 1\nx + 1\n"
+
+    assert run(src, echo_expressions=True) == "2\n"
+    assert run(src) == ""
+
+    source, _ = koatl.transpile_raw(src, mode="interactive", echo_expressions=True)
+    assert "print(x + 1)" in source
+
+    result = koatl.transpile_full(src, mode="interactive", echo_expressions=True)
+    assert "print(x + 1)" in result["source"]
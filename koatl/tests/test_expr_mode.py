@@ -0,0 +1,25 @@
+import ast
+import pytest
+import koatl
+import koatl.runtime
+
+
+def test_expr_mode():
+    tree = koatl.transpile("a + b", mode="expr")
+
+    assert isinstance(tree, ast.Expression)
+    assert eval(compile(tree, "<string>", "eval"), {"a": 1, "b": 2}) == 3
+
+
+def test_expr_mode_rejects_statements():
+    with pytest.raises(SyntaxError):
+        koatl.transpile("x = 1", mode="expr")
+
+
+def test_expr_mode_options():
+    tree = koatl.transpile("(a, b) => (a, b)", mode="expr", record_returns=True)
+    f = eval(compile(tree, "<string>", "eval"), {"__tl__": koatl.runtime.__tl__})
+    assert f(1, 2).b == 2
+
+    with pytest.raises(ValueError):
+        koatl.transpile("a", mode="expr", echo_expressions=True)
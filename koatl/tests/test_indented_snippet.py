@@ -0,0 +1,26 @@
+import ast
+
+import koatl
+
+SNIPPET = """
+    x = 1
+    f = y =>
+        y + x
+    result = f(2)
+"""
+
+
+def test_indented_snippet_transpiles():
+    tree = koatl.transpile(SNIPPET, mode="script")
+    ns = {}
+    exec(compile(tree, "<string>", "exec"), ns)
+    assert ns["result"] == 3
+
+    # locations point into the original, indented source
+    assign = next(node for node in tree.body if isinstance(node, ast.Assign))
+    assert (assign.lineno, assign.col_offset) == (2, 4)
+
+
+def test_indented_snippet_error_span():
+    [diagnostic] = koatl.diagnostics("\n    x = 1\n    y = )\n")
+    assert (diagnostic["start_line"], diagnostic["start_col"]) == (2, 8)
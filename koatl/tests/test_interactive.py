@@ -0,0 +1,16 @@
+import ast
+
+import koatl
+
+
+def test_interactive_emits_interactive_node(capsys):
+    tree = koatl.transpile("x = 1\nx + 1\n", mode="interactive")
+    assert isinstance(tree, ast.Interactive)
+
+    exec(compile(tree, "<string>", "single"), {})
+    assert capsys.readouterr().out == "2\n"
+
+
+def test_script_emits_module():
+    tree = koatl.transpile("x = 1\nx + 1\n", mode="script")
+    assert isinstance(tree, ast.Module)
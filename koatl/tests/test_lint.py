@@ -0,0 +1,32 @@
+import koatl
+
+SOURCE = """
+x = 1
+f = () =>
+    unused = 2
+    g = x => x
+    g(0)
+"""
+
+
+def test_lint_reports_unused_and_shadowing():
+    diagnostics = koatl.lint(SOURCE)
+
+    assert [(d["message"], d["start_line"], d["severity"]) for d in diagnostics] == [
+        ("'unused' is assigned but never used", 3, "warning"),
+        ("'x' shadows a binding in an enclosing scope", 4, "warning"),
+    ]
+
+
+def test_lint_reports_errors():
+    [diagnostic] = koatl.lint("x = )\n")
+    assert diagnostic["severity"] == "error"
+
+
+def test_lint_reports_identifier_spans():
+    source = "x = 1\ni = 0\nf = (a, x) =>\n    for i in a:\n        0\n    0\n"
+    spans = {
+        (d["start_line"], d["start_col"], d["end_line"], d["end_col"])
+        for d in koatl.lint(source)
+    }
+    assert spans == {(2, 8, 2, 9), (3, 8, 3, 9)}
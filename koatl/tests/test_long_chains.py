@@ -0,0 +1,15 @@
+import ast
+
+import koatl
+
+
+def test_long_binary_chain():
+    src = "x = " + " + ".join(["1"] * 1000) + "\n"
+    tree = koatl.transpile(src, mode="script")
+
+    ns = {}
+    exec(compile(tree, "<string>", "exec"), ns)
+    assert ns["x"] == 1000
+
+    assign = next(node for node in tree.body if isinstance(node, ast.Assign))
+    assert assign.value.lineno == 1
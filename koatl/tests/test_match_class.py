@@ -0,0 +1,24 @@
+import ast
+import koatl
+
+
+def match_class_pattern(src):
+    tree = koatl.transpile(src, mode="script")
+    return next(node for node in ast.walk(tree) if isinstance(node, ast.MatchClass))
+
+
+def test_positional_class_pattern():
+    pattern = match_class_pattern("p match:\n    Point(x, y) => x + y\n")
+
+    assert isinstance(pattern.cls, ast.Name) and pattern.cls.id == "Point"
+    assert [p.name for p in pattern.patterns] == ["x", "y"]
+    assert pattern.kwd_attrs == []
+    assert pattern.kwd_patterns == []
+
+
+def test_positional_and_keyword_class_pattern():
+    pattern = match_class_pattern("p match:\n    Point(x, y=b, z=c) => x\n")
+
+    assert [p.name for p in pattern.patterns] == ["x"]
+    assert pattern.kwd_attrs == ["y", "z"]
+    assert [p.name for p in pattern.kwd_patterns] == ["b", "c"]
@@ -0,0 +1,51 @@
+import ast
+
+import koatl
+
+SRC = """
+A = class:
+    f = (self, x) => x
+    g = staticmethod(y => y)
+"""
+
+
+def test_posonly_self():
+    tree = koatl.transpile(SRC, mode="script", posonly_self=True)
+    cls = next(node for node in tree.body if isinstance(node, ast.ClassDef))
+    f = next(node for node in cls.body if isinstance(node, ast.FunctionDef))
+
+    assert [a.arg for a in f.args.posonlyargs] == ["self"]
+    assert [a.arg for a in f.args.args] == ["x"]
+
+    ns = {}
+    exec(compile(tree, "<string>", "exec"), ns)
+    assert ns["A"]().f(1) == 1
+
+
+def test_posonly_self_off_by_default():
+    tree = koatl.transpile(SRC, mode="script")
+    cls = next(node for node in tree.body if isinstance(node, ast.ClassDef))
+    f = next(node for node in cls.body if isinstance(node, ast.FunctionDef))
+
+    assert f.args.posonlyargs == []
+
+
+def test_posonly_self_raw_and_full():
+    source, _ = koatl.transpile_raw(SRC, mode="script", posonly_self=True)
+    assert "def f(self, /, x):" in source
+
+    result = koatl.transpile_full(SRC, mode="script", posonly_self=True)
+    assert "def f(self, /, x):" in result["source"]
+    cls = next(node for node in result["ast"].body if isinstance(node, ast.ClassDef))
+    assert [a.arg for a in cls.body[0].args.posonlyargs] == ["self"]
+
+
+def test_posonly_self_skips_bare_staticmethod_only():
+    src = (
+        "A = class:\n"
+        "    f = &[staticmethod] (x) => x\n"
+        "    g = &[builtins.staticmethod] (x) => x\n"
+    )
+    source, _ = koatl.transpile_raw(src, mode="script", posonly_self=True)
+    assert "def f(x):" in source
+    assert "def g(x, /):" in source
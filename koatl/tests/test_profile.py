@@ -0,0 +1,29 @@
+import ast
+
+import pytest
+
+import koatl
+
+SRC = """
+f = x => x + 1
+print(f(1))
+"""
+
+
+def test_profile_returns_phase_metrics():
+    tree, metrics = koatl.transpile(SRC, mode="script", profile=True)
+
+    assert isinstance(tree, ast.Module)
+    assert set(metrics) == {"lex", "parse", "transform", "emit"}
+    for phase, seconds in metrics.items():
+        assert seconds > 0, phase
+
+
+def test_profile_off_by_default():
+    tree = koatl.transpile(SRC, mode="script")
+    assert isinstance(tree, ast.Module)
+
+
+def test_profile_rejected_in_expr_mode():
+    with pytest.raises(ValueError):
+        koatl.transpile("1 + 2", mode="expr", profile=True)
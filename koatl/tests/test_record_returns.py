@@ -0,0 +1,27 @@
+import koatl
+
+SRC = """
+f = (a, b) =>
+    return a, b
+g = (a, b) =>
+    c = a + b
+    a, c
+"""
+
+
+def test_record_returns():
+    ns = {}
+    exec(compile(koatl.transpile(SRC, mode="script", record_returns=True), "<string>", "exec"), ns)
+    assert ns["f"](1, 2).b == 2
+    assert ns["g"](1, 2).c == 3
+
+    source, _ = koatl.transpile_raw(SRC, mode="script", record_returns=True)
+    assert "Record" in source
+
+    result = koatl.transpile_full(SRC, mode="script", record_returns=True)
+    assert "Record" in result["source"]
+
+
+def test_record_returns_off_by_default():
+    source, _ = koatl.transpile_raw(SRC, mode="script")
+    assert "Record" not in source
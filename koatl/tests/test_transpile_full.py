@@ -0,0 +1,16 @@
+import ast
+import koatl
+
+
+def test_transpile_full():
+    result = koatl.transpile_full("x = 1\ny = x + 1\n", mode="interactive")
+
+    assert set(result) == {"ast", "source", "sourcemap"}
+    assert isinstance(result["ast"], ast.Module)
+
+    g = {}
+    exec(compile(result["ast"], "<string>", "exec"), g)
+    assert g["y"] == 2
+
+    assert "y = x + 1" in result["source"]
+    assert ast.dump(ast.parse(result["source"])) == ast.dump(result["ast"])
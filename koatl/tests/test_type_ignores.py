@@ -0,0 +1,8 @@
+import koatl
+
+
+def test_type_ignore_comments():
+    src = 'x = 1\ny = "# type: ignore"\nz = x.foo  # type: ignore[attr]\n# type: ignored\n'
+    tree = koatl.transpile(src, mode="script")
+
+    assert [(t.lineno, t.tag) for t in tree.type_ignores] == [(3, "[attr]")]
@@ -0,0 +1,40 @@
+import ast
+import sys
+
+import pytest
+
+import koatl
+
+pytestmark = pytest.mark.skipif(
+    sys.version_info < (3, 12), reason="type parameters need Python 3.12"
+)
+
+
+def test_class_type_params():
+    tree = koatl.transpile("Box = class[T]:\n    item: T\n", mode="script")
+    cls = next(node for node in tree.body if isinstance(node, ast.ClassDef))
+
+    assert len(cls.type_params) == 1
+    assert isinstance(cls.type_params[0], ast.TypeVar)
+    assert cls.type_params[0].name == "T"
+
+    ns = {}
+    exec(compile(tree, "<string>", "exec"), ns)
+    assert [p.__name__ for p in ns["Box"].__type_params__] == ["T"]
+
+
+def test_fn_type_params():
+    tree = koatl.transpile("first = (xs) [T] => xs[0]\n", mode="script")
+    fn = next(node for node in tree.body if isinstance(node, ast.FunctionDef))
+
+    assert [p.name for p in fn.type_params] == ["T"]
+
+    ns = {}
+    exec(compile(tree, "<string>", "exec"), ns)
+    assert [p.__name__ for p in ns["first"].__type_params__] == ["T"]
+    assert ns["first"]([1, 2]) == 1
+
+
+def test_type_alias():
+    tree = koatl.transpile("type Pair = tuple[int, int]\n", mode="script")
+    assert any(isinstance(node, ast.TypeAlias) for node in tree.body)
@@ -0,0 +1,12 @@
+import warnings
+
+import koatl
+
+
+def test_assert_tuple_warns():
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        koatl.transpile('x = 1\nassert (x == 1, "oops")\n', mode="script")
+
+    assert [(w.category, w.lineno) for w in caught] == [(SyntaxWarning, 2)]
+    assert "always true" in str(caught[0].message)